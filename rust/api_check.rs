@@ -0,0 +1,117 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Compile-time checks of the generated API, for crates that export
+//! generated messages and want a change to a `.proto` file (or to the code
+//! generator) that breaks their users to fail their own build first.
+//!
+//! [`assert_message_api!`](crate::assert_message_api) lists the items a
+//! message is expected to have, with their exact types. It expands to a
+//! `const` item, so it costs nothing at runtime, and any item that is
+//! renamed, removed or changes type is a compile error.
+
+/// Asserts at compile time that a generated message has the listed
+/// constants, methods and associated functions with exactly these types,
+/// and that the listed types exist.
+///
+/// # Example
+/// ```ignore
+/// protobuf::assert_message_api!(TestAllTypes {
+///     type TestAllTypesView<'static>;
+///     const FULL_NAME: &'static str;
+///     const FIELDS: &'static [protobuf::FieldInfo];
+///     fn new() -> TestAllTypes;
+///     fn optional_int32(&self) -> i32;
+///     fn optional_int32_set(&mut self, Option<i32>);
+/// });
+/// ```
+#[macro_export]
+macro_rules! assert_message_api {
+    ($msg:ty { $($items:tt)* }) => {
+        const _: () = {
+            $crate::__assert_message_api_items!($msg; $($items)*);
+        };
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __assert_message_api_items {
+    ($msg:ty;) => {};
+    ($msg:ty; type $ty:ty; $($rest:tt)*) => {
+        let _ = ::std::marker::PhantomData::<$ty>;
+        $crate::__assert_message_api_items!($msg; $($rest)*);
+    };
+    ($msg:ty; const $name:ident: $ty:ty; $($rest:tt)*) => {
+        let _: $ty = <$msg>::$name;
+        $crate::__assert_message_api_items!($msg; $($rest)*);
+    };
+    ($msg:ty; fn $name:ident(&self $(, $arg:ty)*) $(-> $ret:ty)?; $($rest:tt)*) => {
+        let _: fn(&$msg $(, $arg)*) $(-> $ret)? = <$msg>::$name;
+        $crate::__assert_message_api_items!($msg; $($rest)*);
+    };
+    ($msg:ty; fn $name:ident(&mut self $(, $arg:ty)*) $(-> $ret:ty)?; $($rest:tt)*) => {
+        let _: fn(&mut $msg $(, $arg)*) $(-> $ret)? = <$msg>::$name;
+        $crate::__assert_message_api_items!($msg; $($rest)*);
+    };
+    ($msg:ty; fn $name:ident($($arg:ty),*) $(-> $ret:ty)?; $($rest:tt)*) => {
+        let _: fn($($arg),*) $(-> $ret)? = <$msg>::$name;
+        $crate::__assert_message_api_items!($msg; $($rest)*);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    struct Msg(i32);
+    struct MsgView<'a>(PhantomData<&'a Msg>);
+
+    impl Msg {
+        const FULL_NAME: &'static str = "pkg.Msg";
+
+        fn new() -> Self {
+            Self(0)
+        }
+
+        fn id(&self) -> i32 {
+            self.0
+        }
+
+        fn id_set(&mut self, id: Option<i32>) {
+            self.0 = id.unwrap_or(0);
+        }
+
+        fn as_view(&self) -> MsgView<'_> {
+            MsgView(PhantomData)
+        }
+
+        fn deserialize(&mut self, data: &[u8]) -> Result<(), ()> {
+            self.0 = data.len() as i32;
+            Ok(())
+        }
+    }
+
+    crate::assert_message_api!(Msg {
+        type MsgView<'static>;
+        const FULL_NAME: &'static str;
+        fn new() -> Msg;
+        fn id(&self) -> i32;
+        fn id_set(&mut self, Option<i32>);
+        fn as_view(&self) -> MsgView<'_>;
+        fn deserialize(&mut self, &[u8]) -> Result<(), ()>;
+    });
+
+    #[test]
+    fn test_asserted_items_are_usable() {
+        let mut msg = Msg::new();
+        msg.id_set(Some(3));
+        assert!(msg.deserialize(b"ab").is_ok());
+        let _ = msg.as_view();
+        assert_eq!((Msg::FULL_NAME, msg.id()), ("pkg.Msg", 2));
+    }
+}
@@ -272,7 +272,13 @@ def _rust_proto_aspect_common(target, ctx, is_upb):
     )
     return [RustProtoInfo(dep_variant_info = dep_variant_info)]
 
-def _make_proto_library_aspect(is_upb):
+def _proto_lang_toolchain_label(is_upb, raw_handles):
+    return "//rust:proto_rust_{}{}_toolchain".format(
+        "upb" if is_upb else "cpp",
+        "_raw_handles" if raw_handles else "",
+    )
+
+def _make_proto_library_aspect(is_upb, raw_handles = False):
     return aspect(
         implementation = (_rust_upb_proto_aspect_impl if is_upb else _rust_cc_proto_aspect_impl),
         attr_aspects = ["deps"],
@@ -320,7 +326,7 @@ def _make_proto_library_aspect(is_upb):
                 cfg = "exec",
             ),
             "_proto_lang_toolchain": attr.label(
-                default = Label(("//rust:proto_rust_upb_toolchain" if is_upb else "//rust:proto_rust_cpp_toolchain")),
+                default = Label(_proto_lang_toolchain_label(is_upb, raw_handles)),
             ),
         },
         fragments = ["cpp"],
@@ -334,3 +340,14 @@ def _make_proto_library_aspect(is_upb):
 
 rust_upb_proto_library_aspect = _make_proto_library_aspect(is_upb = True)
 rust_cc_proto_library_aspect = _make_proto_library_aspect(is_upb = False)
+
+# Generate with `raw_handles=enabled`. A proto_library must only be compiled
+# with one of the two aspects of a kernel, since both write the same files.
+rust_upb_raw_handles_proto_library_aspect = _make_proto_library_aspect(
+    is_upb = True,
+    raw_handles = True,
+)
+rust_cc_raw_handles_proto_library_aspect = _make_proto_library_aspect(
+    is_upb = False,
+    raw_handles = True,
+)
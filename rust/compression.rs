@@ -0,0 +1,108 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Pluggable compression of serialized messages.
+//!
+//! The runtime doesn't depend on any compression library. Instead, wrap the
+//! library of your choice (zstd, gzip, ...) in a [`Codec`] and pass it to the
+//! generated `serialize_compressed` and `deserialize_compressed` methods, or
+//! to [`RecordWriter::with_codec`](crate::RecordWriter::with_codec).
+
+use crate::ParseError;
+
+/// Compresses and decompresses serialized messages.
+pub trait Codec {
+    /// Appends the compressed form of `input` to `out`.
+    fn compress(&self, input: &[u8], out: &mut Vec<u8>);
+
+    /// Appends the decompressed form of `input` to `out`, or fails if `input`
+    /// was not produced by [`Codec::compress`].
+    fn decompress(&self, input: &[u8], out: &mut Vec<u8>) -> Result<(), ParseError>;
+}
+
+impl<C: Codec + ?Sized> Codec for &C {
+    fn compress(&self, input: &[u8], out: &mut Vec<u8>) {
+        (**self).compress(input, out)
+    }
+
+    fn decompress(&self, input: &[u8], out: &mut Vec<u8>) -> Result<(), ParseError> {
+        (**self).decompress(input, out)
+    }
+}
+
+impl<C: Codec + ?Sized> Codec for Box<C> {
+    fn compress(&self, input: &[u8], out: &mut Vec<u8>) {
+        (**self).compress(input, out)
+    }
+
+    fn decompress(&self, input: &[u8], out: &mut Vec<u8>) -> Result<(), ParseError> {
+        (**self).decompress(input, out)
+    }
+}
+
+/// Compresses already serialized message bytes with `codec`.
+///
+/// This is what the generated `serialize_compressed` method calls.
+pub fn compress_serialized(serialized: &[u8], codec: &dyn Codec) -> Vec<u8> {
+    let mut out = Vec::new();
+    codec.compress(serialized, &mut out);
+    out
+}
+
+/// Decompresses bytes produced by [`compress_serialized`] with `codec`, ready
+/// to be passed to `<Message>::deserialize()`.
+///
+/// This is what the generated `deserialize_compressed` method calls.
+pub fn decompress_serialized(data: &[u8], codec: &dyn Codec) -> Result<Vec<u8>, ParseError> {
+    let mut out = Vec::new();
+    codec.decompress(data, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A toy run-length codec.
+    pub(crate) struct Rle;
+
+    impl Codec for Rle {
+        fn compress(&self, input: &[u8], out: &mut Vec<u8>) {
+            let mut rest = input;
+            while let Some(&b) = rest.first() {
+                let run = rest.iter().take(255).take_while(|&&c| c == b).count();
+                out.extend_from_slice(&[run as u8, b]);
+                rest = &rest[run..];
+            }
+        }
+
+        fn decompress(&self, input: &[u8], out: &mut Vec<u8>) -> Result<(), ParseError> {
+            let pairs = input.chunks_exact(2);
+            if !pairs.remainder().is_empty() {
+                return Err(ParseError);
+            }
+            for pair in pairs {
+                out.resize(out.len() + usize::from(pair[0]), pair[1]);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data = [&[0u8; 300][..], b"\x08\x01"].concat();
+        let compressed = compress_serialized(&data, &Rle);
+        assert_eq!(compressed, b"\xff\x00\x2d\x00\x01\x08\x01\x01");
+        assert_eq!(decompress_serialized(&compressed, &Rle).unwrap(), data);
+        assert_eq!(decompress_serialized(b"", &Rle).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_decompress_error() {
+        assert!(decompress_serialized(b"\x01", &Rle).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Merging and splitting serialized messages without parsing them.
+//!
+//! Parsing the concatenation of two serialized messages of the same type is
+//! the same as parsing the first and merging the second into it: scalars of
+//! the second replace those of the first, repeated fields are appended to
+//! and submessages are merged. A server can thus merge partial updates, or
+//! split a message into per-field chunks (e.g. to store them separately) and
+//! join them back, without a round trip through the kernel's parser.
+
+use crate::wire::{WireError, WireReader};
+
+/// One top-level field of a serialized message, as split by [`split_fields`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldChunk<'a> {
+    pub field_number: u32,
+
+    /// The field's serialized bytes, tag included.
+    pub data: &'a [u8],
+}
+
+/// Concatenates serialized messages of the same type. Parsing the result is
+/// the same as merging the messages in order.
+///
+/// Inputs aren't checked; if one of them is malformed, so is the result.
+pub fn concat_serialized<I>(parts: I) -> Vec<u8>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut out = Vec::new();
+    for part in parts {
+        out.extend_from_slice(part.as_ref());
+    }
+    out
+}
+
+/// Splits the serialized message `data` into its top-level fields, in order.
+/// Concatenating the chunks' `data` gives back `data`.
+pub fn split_fields(data: &[u8]) -> Result<Vec<FieldChunk<'_>>, WireError> {
+    let mut chunks = Vec::new();
+    let mut reader = WireReader::new(data);
+    while !reader.is_empty() {
+        let start = reader.remaining();
+        let (field_number, _) = reader.read_field()?;
+        let len = start.len() - reader.remaining().len();
+        chunks.push(FieldChunk { field_number, data: &start[..len] });
+    }
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concat() {
+        assert_eq!(concat_serialized([b"\x08\x01", b"\x08\x02"]), b"\x08\x01\x08\x02");
+        assert_eq!(concat_serialized(Vec::<Vec<u8>>::new()), b"");
+    }
+
+    #[test]
+    fn test_split() {
+        // 1: 1, 2: "ab", 3: group { 1: 2 }, 1: 3
+        let data = b"\x08\x01\x12\x02ab\x1b\x08\x02\x1c\x08\x03";
+        let chunks = split_fields(data).unwrap();
+        assert_eq!(
+            chunks,
+            [
+                FieldChunk { field_number: 1, data: b"\x08\x01" },
+                FieldChunk { field_number: 2, data: b"\x12\x02ab" },
+                FieldChunk { field_number: 3, data: b"\x1b\x08\x02\x1c" },
+                FieldChunk { field_number: 1, data: b"\x08\x03" },
+            ]
+        );
+        assert_eq!(concat_serialized(chunks.iter().map(|c| c.data)), data);
+        assert_eq!(split_fields(b""), Ok(vec![]));
+        assert_eq!(split_fields(b"\x08\x01\x12\x05"), Err(WireError::Truncated));
+    }
+}
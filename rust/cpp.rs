@@ -119,6 +119,22 @@ impl Deref for SerializedData {
     }
 }
 
+impl AsRef<[u8]> for SerializedData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for SerializedData {
+    fn from(data: Vec<u8>) -> Self {
+        let len = data.len();
+        let data = NonNull::from(Box::leak(data.into_boxed_slice())).cast();
+        // SAFETY: `data` is a `[u8]` of `len` bytes that the Rust global
+        // allocator allocated, and nothing else owns it.
+        unsafe { Self::from_raw_parts(data, len) }
+    }
+}
+
 impl Drop for SerializedData {
     fn drop(&mut self) {
         // SAFETY: `data` was allocated by the Rust global allocator with a
@@ -201,4 +217,10 @@ mod tests {
         let serialized_data = SerializedData { data: NonNull::new(ptr).unwrap(), len: len };
         assert_eq!(&*serialized_data, b"Hello world");
     }
+
+    #[test]
+    fn test_serialized_data_from_vec() {
+        assert_eq!(&*SerializedData::from(b"Hello world".to_vec()), b"Hello world");
+        assert_eq!(&*SerializedData::from(Vec::new()), b"");
+    }
 }
@@ -12,6 +12,8 @@
 
 #include <cstddef>
 
+#include "google/protobuf/io/coded_stream.h"
+#include "google/protobuf/io/zero_copy_stream_impl_lite.h"
 #include "google/protobuf/message.h"
 
 namespace google {
@@ -48,6 +50,22 @@ inline SerializedData SerializeMsg(const google::protobuf::Message* msg) {
   return SerializedData(static_cast<char*>(bytes), len);
 }
 
+// Like `SerializeMsg`, but with deterministic serialization enabled, so that
+// map entries are written in key order.
+inline SerializedData SerializeMsgDeterministic(
+    const google::protobuf::Message* msg) {
+  size_t len = msg->ByteSizeLong();
+  void* bytes = __pb_rust_alloc(len, alignof(char));
+  google::protobuf::io::ArrayOutputStream array_stream(bytes,
+                                                       static_cast<int>(len));
+  google::protobuf::io::CodedOutputStream stream(&array_stream);
+  stream.SetSerializationDeterministic(true);
+  if (!msg->SerializeToCodedStream(&stream) || stream.HadError()) {
+    ABSL_LOG(FATAL) << "Couldn't serialize the message.";
+  }
+  return SerializedData(static_cast<char*>(bytes), len);
+}
+
 // Represents an ABI-stable version of &[u8]/string_view (borrowed slice of
 // bytes) for FFI use only.
 struct PtrAndLen {
@@ -0,0 +1,171 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Printing serialized messages for logs, with limits on how much is printed.
+//!
+//! Without descriptors, fields are printed by number, like
+//! `protoc --decode_raw` does.
+
+// TODO: Print field names and typed values once descriptors are available.
+
+use crate::wire::{WireReader, WireValue};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Limits on what `debug_string` prints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugStringOptions {
+    /// How many levels of submessages are printed. Deeper submessages are
+    /// summarized by their size.
+    pub max_depth: usize,
+
+    /// How many occurrences of each field number are printed per message. The
+    /// number of elided ones is printed after the message's other fields.
+    pub max_repeated: usize,
+
+    /// How many bytes of each string or bytes value are printed.
+    pub max_bytes_len: usize,
+}
+
+impl Default for DebugStringOptions {
+    fn default() -> Self {
+        Self { max_depth: 16, max_repeated: 16, max_bytes_len: 256 }
+    }
+}
+
+/// Prints the serialized message `data` (e.g. the output of
+/// `<Message>::serialize()`) as indented text, one field per line.
+///
+/// A length-delimited field is printed as a submessage if it parses as one,
+/// and as an escaped string otherwise. Malformed input is printed up to the
+/// first error, followed by the error.
+pub fn debug_string(data: &[u8], options: &DebugStringOptions) -> String {
+    let mut out = String::new();
+    print_message(&mut out, data, options, 0);
+    out
+}
+
+fn print_message(out: &mut String, data: &[u8], options: &DebugStringOptions, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let mut occurrences: BTreeMap<u32, usize> = BTreeMap::new();
+    for field in WireReader::new(data) {
+        let (field_number, value) = match field {
+            Ok(field) => field,
+            Err(e) => {
+                let _ = writeln!(out, "{indent}# {e}");
+                break;
+            }
+        };
+        let count = occurrences.entry(field_number).or_default();
+        *count += 1;
+        if *count > options.max_repeated {
+            continue;
+        }
+
+        match value {
+            WireValue::Varint(v) => {
+                let _ = writeln!(out, "{indent}{field_number}: {v}");
+            }
+            WireValue::Fixed32(v) => {
+                let _ = writeln!(out, "{indent}{field_number}: 0x{v:08x}");
+            }
+            WireValue::Fixed64(v) => {
+                let _ = writeln!(out, "{indent}{field_number}: 0x{v:016x}");
+            }
+            WireValue::Group(sub) => print_submessage(out, field_number, sub, options, depth),
+            WireValue::Delimited(sub) if is_message(sub) => {
+                print_submessage(out, field_number, sub, options, depth)
+            }
+            WireValue::Delimited(bytes) => {
+                let _ = write!(out, "{indent}{field_number}: \"");
+                let shown = &bytes[..bytes.len().min(options.max_bytes_len)];
+                for &b in shown {
+                    out.extend(std::ascii::escape_default(b).map(char::from));
+                }
+                out.push('"');
+                if shown.len() < bytes.len() {
+                    let _ = write!(out, " # {} more bytes", bytes.len() - shown.len());
+                }
+                out.push('\n');
+            }
+        }
+    }
+
+    for (field_number, count) in occurrences {
+        if count > options.max_repeated {
+            let elided = count - options.max_repeated;
+            let _ = writeln!(out, "{indent}# {elided} more of field {field_number}");
+        }
+    }
+}
+
+fn print_submessage(
+    out: &mut String,
+    field_number: u32,
+    data: &[u8],
+    options: &DebugStringOptions,
+    depth: usize,
+) {
+    let indent = "  ".repeat(depth);
+    if depth >= options.max_depth {
+        let _ = writeln!(out, "{indent}{field_number} {{ # {} bytes }}", data.len());
+    } else {
+        let _ = writeln!(out, "{indent}{field_number} {{");
+        print_message(out, data, options, depth + 1);
+        let _ = writeln!(out, "{indent}}}");
+    }
+}
+
+/// Returns whether `data` is a non-empty, well-formed message.
+fn is_message(data: &[u8]) -> bool {
+    !data.is_empty() && WireReader::new(data).all(|field| field.is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values() {
+        // 1: 150, 2: "a\n", 3: { 1: 1 }, 4: group { 1: 2 }, 5: fixed32, 6: fixed64
+        let data = b"\x08\x96\x01\x12\x02a\n\x1a\x02\x08\x01\x23\x08\x02\x24\x2d\x01\x00\x00\x00\
+                     \x31\x02\x00\x00\x00\x00\x00\x00\x00";
+        assert_eq!(
+            debug_string(data, &DebugStringOptions::default()),
+            "1: 150\n\
+             2: \"a\\n\"\n\
+             3 {\n  1: 1\n}\n\
+             4 {\n  1: 2\n}\n\
+             5: 0x00000001\n\
+             6: 0x0000000000000002\n"
+        );
+        assert_eq!(debug_string(b"", &DebugStringOptions::default()), "");
+    }
+
+    #[test]
+    fn test_limits() {
+        // 1: 1, 1: 2, 1: 3, 2: "abcdef", 3: { 3: { 1: 1 } }
+        let data = b"\x08\x01\x08\x02\x08\x03\x12\x06abcdef\x1a\x04\x1a\x02\x08\x01";
+        let options = DebugStringOptions { max_depth: 1, max_repeated: 2, max_bytes_len: 4 };
+        assert_eq!(
+            debug_string(data, &options),
+            "1: 1\n\
+             1: 2\n\
+             2: \"abcd\" # 2 more bytes\n\
+             3 {\n  3 { # 2 bytes }\n}\n\
+             # 1 more of field 1\n"
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            debug_string(b"\x08\x01\x12\x05ab", &DebugStringOptions::default()),
+            "1: 1\n# Input ends in the middle of a field\n"
+        );
+    }
+}
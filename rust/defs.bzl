@@ -4,11 +4,14 @@ Disclaimer: This project is experimental, under heavy development, and should no
 be used yet."""
 
 load("@rules_cc//cc:defs.bzl", "cc_proto_library")
+load("@rules_rust//rust:defs.bzl", "rust_test")
 load(
     "//rust:aspects.bzl",
     "RustProtoInfo",
     "rust_cc_proto_library_aspect",
+    "rust_cc_raw_handles_proto_library_aspect",
     "rust_upb_proto_library_aspect",
+    "rust_upb_raw_handles_proto_library_aspect",
 )
 
 visibility([
@@ -17,7 +20,7 @@ visibility([
     "//rust/...",
 ])
 
-def rust_proto_library(name, deps, visibility = [], **args):
+def rust_proto_library(name, deps, visibility = [], raw_handles = False, **args):
     """Declares all the boilerplate needed to use Rust protobufs conveniently. 
 
     Hopefully no user will ever need to read this code.
@@ -26,6 +29,10 @@ def rust_proto_library(name, deps, visibility = [], **args):
     Args:
         name: name of the Rust protobuf target.
         deps: proto_library target for which to generate Rust gencode.
+        raw_handles: whether to generate the `unsafe` `into_raw()` and
+            `from_raw()` conversions between messages and kernel handles. The
+            proto_library (and its deps) must not also be used by a
+            rust_proto_library without them.
         **args: other args passed to the rust_<kernel>_proto_library targets.
     """
     if not name.endswith("_rust_proto"):
@@ -39,10 +46,14 @@ def rust_proto_library(name, deps, visibility = [], **args):
         visibility = visibility,
     )
 
-    rust_upb_proto_library(
+    # The per-kernel targets are visible so that `rust_test_for_each_kernel`
+    # can depend on them.
+    upb_library = rust_upb_raw_handles_proto_library if raw_handles else rust_upb_proto_library
+    cc_library = rust_cc_raw_handles_proto_library if raw_handles else rust_cc_proto_library
+    upb_library(
         name = name + "_upb_kernel",
         deps = deps,
-        visibility = ["//visibility:private"],
+        visibility = visibility,
         **args
     )
 
@@ -55,13 +66,40 @@ def rust_proto_library(name, deps, visibility = [], **args):
             visibility = ["//visibility:private"],
             **args
         )
-    rust_cc_proto_library(
+    cc_library(
         name = name + "_cpp_kernel",
         deps = [_cc_proto_name],
-        visibility = ["//visibility:private"],
+        visibility = visibility,
         **args
     )
 
+def rust_test_for_each_kernel(name, srcs, rust_proto_deps, deps = [], **args):
+    """Declares the same Rust test once per kernel, so that both are covered.
+
+    Declares `<name>_cpp_test` and `<name>_upb_test`, each of which depends on
+    the matching kernel's variant of `rust_proto_deps` and can use the runtime
+    as `protobuf`.
+
+    Args:
+        name: prefix of the names of the test targets.
+        srcs: sources of both tests.
+        rust_proto_deps: rust_proto_library targets the tests use. Labels must
+            spell out the target name, e.g. `//foo:bar_rust_proto`.
+        deps: other dependencies of both tests.
+        **args: other args passed to both rust_test targets.
+    """
+    for kernel in ["cpp", "upb"]:
+        rust_test(
+            name = "{}_{}_test".format(name, kernel),
+            srcs = srcs,
+            aliases = {"//rust:protobuf_" + kernel: "protobuf"},
+            deps = deps + ["//rust:protobuf_" + kernel] + [
+                "{}_{}_kernel".format(dep, kernel)
+                for dep in rust_proto_deps
+            ],
+            **args
+        )
+
 def _rust_proto_library_impl(ctx):
     deps = ctx.attr.deps
     if not deps:
@@ -74,17 +112,23 @@ def _rust_proto_library_impl(ctx):
     dep_variant_info = rust_proto_info.dep_variant_info
     return [dep_variant_info.crate_info, dep_variant_info.dep_info, dep_variant_info.cc_info]
 
-def _make_rust_proto_library(is_upb):
+def _make_rust_proto_library(is_upb, raw_handles = False):
+    if raw_handles:
+        aspect = rust_upb_raw_handles_proto_library_aspect if is_upb else rust_cc_raw_handles_proto_library_aspect
+    else:
+        aspect = rust_upb_proto_library_aspect if is_upb else rust_cc_proto_library_aspect
     return rule(
         implementation = _rust_proto_library_impl,
         attrs = {
             "deps": attr.label_list(
                 mandatory = True,
                 providers = [ProtoInfo] if is_upb else [CcInfo],
-                aspects = [rust_upb_proto_library_aspect if is_upb else rust_cc_proto_library_aspect],
+                aspects = [aspect],
             ),
         },
     )
 
 rust_upb_proto_library = _make_rust_proto_library(is_upb = True)
 rust_cc_proto_library = _make_rust_proto_library(is_upb = False)
+rust_upb_raw_handles_proto_library = _make_rust_proto_library(is_upb = True, raw_handles = True)
+rust_cc_raw_handles_proto_library = _make_rust_proto_library(is_upb = False, raw_handles = True)
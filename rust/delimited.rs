@@ -0,0 +1,198 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Streams of varint length-delimited messages, as written by
+//! `writeDelimitedTo` in Java and `SerializeDelimitedToOstream` in C++.
+
+use crate::wire::{WireError, WireReader};
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::thread;
+
+/// Iterates over the frames of a fully-buffered delimited stream, yielding
+/// each message's bytes without the length prefix.
+///
+/// Yields an error and stops if the stream ends in the middle of a frame.
+#[derive(Debug, Clone)]
+pub struct DelimitedFrames<'a> {
+    reader: WireReader<'a>,
+}
+
+impl<'a> DelimitedFrames<'a> {
+    /// Iterates over the frames in `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { reader: WireReader::new(data) }
+    }
+}
+
+impl<'a> Iterator for DelimitedFrames<'a> {
+    type Item = Result<&'a [u8], WireError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.reader.is_empty() {
+            return None;
+        }
+        let frame = self.reader.read_delimited();
+        if frame.is_err() {
+            self.reader = WireReader::new(&[]);
+        }
+        Some(frame)
+    }
+}
+
+/// Decodes every frame of a delimited stream with `decode`, spreading the
+/// frames over up to `threads` threads.
+///
+/// The results are returned in stream order. `decode` may return the parsed
+/// messages themselves, since generated messages are `Send`.
+///
+/// The stream is split into frames up front, so a framing error is reported
+/// before anything is decoded. Otherwise, the error of the earliest frame that
+/// failed to decode is returned.
+pub fn decode_delimited_parallel<T, E>(
+    data: &[u8],
+    threads: NonZeroUsize,
+    decode: impl Fn(&[u8]) -> Result<T, E> + Sync,
+) -> Result<Vec<T>, ParallelDecodeError<E>>
+where
+    T: Send,
+    E: Send,
+{
+    let frames: Vec<&[u8]> = DelimitedFrames::new(data)
+        .collect::<Result<_, _>>()
+        .map_err(ParallelDecodeError::Framing)?;
+    if frames.is_empty() {
+        return Ok(Vec::new());
+    }
+    let chunk_len = frames.len().div_ceil(threads.get());
+    let decode = &decode;
+
+    let chunks: Vec<Result<Vec<T>, (usize, E)>> = thread::scope(|s| {
+        let handles: Vec<_> = frames
+            .chunks(chunk_len)
+            .enumerate()
+            .map(|(c, chunk)| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .map(|(i, frame)| decode(frame).map_err(|e| (c * chunk_len + i, e)))
+                        .collect()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    });
+
+    let mut out = Vec::with_capacity(frames.len());
+    for chunk in chunks {
+        match chunk {
+            Ok(values) => out.extend(values),
+            Err((index, error)) => return Err(ParallelDecodeError::Decode { index, error }),
+        }
+    }
+    Ok(out)
+}
+
+/// An error that happened while decoding a delimited stream in parallel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParallelDecodeError<E> {
+    /// The stream couldn't be split into frames.
+    Framing(WireError),
+
+    /// Decoding the frame at `index` failed with `error`.
+    Decode { index: usize, error: E },
+}
+
+impl<E> ParallelDecodeError<E> {
+    /// Returns a stable code for the error, e.g. for metrics labels. Frames
+    /// that fail to decode are `"decode"`, whatever `E` is.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParallelDecodeError::Framing(e) => e.code(),
+            ParallelDecodeError::Decode { .. } => "decode",
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ParallelDecodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParallelDecodeError::Framing(e) => write!(f, "Couldn't split delimited stream: {e}"),
+            ParallelDecodeError::Decode { index, error } => {
+                write!(f, "Couldn't decode frame {index}: {error}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(n: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        for i in 0..n {
+            // A message with `1: i`.
+            out.extend_from_slice(&[2, 0x08, i as u8]);
+        }
+        out
+    }
+
+    fn decode(frame: &[u8]) -> Result<u64, WireError> {
+        match frame {
+            [0x08, rest @ ..] => WireReader::new(rest).read_varint(),
+            _ => Err(WireError::Truncated),
+        }
+    }
+
+    #[test]
+    fn test_frames() {
+        let frames: Vec<_> = DelimitedFrames::new(b"\x01a\x00\x02bc").collect();
+        assert_eq!(frames, [Ok(&b"a"[..]), Ok(b""), Ok(b"bc")]);
+
+        let mut frames = DelimitedFrames::new(b"\x01a\x03b");
+        assert_eq!(frames.next(), Some(Ok(&b"a"[..])));
+        assert_eq!(frames.next(), Some(Err(WireError::Truncated)));
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    fn test_preserves_order() {
+        for threads in [1, 3, 8, 200] {
+            let threads = NonZeroUsize::new(threads).unwrap();
+            assert_eq!(
+                decode_delimited_parallel(&stream(100), threads, decode),
+                Ok((0..100).collect::<Vec<_>>())
+            );
+        }
+        let threads = NonZeroUsize::new(4).unwrap();
+        assert_eq!(decode_delimited_parallel(&[], threads, decode), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let threads = NonZeroUsize::new(4).unwrap();
+        let mut data = stream(20);
+        // Frames 5 and 15 don't start with a tag for field 1.
+        data[5 * 3 + 1] = 0x10;
+        data[15 * 3 + 1] = 0x10;
+        assert_eq!(
+            decode_delimited_parallel(&data, threads, decode),
+            Err(ParallelDecodeError::Decode { index: 5, error: WireError::Truncated })
+        );
+
+        data.push(0x05);
+        assert_eq!(
+            decode_delimited_parallel(&data, threads, decode),
+            Err(ParallelDecodeError::Framing(WireError::Truncated))
+        );
+    }
+}
@@ -0,0 +1,65 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Reporting uses of fields marked `deprecated = true` at runtime.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+type Hook = Box<dyn Fn(&'static str) + Send + Sync>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+/// Registers `hook` to be called with the full name of a deprecated field
+/// (e.g. `"my.pkg.MyMessage.old_field"`) the first time generated code reads
+/// or writes it, replacing any previously registered hook.
+///
+/// Each field is reported at most once per process. Uses of a field before a
+/// hook is registered aren't reported, but don't prevent later reports.
+pub fn set_deprecated_field_hook(hook: impl Fn(&'static str) + Send + Sync + 'static) {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(hook));
+}
+
+/// Unregisters the hook set by [`set_deprecated_field_hook`], if any.
+pub fn clear_deprecated_field_hook() {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Called by generated accessors of deprecated fields. `reported` is a static
+/// flag owned by the field.
+pub fn report_deprecated_field_use(reported: &AtomicBool, full_name: &'static str) {
+    if reported.load(Ordering::Relaxed) {
+        return;
+    }
+    let hook = HOOK.read().unwrap_or_else(|e| e.into_inner());
+    if let Some(hook) = hook.as_ref() {
+        if !reported.swap(true, Ordering::Relaxed) {
+            hook(full_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    static REPORTED: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+    #[test]
+    fn test_report_once() {
+        static FIELD: AtomicBool = AtomicBool::new(false);
+        report_deprecated_field_use(&FIELD, "pkg.Msg.field");
+
+        set_deprecated_field_hook(|name| REPORTED.lock().unwrap().push(name));
+        report_deprecated_field_use(&FIELD, "pkg.Msg.field");
+        report_deprecated_field_use(&FIELD, "pkg.Msg.field");
+        clear_deprecated_field_hook();
+
+        assert_eq!(*REPORTED.lock().unwrap(), ["pkg.Msg.field"]);
+    }
+}
@@ -0,0 +1,64 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! An opt-in, process-wide registry of the serialized `FileDescriptorProto`s
+//! of generated files.
+//!
+//! Every generated message has `file_descriptor_bytes()`, and
+//! `register_file_descriptor()` adds those bytes here, so that code that needs
+//! schemas at runtime (e.g. a reflection service, or resolving the type of an
+//! `Any`) can find them by file name without descriptors being plumbed
+//! through by hand. Registering a file doesn't register its dependencies.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+static REGISTRY: RwLock<BTreeMap<&'static str, &'static [u8]>> = RwLock::new(BTreeMap::new());
+
+/// Registers the serialized `FileDescriptorProto` of file `name` (e.g.
+/// `"google/protobuf/unittest.proto"`). Registering a file again replaces its
+/// bytes.
+pub fn register_file_descriptor(name: &'static str, serialized: &'static [u8]) {
+    REGISTRY.write().unwrap_or_else(|e| e.into_inner()).insert(name, serialized);
+}
+
+/// Returns the serialized `FileDescriptorProto` registered for file `name`.
+pub fn registered_file_descriptor(name: &str) -> Option<&'static [u8]> {
+    REGISTRY.read().unwrap_or_else(|e| e.into_inner()).get(name).copied()
+}
+
+/// Returns the names and serialized `FileDescriptorProto`s of all registered
+/// files, sorted by name.
+pub fn registered_file_descriptors() -> Vec<(&'static str, &'static [u8])> {
+    REGISTRY.read().unwrap_or_else(|e| e.into_inner()).iter().map(|(&k, &v)| (k, v)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(registered_file_descriptor("test/registry_a.proto"), None);
+        register_file_descriptor("test/registry_b.proto", b"\x0a\x01b");
+        register_file_descriptor("test/registry_a.proto", b"");
+        register_file_descriptor("test/registry_a.proto", b"\x0a\x01a");
+        assert_eq!(registered_file_descriptor("test/registry_a.proto"), Some(&b"\x0a\x01a"[..]));
+
+        let files: Vec<_> = registered_file_descriptors()
+            .into_iter()
+            .filter(|(name, _)| name.starts_with("test/registry_"))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("test/registry_a.proto", &b"\x0a\x01a"[..]),
+                ("test/registry_b.proto", &b"\x0a\x01b"[..])
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! A standard envelope for publishing messages on topics (e.g. Kafka or
+//! NATS), so that consumers can tell what a record holds before parsing it:
+//!
+//! ```protobuf
+//! message Envelope {
+//!   string type_url = 1;
+//!   bytes payload = 2;
+//!   map<string, string> headers = 3;
+//! }
+//! ```
+//!
+//! Publishing a message is
+//! `Envelope::new(MyMessage::TYPE_URL, msg.serialize().to_vec()).serialize()`,
+//! and consuming one is
+//! `msg.deserialize(Envelope::parse(data)?.payload_for(MyMessage::TYPE_URL)?)`.
+
+use crate::wire::{WireError, WireReader, WireValue, WireWriter};
+use std::collections::BTreeMap;
+use std::fmt;
+
+const TYPE_URL: u32 = 1;
+const PAYLOAD: u32 = 2;
+const HEADERS: u32 = 3;
+const HEADER_KEY: u32 = 1;
+const HEADER_VALUE: u32 = 2;
+
+/// A serialized message with its type URL and optional headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Envelope {
+    /// The payload's type URL, e.g. `<Message>::TYPE_URL`.
+    pub type_url: String,
+
+    /// The serialized message, e.g. the output of `<Message>::serialize()`.
+    pub payload: Vec<u8>,
+
+    /// Application-defined headers, e.g. a trace ID or a producer name.
+    pub headers: BTreeMap<String, String>,
+}
+
+/// An error that happened while reading an envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeError {
+    /// The input isn't valid wire format.
+    Wire(WireError),
+
+    /// The type URL or a header isn't UTF-8.
+    InvalidUtf8,
+
+    /// The envelope has no type URL.
+    MissingTypeUrl,
+
+    /// The payload isn't of the expected type.
+    UnexpectedType,
+}
+
+impl EnvelopeError {
+    /// Returns a stable code for the error, e.g. for metrics labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Wire(e) => e.code(),
+            Self::InvalidUtf8 => "invalid_utf8",
+            Self::MissingTypeUrl => "missing_type_url",
+            Self::UnexpectedType => "unexpected_type",
+        }
+    }
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Wire(e) => write!(f, "{e}"),
+            Self::InvalidUtf8 => write!(f, "Envelope has a type URL or header that isn't UTF-8"),
+            Self::MissingTypeUrl => write!(f, "Envelope has no type URL"),
+            Self::UnexpectedType => write!(f, "Envelope payload isn't of the expected type"),
+        }
+    }
+}
+
+impl From<WireError> for EnvelopeError {
+    fn from(e: WireError) -> Self {
+        Self::Wire(e)
+    }
+}
+
+impl Envelope {
+    /// Constructs an envelope without headers.
+    pub fn new(type_url: impl Into<String>, payload: Vec<u8>) -> Self {
+        Self { type_url: type_url.into(), payload, headers: BTreeMap::new() }
+    }
+
+    /// Adds header `key`, replacing any previous value.
+    pub fn with_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    /// Returns the payload if it has type URL `type_url`, ready to be passed
+    /// to `<Message>::deserialize()`.
+    pub fn payload_for(&self, type_url: &str) -> Result<&[u8], EnvelopeError> {
+        if self.type_url != type_url {
+            return Err(EnvelopeError::UnexpectedType);
+        }
+        Ok(&self.payload)
+    }
+
+    /// Serializes the envelope. Headers are written in key order, so equal
+    /// envelopes serialize to equal bytes.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = WireWriter::new();
+        out.write_delimited(TYPE_URL, self.type_url.as_bytes());
+        out.write_delimited(PAYLOAD, &self.payload);
+        for (key, value) in &self.headers {
+            let mut entry = WireWriter::new();
+            entry.write_delimited(HEADER_KEY, key.as_bytes());
+            entry.write_delimited(HEADER_VALUE, value.as_bytes());
+            out.write_delimited(HEADERS, entry.as_bytes());
+        }
+        out.into_vec()
+    }
+
+    /// Parses a serialized envelope.
+    ///
+    /// As for any message, the last type URL and payload win, as does the
+    /// last value of a repeated header.
+    pub fn parse(data: &[u8]) -> Result<Self, EnvelopeError> {
+        let mut envelope = Self::default();
+        for field in WireReader::new(data) {
+            match field? {
+                (TYPE_URL, WireValue::Delimited(url)) => envelope.type_url = utf8(url)?,
+                (PAYLOAD, WireValue::Delimited(payload)) => envelope.payload = payload.to_vec(),
+                (HEADERS, WireValue::Delimited(entry)) => {
+                    let (mut key, mut value) = (String::new(), String::new());
+                    for field in WireReader::new(entry) {
+                        match field? {
+                            (HEADER_KEY, WireValue::Delimited(k)) => key = utf8(k)?,
+                            (HEADER_VALUE, WireValue::Delimited(v)) => value = utf8(v)?,
+                            _ => {}
+                        }
+                    }
+                    envelope.headers.insert(key, value);
+                }
+                _ => {}
+            }
+        }
+        if envelope.type_url.is_empty() {
+            return Err(EnvelopeError::MissingTypeUrl);
+        }
+        Ok(envelope)
+    }
+}
+
+fn utf8(data: &[u8]) -> Result<String, EnvelopeError> {
+    std::str::from_utf8(data).map(str::to_owned).map_err(|_| EnvelopeError::InvalidUtf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let envelope = Envelope::new("pkg.M", b"\x08\x01".to_vec())
+            .with_header("trace", "abc")
+            .with_header("from", "me");
+        let data = envelope.serialize();
+        assert_eq!(
+            data,
+            b"\x0a\x05pkg.M\x12\x02\x08\x01\
+              \x1a\x0a\x0a\x04from\x12\x02me\
+              \x1a\x0c\x0a\x05trace\x12\x03abc"
+        );
+        assert_eq!(Envelope::parse(&data), Ok(envelope));
+    }
+
+    #[test]
+    fn test_payload_for() {
+        let envelope = Envelope::new("pkg.M", b"\x08\x01".to_vec());
+        assert_eq!(envelope.payload_for("pkg.M"), Ok(&b"\x08\x01"[..]));
+        assert_eq!(envelope.payload_for("pkg.N"), Err(EnvelopeError::UnexpectedType));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Envelope::parse(b"\x12\x00"), Err(EnvelopeError::MissingTypeUrl));
+        assert_eq!(Envelope::parse(b"\x0a\x01\xff"), Err(EnvelopeError::InvalidUtf8));
+        assert_eq!(
+            Envelope::parse(b"\x0a\x01m\x1a\x03\x0a\x01\xff"),
+            Err(EnvelopeError::InvalidUtf8)
+        );
+        assert_eq!(Envelope::parse(b"\x0a\x02m"), Err(WireError::Truncated.into()));
+
+        assert_eq!(EnvelopeError::MissingTypeUrl.code(), "missing_type_url");
+        assert_eq!(EnvelopeError::Wire(WireError::Truncated).code(), "truncated");
+    }
+}
@@ -0,0 +1,97 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Discovering which extensions are set on a serialized message.
+//!
+//! Rust Protobuf has no typed extension accessors yet, so extensions are
+//! reported by number with their undecoded wire format value.
+
+// TODO: Add an `ExtensionRegistry` that can be passed to `deserialize()`, so
+// that known extensions are parsed into typed storage instead of being kept
+// as unknown fields (as C++ and Java do). This needs typed extension
+// identifiers in gencode, and both kernels need a registry to parse
+// MessageSet payloads.
+
+use crate::wire::{WireError, WireReader, WireValue};
+use std::ops::Range;
+
+/// An extension field set on a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtensionNumberAndValue<'a> {
+    /// The extension's field number.
+    pub number: u32,
+
+    /// The extension's value, as it appeared on the wire.
+    pub value: WireValue<'a>,
+}
+
+/// Iterates over the extension fields of a serialized message, in wire order.
+///
+/// A repeated extension is yielded once per element (or once per packed
+/// run). Stops after the first error.
+#[derive(Debug, Clone)]
+pub struct ExtensionFields<'a> {
+    reader: WireReader<'a>,
+    ranges: &'a [Range<u32>],
+}
+
+impl<'a> ExtensionFields<'a> {
+    /// Reads the extensions in `data` (e.g. the output of
+    /// `<Message>::serialize()`), where `ranges` are the message's extension
+    /// ranges, i.e. `<Message>::EXTENSION_RANGES`.
+    pub fn new(data: &'a [u8], ranges: &'a [Range<u32>]) -> Self {
+        Self { reader: WireReader::new(data), ranges }
+    }
+}
+
+impl<'a> Iterator for ExtensionFields<'a> {
+    type Item = Result<ExtensionNumberAndValue<'a>, WireError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for field in &mut self.reader {
+            match field {
+                Ok((number, value)) if self.ranges.iter().any(|r| r.contains(&number)) => {
+                    return Some(Ok(ExtensionNumberAndValue { number, value }));
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RANGES: &[Range<u32>] = &[1..2, 100..200];
+
+    #[test]
+    fn test_extensions() {
+        // 1: 1, 2: 2, 150: "ab", 199: 3, 200: 4
+        let data = b"\x08\x01\x10\x02\xb2\x09\x02ab\xb8\x0c\x03\xc0\x0c\x04";
+        let found: Vec<_> = ExtensionFields::new(data, RANGES).map(Result::unwrap).collect();
+        assert_eq!(
+            found,
+            [
+                ExtensionNumberAndValue { number: 1, value: WireValue::Varint(1) },
+                ExtensionNumberAndValue { number: 150, value: WireValue::Delimited(b"ab") },
+                ExtensionNumberAndValue { number: 199, value: WireValue::Varint(3) },
+            ]
+        );
+        assert_eq!(ExtensionFields::new(data, &[]).count(), 0);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut fields = ExtensionFields::new(b"\x08\x01\x10", RANGES);
+        assert!(fields.next().unwrap().is_ok());
+        assert_eq!(fields.next(), Some(Err(WireError::Truncated)));
+        assert_eq!(fields.next(), None);
+    }
+}
@@ -0,0 +1,248 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Pulling selected fields out of serialized messages without parsing them.
+//!
+//! A path is a list of field numbers: `[3, 1]` is field 1 of the submessage
+//! (or group) in field 3. Everything that isn't on a requested path is
+//! skipped without being decoded further.
+
+use crate::wire::{WirePathError, WireReader, WireValue};
+use std::fmt;
+use std::io::{self, Read};
+
+/// Extracts the values at a fixed set of field number paths.
+#[derive(Debug, Clone)]
+pub struct FieldExtractor {
+    paths: Vec<Vec<u32>>,
+}
+
+impl FieldExtractor {
+    /// Constructs an extractor for `paths`. Empty paths never match.
+    pub fn new<P: AsRef<[u32]>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Self { paths: paths.into_iter().map(|p| p.as_ref().to_vec()).collect() }
+    }
+
+    /// Calls `f` with the index of the path and the value for every field of
+    /// `data` that is at one of the paths, in wire order.
+    ///
+    /// Fails if `data` or one of the submessages on a path is malformed.
+    /// Fields are reported up to the error, and the error's path leads to the
+    /// malformed submessage.
+    pub fn extract<'a>(
+        &self,
+        data: &'a [u8],
+        mut f: impl FnMut(usize, WireValue<'a>),
+    ) -> Result<(), WirePathError> {
+        let candidates: Vec<usize> =
+            (0..self.paths.len()).filter(|&i| !self.paths[i].is_empty()).collect();
+        self.scan(data, 0, &candidates, &mut f)
+    }
+
+    fn scan<'a>(
+        &self,
+        data: &'a [u8],
+        depth: usize,
+        candidates: &[usize],
+        f: &mut impl FnMut(usize, WireValue<'a>),
+    ) -> Result<(), WirePathError> {
+        let mut nested = Vec::new();
+        for field in WireReader::new(data) {
+            let (field_number, value) = field?;
+            nested.clear();
+            for &i in candidates {
+                let path = &self.paths[i];
+                if path[depth] != field_number {
+                    continue;
+                }
+                if path.len() == depth + 1 {
+                    f(i, value);
+                } else {
+                    nested.push(i);
+                }
+            }
+            if nested.is_empty() {
+                continue;
+            }
+            if let WireValue::Delimited(sub) | WireValue::Group(sub) = value {
+                self.scan(sub, depth + 1, &nested, f).map_err(|e| e.in_field(field_number))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs [`FieldExtractor::extract`] on every record of a stream of varint
+    /// length-delimited messages, calling `f` with the index of the record,
+    /// the index of the path and the value.
+    ///
+    /// Only one record is held in memory at a time; records longer than
+    /// `max_record_len` are rejected. Returns the number of records read.
+    pub fn extract_delimited_stream(
+        &self,
+        mut input: impl Read,
+        max_record_len: usize,
+        mut f: impl FnMut(usize, usize, WireValue<'_>),
+    ) -> Result<usize, ExtractError> {
+        let mut buf = Vec::new();
+        let mut records = 0;
+        while read_delimited_record(&mut input, max_record_len, &mut buf)? {
+            self.extract(&buf, |path, value| f(records, path, value))
+                .map_err(|e| ExtractError::Wire { record: records, error: e })?;
+            records += 1;
+        }
+        Ok(records)
+    }
+}
+
+/// Reads one varint length-delimited record into `buf`, returning `false` at
+/// a clean end of input.
+pub(crate) fn read_delimited_record(
+    input: &mut impl Read,
+    max_record_len: usize,
+    buf: &mut Vec<u8>,
+) -> Result<bool, ExtractError> {
+    let mut len = 0u64;
+    for i in 0..10 {
+        let mut b = [0];
+        if input.read(&mut b)? == 0 {
+            return if i == 0 { Ok(false) } else { Err(ExtractError::Truncated) };
+        }
+        len |= u64::from(b[0] & 0x7f) << (7 * i);
+        if b[0] < 0x80 {
+            let len = usize::try_from(len)
+                .ok()
+                .filter(|&len| len <= max_record_len)
+                .ok_or(ExtractError::RecordTooLarge { len })?;
+            buf.clear();
+            if input.take(len as u64).read_to_end(buf)? != len {
+                return Err(ExtractError::Truncated);
+            }
+            return Ok(true);
+        }
+    }
+    Err(ExtractError::MalformedLength)
+}
+
+/// An error that happened while extracting fields from a stream.
+#[derive(Debug)]
+pub enum ExtractError {
+    /// The underlying reader failed.
+    Io(io::Error),
+
+    /// The stream ends in the middle of a record.
+    Truncated,
+
+    /// A record's length prefix is longer than 10 bytes.
+    MalformedLength,
+
+    /// A record's length prefix exceeds the maximum record length.
+    RecordTooLarge { len: u64 },
+
+    /// A record is malformed. `record` is its index in the stream.
+    Wire { record: usize, error: WirePathError },
+}
+
+impl From<io::Error> for ExtractError {
+    fn from(e: io::Error) -> Self {
+        ExtractError::Io(e)
+    }
+}
+
+impl ExtractError {
+    /// Returns a stable code for the error, e.g. for metrics labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExtractError::Io(_) => "io",
+            ExtractError::Truncated => "truncated",
+            ExtractError::MalformedLength => "malformed_length",
+            ExtractError::RecordTooLarge { .. } => "record_too_large",
+            ExtractError::Wire { error, .. } => error.code(),
+        }
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExtractError::Io(e) => write!(f, "Couldn't read record stream: {e}"),
+            ExtractError::Truncated => write!(f, "Record stream ends in the middle of a record"),
+            ExtractError::MalformedLength => write!(f, "Record length is longer than 10 bytes"),
+            ExtractError::RecordTooLarge { len } => {
+                write!(f, "Record of {len} bytes exceeds the maximum record length")
+            }
+            ExtractError::Wire { record, error } => write!(f, "Record {record}: {error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::WireError;
+
+    // 1: 7, 2: { 1: "x", 3: { 1: 9 } }, 2: { 1: "y" }, 4: group { 1: 5 }
+    const MSG: &[u8] =
+        b"\x08\x07\x12\x07\x0a\x01x\x1a\x02\x08\x09\x12\x03\x0a\x01y\x23\x08\x05\x24";
+
+    fn extract<'a>(
+        paths: &[&[u32]],
+        data: &'a [u8],
+    ) -> Result<Vec<(usize, WireValue<'a>)>, WirePathError> {
+        let mut out = Vec::new();
+        FieldExtractor::new(paths).extract(data, |i, v| out.push((i, v)))?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_extract() {
+        assert_eq!(
+            extract(&[&[2, 1], &[1], &[2, 3, 1], &[4, 1]], MSG).unwrap(),
+            [
+                (1, WireValue::Varint(7)),
+                (0, WireValue::Delimited(b"x")),
+                (2, WireValue::Varint(9)),
+                (0, WireValue::Delimited(b"y")),
+                (3, WireValue::Varint(5)),
+            ]
+        );
+        assert_eq!(extract(&[&[], &[5], &[1, 1]], MSG).unwrap(), []);
+    }
+
+    #[test]
+    fn test_extract_malformed() {
+        // Only submessages on a requested path are parsed.
+        let data = b"\x0a\x01\xff\x12\x01\x08";
+        assert_eq!(extract(&[&[2]], data).unwrap(), [(0, WireValue::Delimited(b"\x08"))]);
+        assert_eq!(extract(&[&[1, 1]], data).unwrap_err().path(), [1]);
+        assert_eq!(extract(&[&[1, 1]], data).unwrap_err().error(), WireError::Truncated);
+    }
+
+    #[test]
+    fn test_extract_delimited_stream() {
+        let stream = b"\x02\x08\x01\x00\x02\x08\x03";
+        let mut out = Vec::new();
+        let records = FieldExtractor::new([[1]])
+            .extract_delimited_stream(&stream[..], 16, |r, p, v| out.push((r, p, v.wire_type())))
+            .unwrap();
+        assert_eq!(records, 3);
+        assert_eq!(out.iter().map(|&(r, _, _)| r).collect::<Vec<_>>(), [0, 2]);
+
+        let extractor = FieldExtractor::new([[1]]);
+        assert!(matches!(
+            extractor.extract_delimited_stream(&b"\x05\x08"[..], 16, |_, _, _| {}),
+            Err(ExtractError::Truncated)
+        ));
+        assert!(matches!(
+            extractor.extract_delimited_stream(&b"\x11"[..], 16, |_, _, _| {}),
+            Err(ExtractError::RecordTooLarge { len: 17 })
+        ));
+        assert!(matches!(
+            extractor.extract_delimited_stream(&b"\x00\x01\x08"[..], 16, |_, _, _| {}),
+            Err(ExtractError::Wire { record: 1, error }) if error.error() == WireError::Truncated
+        ));
+    }
+}
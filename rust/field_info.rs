@@ -0,0 +1,282 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Static information about the fields of generated messages.
+//!
+//! Every generated message has a `FIELDS` table, and `fields_with_presence()`
+//! pairs each entry with whether the field is set. This is much cheaper than
+//! full reflection, e.g. for loggers that only print the fields that are set.
+
+use crate::wire::{WireError, WireReader, WireType};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/// The type of a field, as declared in its `.proto` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FieldKind {
+    Double,
+    Float,
+    Int64,
+    UInt64,
+    Int32,
+    Fixed64,
+    Fixed32,
+    Bool,
+    String,
+    Group,
+    Message,
+    Bytes,
+    UInt32,
+    Enum,
+    SFixed32,
+    SFixed64,
+    SInt32,
+    SInt64,
+}
+
+impl FieldKind {
+    /// Returns the wire type of a single value of this kind. Packed repeated
+    /// fields are `Delimited` instead.
+    pub fn wire_type(self) -> WireType {
+        match self {
+            Self::Int64
+            | Self::UInt64
+            | Self::Int32
+            | Self::Bool
+            | Self::UInt32
+            | Self::Enum
+            | Self::SInt32
+            | Self::SInt64 => WireType::Varint,
+            Self::Double | Self::Fixed64 | Self::SFixed64 => WireType::Fixed64,
+            Self::Float | Self::Fixed32 | Self::SFixed32 => WireType::Fixed32,
+            Self::String | Self::Message | Self::Bytes => WireType::Delimited,
+            Self::Group => WireType::StartGroup,
+        }
+    }
+}
+
+/// The message type of a message or group field, as listed in its
+/// [`FieldInfo`].
+///
+/// Two `MessageType`s are equal if their full names are.
+#[derive(Clone, Copy)]
+pub struct MessageType {
+    /// The full name of the message type, e.g. `"my.pkg.MyMessage"`.
+    pub full_name: &'static str,
+
+    /// Returns the type's `FIELDS` table. `None` unless the type is declared
+    /// in the same `.proto` file as the field, since generated code can't
+    /// name the types of other files yet.
+    pub fields: Option<fn() -> &'static [FieldInfo]>,
+}
+
+impl PartialEq for MessageType {
+    fn eq(&self, other: &Self) -> bool {
+        self.full_name == other.full_name
+    }
+}
+
+impl Eq for MessageType {}
+
+impl fmt::Debug for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MessageType").field("full_name", &self.full_name).finish_non_exhaustive()
+    }
+}
+
+/// A field of a generated message, as listed in `<Message>::FIELDS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldInfo {
+    pub number: u32,
+
+    /// The field's name in its `.proto` file.
+    pub name: &'static str,
+
+    pub kind: FieldKind,
+
+    /// Whether the field is repeated (or a map).
+    pub is_repeated: bool,
+
+    /// Whether the field is serialized packed, as set by its `packed` option
+    /// or its edition's `repeated_field_encoding` feature. Always `false` for
+    /// fields that can't be packed.
+    pub is_packed: bool,
+
+    /// Whether the field tracks presence, i.e. whether it can be unset
+    /// rather than hold its default value, as resolved from its label or
+    /// its edition's `field_presence` feature.
+    pub has_presence: bool,
+
+    /// Whether the field is of a closed enum type, whose unknown values are
+    /// kept as unknown fields when parsing. Always `false` for fields that
+    /// aren't enums.
+    pub is_closed_enum: bool,
+
+    /// Whether parsing checks that the field is UTF-8, as set by its
+    /// syntax or its edition's `utf8_validation` feature. Always `false` for
+    /// fields that aren't strings.
+    pub validates_utf8: bool,
+
+    /// The field's message type, for message and group fields. `None` for
+    /// other fields, and for map fields, whose entry types aren't generated.
+    pub message_type: Option<MessageType>,
+}
+
+/// A field in serialized data that its message type doesn't declare, e.g.
+/// because it was removed from the schema after the data was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndeclaredField {
+    pub field_number: u32,
+
+    /// How many times the field occurs.
+    pub count: usize,
+
+    /// How many serialized bytes its occurrences take.
+    pub bytes: usize,
+}
+
+/// Lists the top-level fields of the serialized message `data` that are not
+/// in `fields` and not in `extension_ranges`, i.e. that `<Message>::FIELDS`
+/// and `<Message>::EXTENSION_RANGES` would report as unknown. The result is
+/// sorted by field number.
+///
+/// Only field numbers are known; reporting the names of removed fields
+/// needs the descriptors the data was written with.
+pub fn undeclared_fields(
+    data: &[u8],
+    fields: &[FieldInfo],
+    extension_ranges: &[Range<u32>],
+) -> Result<Vec<UndeclaredField>, WireError> {
+    let mut undeclared: BTreeMap<u32, UndeclaredField> = BTreeMap::new();
+    let mut reader = WireReader::new(data);
+    while !reader.is_empty() {
+        let before = reader.remaining().len();
+        let (field_number, _) = reader.read_field()?;
+        if fields.iter().any(|f| f.number == field_number)
+            || extension_ranges.iter().any(|r| r.contains(&field_number))
+        {
+            continue;
+        }
+        let entry = undeclared.entry(field_number).or_insert(UndeclaredField {
+            field_number,
+            count: 0,
+            bytes: 0,
+        });
+        entry.count += 1;
+        entry.bytes += before - reader.remaining().len();
+    }
+    Ok(undeclared.into_values().collect())
+}
+
+/// A singular field that occurs more than once in serialized data. Parsing
+/// keeps the last value of a scalar field and merges the occurrences of a
+/// message field, as the wire format specifies, but producers that emit
+/// duplicates usually have a bug.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DuplicateField {
+    pub field_number: u32,
+
+    /// How many times the field occurs.
+    pub count: usize,
+}
+
+/// Lists the top-level singular fields in `fields` that occur more than once
+/// in the serialized message `data`, sorted by field number.
+pub fn duplicate_fields(
+    data: &[u8],
+    fields: &[FieldInfo],
+) -> Result<Vec<DuplicateField>, WireError> {
+    let mut counts: BTreeMap<u32, usize> = BTreeMap::new();
+    for field in WireReader::new(data) {
+        let (field_number, _) = field?;
+        if fields.iter().any(|f| f.number == field_number && !f.is_repeated) {
+            *counts.entry(field_number).or_insert(0) += 1;
+        }
+    }
+    Ok(counts
+        .into_iter()
+        .filter(|&(_, count)| count > 1)
+        .map(|(field_number, count)| DuplicateField { field_number, count })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_type() {
+        assert_eq!(FieldKind::SInt64.wire_type(), WireType::Varint);
+        assert_eq!(FieldKind::Double.wire_type(), WireType::Fixed64);
+        assert_eq!(FieldKind::SFixed32.wire_type(), WireType::Fixed32);
+        assert_eq!(FieldKind::Message.wire_type(), WireType::Delimited);
+        assert_eq!(FieldKind::Group.wire_type(), WireType::StartGroup);
+    }
+
+    #[test]
+    fn test_undeclared_fields() {
+        const FIELDS: &[FieldInfo] = &[FieldInfo {
+            number: 1,
+            name: "id",
+            kind: FieldKind::Int32,
+            is_repeated: false,
+            is_packed: false,
+            has_presence: true,
+            is_closed_enum: false,
+            validates_utf8: false,
+            message_type: None,
+        }];
+        const EXTENSION_RANGES: &[Range<u32>] = &[Range { start: 100, end: 200 }];
+        // 1: 1, 7: 1, 3: "ab", 7: 2, 100: 3
+        let data = b"\x08\x01\x38\x01\x1a\x02ab\x38\x02\xa0\x06\x03";
+        assert_eq!(
+            undeclared_fields(data, FIELDS, EXTENSION_RANGES),
+            Ok(vec![
+                UndeclaredField { field_number: 3, count: 1, bytes: 4 },
+                UndeclaredField { field_number: 7, count: 2, bytes: 4 },
+            ])
+        );
+        assert_eq!(undeclared_fields(b"\x38", FIELDS, &[]), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn test_duplicate_fields() {
+        const FIELDS: &[FieldInfo] = &[
+            FieldInfo {
+                number: 1,
+                name: "id",
+                kind: FieldKind::Int32,
+                is_repeated: false,
+                is_packed: false,
+                has_presence: true,
+                is_closed_enum: false,
+                validates_utf8: false,
+                message_type: None,
+            },
+            FieldInfo {
+                number: 2,
+                name: "tags",
+                kind: FieldKind::String,
+                is_repeated: true,
+                is_packed: false,
+                has_presence: false,
+                is_closed_enum: false,
+                validates_utf8: true,
+                message_type: None,
+            },
+        ];
+        // 1: 1, 2: "a", 1: 2, 2: "b", 7: 1, 7: 2, 1: 3
+        let data = b"\x08\x01\x12\x01a\x08\x02\x12\x01b\x38\x01\x38\x02\x08\x03";
+        assert_eq!(
+            duplicate_fields(data, FIELDS),
+            Ok(vec![DuplicateField { field_number: 1, count: 3 }])
+        );
+        assert_eq!(duplicate_fields(b"\x08\x01", FIELDS), Ok(vec![]));
+        assert_eq!(duplicate_fields(b"\x08", FIELDS), Err(WireError::Truncated));
+    }
+}
@@ -0,0 +1,242 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Dropping unneeded fields from serialized bytes before they are parsed.
+//!
+//! Filtering is a single pass over the wire format that copies allowed fields
+//! and skips everything else without decoding it, so large fields that a
+//! service doesn't read never reach the kernel's parser.
+
+use crate::field_info::FieldInfo;
+use crate::wire::{write_varint, WirePathError, WireReader, WireValue};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// A field number allowlist for a message type and, optionally, for the
+/// types of its submessage fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldFilter {
+    fields: BTreeMap<u32, Option<FieldFilter>>,
+}
+
+impl FieldFilter {
+    /// Constructs a filter that drops every field.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a filter that keeps the fields that both `src` and `dst`
+    /// (e.g. `<Message>::FIELDS` of two mirrored message types) declare with
+    /// the same number, kind and cardinality, so that data of the `src` type
+    /// can be parsed as the `dst` type.
+    ///
+    /// Submessage fields also need the same `message_type`, so that a
+    /// submessage is never parsed as an unrelated type. They are then kept
+    /// whole.
+    pub fn matching(src: &[FieldInfo], dst: &[FieldInfo]) -> Self {
+        let mut filter = Self::new();
+        for field in src {
+            if dst.iter().any(|d| {
+                d.number == field.number
+                    && d.kind == field.kind
+                    && d.is_repeated == field.is_repeated
+                    && d.message_type == field.message_type
+            }) {
+                filter = filter.allow(field.number);
+            }
+        }
+        filter
+    }
+
+    /// Keeps field `field_number` as-is.
+    pub fn allow(mut self, field_number: u32) -> Self {
+        self.fields.insert(field_number, None);
+        self
+    }
+
+    /// Keeps the submessage (or group) field `field_number`, filtering its
+    /// contents with `nested`.
+    pub fn allow_message(mut self, field_number: u32, nested: FieldFilter) -> Self {
+        self.fields.insert(field_number, Some(nested));
+        self
+    }
+
+    /// Returns whether field `field_number` is kept.
+    pub fn is_allowed(&self, field_number: u32) -> bool {
+        self.fields.contains_key(&field_number)
+    }
+
+    /// Returns the allowed fields of `data`, ready to be passed to
+    /// `<Message>::deserialize()`. They are copied into a new buffer, unless
+    /// every field of `data` is allowed as it is, in which case `data` itself
+    /// is returned.
+    ///
+    /// Fails if `data`, or a submessage that has a nested filter, is
+    /// malformed. The error's path leads to the malformed submessage.
+    pub fn filter<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>, WirePathError> {
+        // Only allocated once a field is dropped or changed, starting with a
+        // copy of the fields before it.
+        let mut out: Option<Vec<u8>> = None;
+        let mut reader = WireReader::new(data);
+        while !reader.is_empty() {
+            let start = reader.remaining();
+            let offset = data.len() - start.len();
+            let (field_number, wire_type) = reader.read_tag()?;
+            let tag_len = start.len() - reader.remaining().len();
+            let value = reader.read_value(field_number, wire_type)?;
+            let field = &start[..start.len() - reader.remaining().len()];
+
+            // What to write instead of `field`, or `None` to keep it as it is.
+            let replacement = match (self.fields.get(&field_number), value) {
+                (None, _) => Some(Vec::new()),
+                (Some(Some(nested)), WireValue::Delimited(sub)) => {
+                    match nested.filter(sub).map_err(|e| e.in_field(field_number))? {
+                        Cow::Borrowed(_) => None,
+                        Cow::Owned(filtered) => {
+                            let mut field = field[..tag_len].to_vec();
+                            write_varint(&mut field, filtered.len() as u64);
+                            field.extend_from_slice(&filtered);
+                            Some(field)
+                        }
+                    }
+                }
+                (Some(Some(nested)), WireValue::Group(sub)) => {
+                    match nested.filter(sub).map_err(|e| e.in_field(field_number))? {
+                        Cow::Borrowed(_) => None,
+                        // Between the start and end group tags.
+                        Cow::Owned(filtered) => Some(
+                            [&field[..tag_len], &filtered, &field[tag_len + sub.len()..]].concat(),
+                        ),
+                    }
+                }
+                // Values that can't be submessages are kept, so that the
+                // kernel decides what to do with the unexpected wire type.
+                (Some(_), _) => None,
+            };
+            match (replacement, &mut out) {
+                (None, None) => {}
+                (None, Some(out)) => out.extend_from_slice(field),
+                (Some(replacement), out) => {
+                    let out = out.get_or_insert_with(|| {
+                        let mut out = Vec::with_capacity(data.len());
+                        out.extend_from_slice(&data[..offset]);
+                        out
+                    });
+                    out.extend_from_slice(&replacement);
+                }
+            }
+        }
+        Ok(out.map_or(Cow::Borrowed(data), Cow::Owned))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::WireError;
+
+    // 1: 1, 2: "big", 3: { 1: 2, 2: "big" }, 4: group { 1: 3, 2: 4 }, 1: 5
+    const MSG: &[u8] =
+        b"\x08\x01\x12\x03big\x1a\x07\x08\x02\x12\x03big\x23\x08\x03\x10\x04\x24\x08\x05";
+
+    #[test]
+    fn test_allow() {
+        assert_eq!(&*FieldFilter::new().filter(MSG).unwrap(), b"");
+        assert_eq!(&*FieldFilter::new().allow(1).filter(MSG).unwrap(), b"\x08\x01\x08\x05");
+        assert_eq!(
+            &*FieldFilter::new().allow(2).allow(3).filter(MSG).unwrap(),
+            b"\x12\x03big\x1a\x07\x08\x02\x12\x03big"
+        );
+        assert!(FieldFilter::new().allow(2).is_allowed(2));
+        assert!(!FieldFilter::new().allow(2).is_allowed(3));
+    }
+
+    #[test]
+    fn test_allow_message() {
+        let filter = FieldFilter::new()
+            .allow_message(3, FieldFilter::new().allow(1))
+            .allow_message(4, FieldFilter::new().allow(2));
+        assert_eq!(&*filter.filter(MSG).unwrap(), b"\x1a\x02\x08\x02\x23\x10\x04\x24");
+    }
+
+    #[test]
+    fn test_borrows_unless_something_is_dropped() {
+        let all = FieldFilter::new()
+            .allow(1)
+            .allow(2)
+            .allow_message(3, FieldFilter::new().allow(1).allow(2))
+            .allow_message(4, FieldFilter::new().allow(1).allow(2));
+        assert!(matches!(all.filter(MSG).unwrap(), Cow::Borrowed(data) if data == MSG));
+
+        let nested = all.clone().allow_message(4, FieldFilter::new().allow(1));
+        assert!(matches!(nested.filter(MSG).unwrap(), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_matching() {
+        use crate::field_info::{FieldKind, MessageType};
+
+        fn field(number: u32, kind: FieldKind, is_repeated: bool) -> FieldInfo {
+            FieldInfo {
+                number,
+                name: "",
+                kind,
+                is_repeated,
+                is_packed: false,
+                has_presence: !is_repeated,
+                is_closed_enum: false,
+                validates_utf8: false,
+                message_type: None,
+            }
+        }
+        fn message(number: u32, full_name: &'static str) -> FieldInfo {
+            let message_type = Some(MessageType { full_name, fields: None });
+            FieldInfo { message_type, ..field(number, FieldKind::Message, false) }
+        }
+        let src = [
+            field(1, FieldKind::Int32, false),
+            field(2, FieldKind::String, false),
+            message(3, "pkg.Inner"),
+            field(4, FieldKind::Int64, true),
+            field(5, FieldKind::Int32, false),
+            message(6, "pkg.Inner"),
+        ];
+        let dst = [
+            field(1, FieldKind::Int32, false),
+            field(2, FieldKind::Bytes, false),
+            message(3, "pkg.Inner"),
+            field(4, FieldKind::Int64, false),
+            message(6, "pkg.Other"),
+        ];
+        let filter = FieldFilter::matching(&src, &dst);
+        assert_eq!(filter, FieldFilter::new().allow(1).allow(3));
+        assert_eq!(&*filter.filter(MSG).unwrap(), b"\x08\x01\x1a\x07\x08\x02\x12\x03big\x08\x05");
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(FieldFilter::new().filter(b"\x08"), Err(WireError::Truncated.into()));
+
+        // Submessages are only parsed if they have a nested filter.
+        let data = b"\x0a\x01\xff";
+        assert_eq!(&*FieldFilter::new().allow(1).filter(data).unwrap(), data);
+        assert_eq!(
+            FieldFilter::new().allow_message(1, FieldFilter::new()).filter(data),
+            Err(WirePathError::from(WireError::Truncated).in_field(1))
+        );
+
+        // Errors in nested groups and submessages carry the path to them.
+        let data = b"\x0b\x12\x01\xff\x0c";
+        let filter = FieldFilter::new()
+            .allow_message(1, FieldFilter::new().allow_message(2, FieldFilter::new()));
+        assert_eq!(filter.filter(data).unwrap_err().path(), [1, 2]);
+
+        // A scalar where a submessage is expected is left to the kernel.
+        let filter = FieldFilter::new().allow_message(1, FieldFilter::new());
+        assert_eq!(&*filter.filter(b"\x08\x01").unwrap(), b"\x08\x01");
+    }
+}
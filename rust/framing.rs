@@ -0,0 +1,372 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Length-prefixed message framing as used by gRPC-web and Connect.
+//!
+//! Both protocols wrap each serialized message in a 5-byte prefix: one byte
+//! of flags followed by the payload length as a big-endian `u32`. gRPC-web
+//! sends its trailers in a final frame with the high flag bit set, and
+//! Connect streaming marks its end-of-stream message with `0x02`.
+//!
+//! This module does no I/O. Decoding reports how many more bytes are needed
+//! when a frame is incomplete, so it can be driven by blocking and async
+//! readers alike.
+
+use std::fmt;
+
+/// The compressed-payload flag, shared by gRPC-web and Connect.
+const FLAG_COMPRESSED: u8 = 0x01;
+
+/// The Connect end-of-stream flag.
+const FLAG_CONNECT_END_STREAM: u8 = 0x02;
+
+/// The gRPC-web trailers flag.
+const FLAG_GRPC_WEB_TRAILERS: u8 = 0x80;
+
+/// The length of the flags and length prefix of every frame.
+pub const GRPC_FRAME_HEADER_LEN: usize = 5;
+
+/// What a frame's payload contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrpcFrameKind {
+    /// A serialized message.
+    Message,
+
+    /// A gRPC-web trailers block, see [`GrpcFrame::grpc_web_trailers`].
+    GrpcWebTrailers,
+
+    /// A Connect end-of-stream message. The payload is JSON.
+    ConnectEndStream,
+}
+
+/// A single length-prefixed frame, borrowing its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrpcFrame<'a> {
+    kind: GrpcFrameKind,
+    compressed: bool,
+    payload: &'a [u8],
+}
+
+impl<'a> GrpcFrame<'a> {
+    /// Constructs an uncompressed frame holding serialized message bytes.
+    ///
+    /// This accepts the output of `<Message>::serialize()` directly.
+    pub fn message(payload: &'a [u8]) -> Self {
+        Self { kind: GrpcFrameKind::Message, compressed: false, payload }
+    }
+
+    /// Constructs a gRPC-web trailers frame from an encoded trailers block.
+    ///
+    /// Use [`encode_grpc_web_trailers`] to build the block.
+    pub fn grpc_web_trailers(block: &'a [u8]) -> Self {
+        Self { kind: GrpcFrameKind::GrpcWebTrailers, compressed: false, payload: block }
+    }
+
+    /// Constructs a Connect end-of-stream frame from its JSON payload.
+    pub fn connect_end_stream(json: &'a [u8]) -> Self {
+        Self { kind: GrpcFrameKind::ConnectEndStream, compressed: false, payload: json }
+    }
+
+    /// Marks whether the payload has already been compressed by the caller.
+    pub fn with_compressed(self, compressed: bool) -> Self {
+        Self { compressed, ..self }
+    }
+
+    /// Returns what this frame's payload contains.
+    pub fn kind(&self) -> GrpcFrameKind {
+        self.kind
+    }
+
+    /// Returns whether the compressed flag is set.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Returns the payload, without the 5-byte prefix.
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    fn flags(&self) -> u8 {
+        let kind = match self.kind {
+            GrpcFrameKind::Message => 0,
+            GrpcFrameKind::GrpcWebTrailers => FLAG_GRPC_WEB_TRAILERS,
+            GrpcFrameKind::ConnectEndStream => FLAG_CONNECT_END_STREAM,
+        };
+        if self.compressed { kind | FLAG_COMPRESSED } else { kind }
+    }
+
+    /// Appends the encoded frame to `out`.
+    ///
+    /// Fails if the payload is longer than `u32::MAX` bytes.
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<(), FramingError> {
+        let len = u32::try_from(self.payload.len()).map_err(|_| FramingError::TooLarge)?;
+        out.reserve(GRPC_FRAME_HEADER_LEN + self.payload.len());
+        out.push(self.flags());
+        out.extend_from_slice(&len.to_be_bytes());
+        out.extend_from_slice(self.payload);
+        Ok(())
+    }
+
+    /// Decodes the frame at the start of `data`, returning it along with the
+    /// bytes that follow it.
+    ///
+    /// If `data` doesn't hold a full frame yet, this returns
+    /// [`FramingError::Incomplete`] with the number of missing bytes.
+    pub fn decode(data: &'a [u8]) -> Result<(Self, &'a [u8]), FramingError> {
+        if data.len() < GRPC_FRAME_HEADER_LEN {
+            return Err(FramingError::Incomplete { needed: GRPC_FRAME_HEADER_LEN - data.len() });
+        }
+        let (header, rest) = data.split_at(GRPC_FRAME_HEADER_LEN);
+        let flags = header[0];
+        let kind = match flags & !FLAG_COMPRESSED {
+            0 => GrpcFrameKind::Message,
+            FLAG_GRPC_WEB_TRAILERS => GrpcFrameKind::GrpcWebTrailers,
+            FLAG_CONNECT_END_STREAM => GrpcFrameKind::ConnectEndStream,
+            _ => return Err(FramingError::UnknownFlags(flags)),
+        };
+        let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+        // A `u32` always fits in `usize` on the platforms the runtime supports.
+        let len = len as usize;
+        if rest.len() < len {
+            return Err(FramingError::Incomplete { needed: len - rest.len() });
+        }
+        let (payload, rest) = rest.split_at(len);
+        Ok((Self { kind, compressed: flags & FLAG_COMPRESSED != 0, payload }, rest))
+    }
+}
+
+/// Iterates over the frames of a fully-buffered gRPC-web or Connect body.
+///
+/// Yields an error and stops if the body ends in the middle of a frame.
+#[derive(Debug, Clone)]
+pub struct GrpcFrames<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> GrpcFrames<'a> {
+    /// Iterates over the frames in `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for GrpcFrames<'a> {
+    type Item = Result<GrpcFrame<'a>, FramingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        match GrpcFrame::decode(self.data) {
+            Ok((frame, rest)) => {
+                self.data = rest;
+                Some(Ok(frame))
+            }
+            Err(e) => {
+                self.data = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Encodes gRPC-web trailers as an HTTP/1-style header block.
+///
+/// Names are lowercased as required by gRPC-web. The result goes into a
+/// [`GrpcFrame::grpc_web_trailers`] frame.
+///
+/// Fails with [`FramingError::InvalidTrailer`] if a name isn't an HTTP token
+/// or a value contains CR, LF or NUL, since those would let the value end the
+/// block or inject trailers of its own. Values such as `grpc-message` have to
+/// be percent-encoded by the caller.
+pub fn encode_grpc_web_trailers<'t>(
+    trailers: impl IntoIterator<Item = (&'t str, &'t str)>,
+) -> Result<Vec<u8>, FramingError> {
+    let mut out = Vec::new();
+    for (name, value) in trailers {
+        if name.is_empty() || !name.bytes().all(is_token_byte) {
+            return Err(FramingError::InvalidTrailer);
+        }
+        if value.bytes().any(|b| matches!(b, b'\r' | b'\n' | b'\0')) {
+            return Err(FramingError::InvalidTrailer);
+        }
+        out.extend(name.bytes().map(|b| b.to_ascii_lowercase()));
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    Ok(out)
+}
+
+/// Returns whether `b` may appear in an HTTP header name (a `tchar` in
+/// RFC 9110).
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Parses the payload of a gRPC-web trailers frame into name/value pairs.
+///
+/// Surrounding whitespace is trimmed from both names and values. Empty lines
+/// are ignored.
+pub fn parse_grpc_web_trailers(block: &[u8]) -> Result<Vec<(&str, &str)>, FramingError> {
+    let block = std::str::from_utf8(block).map_err(|_| FramingError::MalformedTrailers)?;
+    block
+        .split("\r\n")
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (name, value) = line.split_once(':').ok_or(FramingError::MalformedTrailers)?;
+            Ok((name.trim(), value.trim()))
+        })
+        .collect()
+}
+
+/// An error that happened while encoding or decoding a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FramingError {
+    /// The input ends before the frame does; `needed` more bytes are
+    /// required to make progress.
+    Incomplete { needed: usize },
+
+    /// The payload is too long to be described by a `u32` length prefix.
+    TooLarge,
+
+    /// The flags byte has bits set that neither gRPC-web nor Connect define.
+    UnknownFlags(u8),
+
+    /// A gRPC-web trailers block is not a valid HTTP/1-style header block.
+    MalformedTrailers,
+
+    /// A trailer to encode has an invalid name, or a value with CR, LF or
+    /// NUL in it.
+    InvalidTrailer,
+}
+
+impl FramingError {
+    /// Returns a stable code for the error, e.g. for metrics labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            FramingError::Incomplete { .. } => "incomplete",
+            FramingError::TooLarge => "too_large",
+            FramingError::UnknownFlags(_) => "unknown_flags",
+            FramingError::MalformedTrailers => "malformed_trailers",
+            FramingError::InvalidTrailer => "invalid_trailer",
+        }
+    }
+}
+
+impl fmt::Display for FramingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FramingError::Incomplete { needed } => {
+                write!(f, "Frame is incomplete, {needed} more bytes are needed")
+            }
+            FramingError::TooLarge => write!(f, "Frame payload is longer than u32::MAX bytes"),
+            FramingError::UnknownFlags(flags) => write!(f, "Unknown frame flags {flags:#04x}"),
+            FramingError::MalformedTrailers => write!(f, "Malformed gRPC-web trailers block"),
+            FramingError::InvalidTrailer => write!(f, "Invalid gRPC-web trailer name or value"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let mut out = Vec::new();
+        GrpcFrame::message(b"\x08\x2a").encode(&mut out).unwrap();
+        assert_eq!(out, b"\x00\x00\x00\x00\x02\x08\x2a");
+
+        let (frame, rest) = GrpcFrame::decode(&out).unwrap();
+        assert_eq!(frame.kind(), GrpcFrameKind::Message);
+        assert!(!frame.is_compressed());
+        assert_eq!(frame.payload(), b"\x08\x2a");
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut out = Vec::new();
+        GrpcFrame::message(b"").with_compressed(true).encode(&mut out).unwrap();
+        GrpcFrame::grpc_web_trailers(b"").encode(&mut out).unwrap();
+        GrpcFrame::connect_end_stream(b"{}").with_compressed(true).encode(&mut out).unwrap();
+        assert_eq!(out[0], 0x01);
+        assert_eq!(out[5], 0x80);
+        assert_eq!(out[10], 0x03);
+
+        let frames: Vec<_> = GrpcFrames::new(&out).collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            frames,
+            [
+                GrpcFrame::message(b"").with_compressed(true),
+                GrpcFrame::grpc_web_trailers(b""),
+                GrpcFrame::connect_end_stream(b"{}").with_compressed(true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_incomplete() {
+        assert_eq!(GrpcFrame::decode(b"\x00\x00"), Err(FramingError::Incomplete { needed: 3 }));
+        assert_eq!(
+            GrpcFrame::decode(b"\x00\x00\x00\x00\x04ab"),
+            Err(FramingError::Incomplete { needed: 2 })
+        );
+
+        let mut frames = GrpcFrames::new(b"\x00\x00\x00\x00\x01a\x00\x00");
+        assert_eq!(frames.next(), Some(Ok(GrpcFrame::message(b"a"))));
+        assert_eq!(frames.next(), Some(Err(FramingError::Incomplete { needed: 3 })));
+        assert_eq!(frames.next(), None);
+    }
+
+    #[test]
+    fn test_decode_unknown_flags() {
+        assert_eq!(GrpcFrame::decode(b"\x04\x00\x00\x00\x00"), Err(FramingError::UnknownFlags(4)));
+        assert_eq!(
+            GrpcFrame::decode(b"\x82\x00\x00\x00\x00"),
+            Err(FramingError::UnknownFlags(0x82))
+        );
+    }
+
+    #[test]
+    fn test_grpc_web_trailers() {
+        let block =
+            encode_grpc_web_trailers([("Grpc-Status", "0"), ("grpc-message", "OK")]).unwrap();
+        assert_eq!(block, b"grpc-status: 0\r\ngrpc-message: OK\r\n");
+        assert_eq!(
+            parse_grpc_web_trailers(&block),
+            Ok(vec![("grpc-status", "0"), ("grpc-message", "OK")])
+        );
+        assert_eq!(parse_grpc_web_trailers(b""), Ok(vec![]));
+        assert_eq!(parse_grpc_web_trailers(b"grpc-status"), Err(FramingError::MalformedTrailers));
+        assert_eq!(parse_grpc_web_trailers(b"\xff: 0"), Err(FramingError::MalformedTrailers));
+    }
+
+    #[test]
+    fn test_grpc_web_trailers_injection() {
+        assert_eq!(
+            encode_grpc_web_trailers([("grpc-message", "a\r\ngrpc-status: 0")]),
+            Err(FramingError::InvalidTrailer)
+        );
+        assert_eq!(
+            encode_grpc_web_trailers([("grpc-message", "a\nb")]),
+            Err(FramingError::InvalidTrailer)
+        );
+        assert_eq!(
+            encode_grpc_web_trailers([("grpc-status: 0\r\nx", "1")]),
+            Err(FramingError::InvalidTrailer)
+        );
+        assert_eq!(encode_grpc_web_trailers([("", "1")]), Err(FramingError::InvalidTrailer));
+        assert_eq!(FramingError::InvalidTrailer.code(), "invalid_trailer");
+        assert_eq!(
+            encode_grpc_web_trailers([("x-note", "a%0D%0Ab")]),
+            Ok(b"x-note: a%0D%0Ab\r\n".to_vec())
+        );
+    }
+}
@@ -0,0 +1,183 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Per message type hooks that generated `deserialize()` and `serialize()`
+//! run, for cross-cutting policies like filling in defaults, enforcing
+//! invariants or decrypting a bytes field without wrapping every call site.
+//!
+//! Like the metrics hook, the only cost to generated code until a hook is
+//! registered is one relaxed atomic load per call.
+//!
+//! Hooks only run for the user-facing calls: `serialize()`,
+//! `serialize_deterministic()`, `deserialize()` and the `deserialize_*()`
+//! variants that parse into the message. Generated methods that serialize or
+//! parse as an implementation detail, like `debug_string()`,
+//! `truncate_to_size()`, `copy_matching_fields_from()` and
+//! `<field>_set_serialized()`, don't run them.
+
+use crate::ParseError;
+use std::any::{Any, TypeId};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+type ParseHook<M> = Arc<dyn Fn(&mut M) -> Result<(), ParseError> + Send + Sync>;
+type SerializeHook<M> = Arc<dyn Fn(&M) + Send + Sync>;
+
+/// Registered hooks in registration order. Each is a `ParseHook<M>` or
+/// `SerializeHook<M>` for the message type with the given `TypeId`.
+///
+/// Hooks are shared so that they can be called after the lock is released:
+/// a hook that serializes or parses any message runs hooks itself.
+struct Hooks {
+    parse: Vec<(TypeId, Box<dyn Any + Send + Sync>)>,
+    serialize: Vec<(TypeId, Box<dyn Any + Send + Sync>)>,
+}
+
+static HOOKS: RwLock<Hooks> = RwLock::new(Hooks { parse: Vec::new(), serialize: Vec::new() });
+static HOOKS_SET: AtomicBool = AtomicBool::new(false);
+
+/// Registers `hook` to be called on every message of type `M` right after
+/// generated `deserialize()` parsed it, e.g. to fill in computed defaults or
+/// decrypt a field. If `hook` fails, so does `deserialize()`.
+///
+/// Hooks of the same type run in the order they were added, and stop at the
+/// first error. Hooks added while a message is being parsed only apply to
+/// later parses.
+pub fn add_parse_hook<M: 'static>(
+    hook: impl Fn(&mut M) -> Result<(), ParseError> + Send + Sync + 'static,
+) {
+    let hook: ParseHook<M> = Arc::new(hook);
+    HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .parse
+        .push((TypeId::of::<M>(), Box::new(hook)));
+    HOOKS_SET.store(true, Ordering::Relaxed);
+}
+
+/// Registers `hook` to be called on every message of type `M` right before
+/// generated `serialize()` or `serialize_deterministic()` serializes it, e.g.
+/// to check invariants.
+///
+/// `serialize()` only borrows the message, so these hooks can't modify it;
+/// policies that do (e.g. encrypting a field) have to run before
+/// `serialize()` is called. Hooks of the same type run in the order they were
+/// added.
+pub fn add_serialize_hook<M: 'static>(hook: impl Fn(&M) + Send + Sync + 'static) {
+    let hook: SerializeHook<M> = Arc::new(hook);
+    HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .serialize
+        .push((TypeId::of::<M>(), Box::new(hook)));
+    HOOKS_SET.store(true, Ordering::Relaxed);
+}
+
+/// Unregisters all hooks added by [`add_parse_hook`] and
+/// [`add_serialize_hook`].
+pub fn clear_message_hooks() {
+    HOOKS_SET.store(false, Ordering::Relaxed);
+    let mut hooks = HOOKS.write().unwrap_or_else(|e| e.into_inner());
+    hooks.parse.clear();
+    hooks.serialize.clear();
+}
+
+/// Called by generated `deserialize()` after a successful parse.
+pub fn run_parse_hooks<M: 'static>(msg: &mut M) -> Result<(), ParseError> {
+    if !HOOKS_SET.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    for hook in matching_hooks::<ParseHook<M>>(|hooks| &hooks.parse, TypeId::of::<M>()) {
+        hook(msg)?;
+    }
+    Ok(())
+}
+
+/// Called by generated `serialize()` and `serialize_deterministic()` before
+/// serializing.
+pub fn run_serialize_hooks<M: 'static>(msg: &M) {
+    if !HOOKS_SET.load(Ordering::Relaxed) {
+        return;
+    }
+    for hook in matching_hooks::<SerializeHook<M>>(|hooks| &hooks.serialize, TypeId::of::<M>()) {
+        hook(msg);
+    }
+}
+
+/// Clones the hooks registered for `type_id` out of `HOOKS`, so that they run
+/// without holding the lock.
+fn matching_hooks<H: Clone + 'static>(
+    list: impl Fn(&Hooks) -> &Vec<(TypeId, Box<dyn Any + Send + Sync>)>,
+    type_id: TypeId,
+) -> Vec<H> {
+    let hooks = HOOKS.read().unwrap_or_else(|e| e.into_inner());
+    list(&hooks)
+        .iter()
+        .filter(|(id, _)| *id == type_id)
+        .filter_map(|(_, hook)| hook.downcast_ref::<H>().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    struct Msg(i32);
+    struct Other(i32);
+
+    static SERIALIZED: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+    #[test]
+    fn test_hooks() {
+        let mut msg = Msg(1);
+        assert!(run_parse_hooks(&mut msg).is_ok());
+
+        add_parse_hook(|m: &mut Msg| {
+            m.0 *= 10;
+            Ok(())
+        });
+        add_parse_hook(|m: &mut Msg| if m.0 > 100 { Err(ParseError) } else { Ok(()) });
+        add_parse_hook(|m: &mut Other| {
+            m.0 = 0;
+            Ok(())
+        });
+        add_serialize_hook(|m: &Msg| SERIALIZED.lock().unwrap().push(m.0));
+
+        // Hooks run in order, and only for their own type.
+        assert!(run_parse_hooks(&mut msg).is_ok());
+        assert_eq!(msg.0, 10);
+        assert!(run_parse_hooks(&mut msg).is_ok());
+        assert_eq!(msg.0, 100);
+        assert!(run_parse_hooks(&mut msg).is_err());
+        let mut other = Other(5);
+        assert!(run_parse_hooks(&mut other).is_ok());
+        assert_eq!(other.0, 0);
+        run_serialize_hooks(&msg);
+        run_serialize_hooks(&other);
+
+        clear_message_hooks();
+        assert!(run_parse_hooks(&mut msg).is_ok());
+        run_serialize_hooks(&msg);
+
+        // A hook can run hooks of its own, even while another thread is
+        // waiting to add one.
+        add_parse_hook(|m: &mut Msg| {
+            let writer = std::thread::spawn(|| add_serialize_hook(|_: &Other| {}));
+            std::thread::sleep(Duration::from_millis(10));
+            run_serialize_hooks(&Other(m.0));
+            writer.join().unwrap();
+            Ok(())
+        });
+        assert!(run_parse_hooks(&mut msg).is_ok());
+        clear_message_hooks();
+
+        assert_eq!(msg.0, 1000);
+        assert_eq!(*SERIALIZED.lock().unwrap(), [1000]);
+    }
+}
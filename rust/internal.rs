@@ -9,16 +9,31 @@
 //! exposed to through the `protobuf` path but must be public for use by
 //! generated code.
 
+use crate::__runtime::SerializedData;
+pub use crate::deprecation::report_deprecated_field_use;
+pub use crate::hooks::{run_parse_hooks, run_serialize_hooks};
+pub use crate::metrics::record_message_op;
 pub use crate::vtable::{
     new_vtable_field_entry, BytesMutVTable, BytesOptionalMutVTable, PrimitiveVTable,
     RawVTableMutator,
 };
+pub use crate::wire::replace_delimited_field;
+use crate::ParseError;
 use std::ptr::NonNull;
 use std::slice;
 
 /// Used to protect internal-only items from being used accidentally.
 pub struct Private;
 
+/// The kernel's serialize and parse of a generated message, without the
+/// metrics and hooks of its public methods, for methods that only round-trip
+/// through the wire format as an implementation detail. They are trait methods
+/// rather than inherent ones so that no field accessor can clash with them.
+pub trait KernelMessage {
+    fn kernel_serialize(&self) -> SerializedData;
+    fn kernel_deserialize(&mut self, data: &[u8]) -> Result<(), ParseError>;
+}
+
 /// Defines a set of opaque, unique, non-accessible pointees.
 ///
 /// The [Rustonomicon][nomicon] currently recommends a zero-sized struct,
@@ -0,0 +1,94 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Recovering what can be recovered from partially written messages.
+
+use crate::wire::{WireError, WireReader};
+use std::fmt;
+
+/// Describes input that was dropped by a lenient parse.
+///
+/// A lenient parse reports at most one: once a field can't be read, the
+/// reader can't tell where the next field would start, so everything from
+/// there to the end of the input is dropped as a single run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LenientWarning {
+    /// The offset of the first dropped byte.
+    pub offset: usize,
+
+    /// How many bytes were dropped, up to the end of the input.
+    pub skipped: usize,
+
+    /// Why the field at `offset` couldn't be read.
+    pub error: WireError,
+}
+
+impl fmt::Display for LenientWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Skipped {} bytes at offset {}: {}", self.skipped, self.offset, self.error)
+    }
+}
+
+/// Splits `data` into its longest prefix of complete top-level fields and a
+/// warning describing the rest, if there is any. There is never more than one
+/// warning, since the rest is dropped as a whole (see `LenientWarning`).
+///
+/// This recovers from truncated final fields and trailing garbage, as left by
+/// partially written files. It doesn't look inside submessages, so the
+/// prefix can still fail to parse if one of them is corrupt.
+pub fn lenient_prefix(data: &[u8]) -> (&[u8], Option<LenientWarning>) {
+    let mut reader = WireReader::new(data);
+    while !reader.is_empty() {
+        let valid = data.len() - reader.remaining().len();
+        if let Err(error) = reader.read_field() {
+            let warning = LenientWarning { offset: valid, skipped: data.len() - valid, error };
+            return (&data[..valid], Some(warning));
+        }
+    }
+    (data, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid() {
+        let data = b"\x08\x01\x12\x02ab";
+        assert_eq!(lenient_prefix(data), (&data[..], None));
+        assert_eq!(lenient_prefix(b""), (&b""[..], None));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = b"\x08\x01\x12\x05ab";
+        let (valid, warning) = lenient_prefix(data);
+        assert_eq!(valid, b"\x08\x01");
+        assert_eq!(
+            warning,
+            Some(LenientWarning { offset: 2, skipped: 4, error: WireError::Truncated })
+        );
+        assert_eq!(
+            warning.unwrap().to_string(),
+            "Skipped 4 bytes at offset 2: Input ends in the middle of a field"
+        );
+    }
+
+    #[test]
+    fn test_garbage() {
+        let (valid, warning) = lenient_prefix(b"\x08\x01\x00\x00\x00");
+        assert_eq!(valid, b"\x08\x01");
+        assert_eq!(warning.unwrap().error, WireError::InvalidFieldNumber);
+    }
+
+    #[test]
+    fn test_fields_after_garbage_are_dropped_too() {
+        let (valid, warning) = lenient_prefix(b"\x08\x01\x00\x00\x08\x02");
+        assert_eq!(valid, b"\x08\x01");
+        assert_eq!(warning.unwrap().skipped, 4);
+    }
+}
@@ -0,0 +1,301 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Size caps checked before parsing, for services that accept messages from
+//! untrusted clients.
+//!
+//! The kernels allocate as they parse, so a small input that declares a huge
+//! repeated field makes them allocate a lot before anything can reject it.
+//! Checking the serialized fields against a message's `FIELDS` table first
+//! rejects such messages before the kernel allocates anything for them; the
+//! check itself only allocates a count per repeated field.
+//!
+//! The check walks into submessages whose `FieldInfo` links to their type's
+//! `FIELDS` (see [`MessageType`](crate::MessageType)). Submessages of types
+//! declared in other `.proto` files, and map entries, aren't checked yet.
+
+use crate::field_info::{FieldInfo, FieldKind};
+use crate::wire::{WireError, WireReader, WireType, WireValue};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Caps on the sizes of fields, as checked by [`check_limits`]. Every cap is
+/// unlimited unless set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseLimits {
+    max_string_len: Option<usize>,
+    max_bytes_len: Option<usize>,
+    max_repeated_len: Option<usize>,
+}
+
+impl ParseLimits {
+    /// Constructs limits that allow everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caps the length of every `string` value, in bytes.
+    pub fn max_string_len(self, max: usize) -> Self {
+        Self { max_string_len: Some(max), ..self }
+    }
+
+    /// Caps the length of every `bytes` value.
+    pub fn max_bytes_len(self, max: usize) -> Self {
+        Self { max_bytes_len: Some(max), ..self }
+    }
+
+    /// Caps the number of elements of every repeated field, and of entries of
+    /// every map field.
+    pub fn max_repeated_len(self, max: usize) -> Self {
+        Self { max_repeated_len: Some(max), ..self }
+    }
+}
+
+/// A field that exceeds a [`ParseLimits`] cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    /// The input isn't valid wire format.
+    Wire(WireError),
+
+    /// A string or bytes value of field `field_number`, of the message or one
+    /// of its submessages, is `len` bytes long.
+    TooLong { field_number: u32, len: usize },
+
+    /// Repeated or map field `field_number`, of the message or one of its
+    /// submessages, has more elements than allowed.
+    TooManyElements { field_number: u32 },
+}
+
+impl LimitError {
+    /// Returns a stable code for the error, e.g. for metrics labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Wire(e) => e.code(),
+            Self::TooLong { .. } => "too_long",
+            Self::TooManyElements { .. } => "too_many_elements",
+        }
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Wire(e) => write!(f, "{e}"),
+            Self::TooLong { field_number, len } => {
+                write!(f, "Field {field_number} has a value of {len} bytes, over the limit")
+            }
+            Self::TooManyElements { field_number } => {
+                write!(f, "Field {field_number} has more elements than the limit")
+            }
+        }
+    }
+}
+
+impl From<WireError> for LimitError {
+    fn from(e: WireError) -> Self {
+        Self::Wire(e)
+    }
+}
+
+/// How deeply `check_limits` walks into submessages, as deep as the kernels
+/// parse.
+const MAX_DEPTH: usize = 100;
+
+/// Checks the fields of the serialized message `data`, as described by
+/// `fields` (e.g. `<Message>::FIELDS`), against `limits`, along with the
+/// fields of its submessages.
+///
+/// Fields that `fields` doesn't declare are not checked, and neither are
+/// submessages whose type `check_limits` can't look up (see the module docs).
+/// Messages nested more than 100 deep fail with `WireError::RecursionLimit`,
+/// as they would in the kernels.
+pub fn check_limits(
+    data: &[u8],
+    fields: &[FieldInfo],
+    limits: &ParseLimits,
+) -> Result<(), LimitError> {
+    check_message(data, fields, limits, 0)
+}
+
+fn check_message(
+    data: &[u8],
+    fields: &[FieldInfo],
+    limits: &ParseLimits,
+    depth: usize,
+) -> Result<(), LimitError> {
+    if depth == MAX_DEPTH {
+        return Err(WireError::RecursionLimit.into());
+    }
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for field in WireReader::new(data) {
+        let (field_number, value) = field?;
+        let Some(info) = fields.iter().find(|f| f.number == field_number) else {
+            continue;
+        };
+        if let WireValue::Delimited(bytes) = value {
+            let max_len = match info.kind {
+                FieldKind::String => limits.max_string_len,
+                FieldKind::Bytes => limits.max_bytes_len,
+                _ => None,
+            };
+            if max_len.is_some_and(|max| bytes.len() > max) {
+                return Err(LimitError::TooLong { field_number, len: bytes.len() });
+            }
+        }
+        if let (true, Some(max)) = (info.is_repeated, limits.max_repeated_len) {
+            let count = counts.entry(field_number).or_insert(0);
+            *count += element_count(info.kind, &value);
+            if *count > max {
+                return Err(LimitError::TooManyElements { field_number });
+            }
+        }
+        let Some(sub_fields) = info.message_type.and_then(|t| t.fields) else {
+            continue;
+        };
+        if let WireValue::Delimited(sub) | WireValue::Group(sub) = value {
+            check_message(sub, sub_fields(), limits, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns how many elements of a repeated field of kind `kind` `value`
+/// holds: one, unless it's packed.
+fn element_count(kind: FieldKind, value: &WireValue) -> usize {
+    match (kind.wire_type(), value) {
+        (WireType::Varint, WireValue::Delimited(packed)) => {
+            packed.iter().filter(|&&b| b & 0x80 == 0).count()
+        }
+        (WireType::Fixed32, WireValue::Delimited(packed)) => packed.len() / 4,
+        (WireType::Fixed64, WireValue::Delimited(packed)) => packed.len() / 8,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field_info::MessageType;
+    use crate::wire::WireWriter;
+
+    const FIELDS: &[FieldInfo] = &[
+        FieldInfo {
+            number: 1,
+            name: "name",
+            kind: FieldKind::String,
+            is_repeated: false,
+            is_packed: false,
+            has_presence: true,
+            is_closed_enum: false,
+            validates_utf8: true,
+            message_type: None,
+        },
+        FieldInfo {
+            number: 2,
+            name: "data",
+            kind: FieldKind::Bytes,
+            is_repeated: false,
+            is_packed: false,
+            has_presence: true,
+            is_closed_enum: false,
+            validates_utf8: false,
+            message_type: None,
+        },
+        FieldInfo {
+            number: 3,
+            name: "ids",
+            kind: FieldKind::Int64,
+            is_repeated: true,
+            is_packed: true,
+            has_presence: false,
+            is_closed_enum: false,
+            validates_utf8: false,
+            message_type: None,
+        },
+        FieldInfo {
+            number: 4,
+            name: "tags",
+            kind: FieldKind::String,
+            is_repeated: true,
+            is_packed: false,
+            has_presence: false,
+            is_closed_enum: false,
+            validates_utf8: true,
+            message_type: None,
+        },
+        FieldInfo {
+            number: 5,
+            name: "child",
+            kind: FieldKind::Message,
+            is_repeated: false,
+            is_packed: false,
+            has_presence: true,
+            is_closed_enum: false,
+            validates_utf8: false,
+            message_type: Some(MessageType { full_name: "pkg.Msg", fields: Some(|| FIELDS) }),
+        },
+    ];
+
+    #[test]
+    fn test_lengths() {
+        // name: "abc", data: "abcd"
+        let data = b"\x0a\x03abc\x12\x04abcd";
+        assert_eq!(check_limits(data, FIELDS, &ParseLimits::new()), Ok(()));
+        let limits = ParseLimits::new().max_string_len(3).max_bytes_len(4);
+        assert_eq!(check_limits(data, FIELDS, &limits), Ok(()));
+        assert_eq!(
+            check_limits(data, FIELDS, &limits.max_string_len(2)),
+            Err(LimitError::TooLong { field_number: 1, len: 3 })
+        );
+        assert_eq!(
+            check_limits(data, FIELDS, &limits.max_bytes_len(3)),
+            Err(LimitError::TooLong { field_number: 2, len: 4 })
+        );
+    }
+
+    #[test]
+    fn test_repeated() {
+        // ids: [1, 300] (packed), ids: 5, tags: "a", tags: "b", 9: "undeclared"
+        let data = b"\x1a\x03\x01\xac\x02\x18\x05\x22\x01a\x22\x01b\x4a\x01x";
+        let limits = ParseLimits::new().max_repeated_len(3);
+        assert_eq!(check_limits(data, FIELDS, &limits), Ok(()));
+        assert_eq!(
+            check_limits(data, FIELDS, &limits.max_repeated_len(2)),
+            Err(LimitError::TooManyElements { field_number: 3 })
+        );
+        assert_eq!(check_limits(b"\x18", FIELDS, &limits), Err(WireError::Truncated.into()));
+    }
+
+    #[test]
+    fn test_submessages() {
+        // child: { child: { name: "abc" } }
+        let data = b"\x2a\x07\x2a\x05\x0a\x03abc";
+        let limits = ParseLimits::new().max_string_len(3);
+        assert_eq!(check_limits(data, FIELDS, &limits), Ok(()));
+        assert_eq!(
+            check_limits(data, FIELDS, &limits.max_string_len(2)),
+            Err(LimitError::TooLong { field_number: 1, len: 3 })
+        );
+        assert_eq!(
+            check_limits(b"\x2a\x02\x0a\x05", FIELDS, &limits),
+            Err(WireError::Truncated.into())
+        );
+
+        let nested = |depth| {
+            (0..depth).fold(Vec::new(), |child, _| {
+                let mut w = WireWriter::new();
+                w.write_field(5, WireValue::Delimited(&child));
+                w.into_vec()
+            })
+        };
+        assert_eq!(check_limits(&nested(99), FIELDS, &limits), Ok(()));
+        assert_eq!(
+            check_limits(&nested(100), FIELDS, &limits),
+            Err(WireError::RecursionLimit.into())
+        );
+    }
+}
@@ -0,0 +1,186 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Reading and writing the legacy MessageSet wire format.
+//!
+//! Messages with `option message_set_wire_format = true` store their
+//! extensions as repeated `Item` groups instead of as regular fields:
+//!
+//! ```protobuf
+//! repeated group Item = 1 {
+//!   required int32 type_id = 2;
+//!   required bytes message = 3;
+//! }
+//! ```
+//!
+//! Both kernels already parse and serialize such messages. These helpers are
+//! for handling the items of a MessageSet payload without its message type,
+//! e.g. in storage systems that pass them through.
+
+use crate::wire::{check_field_number, write_varint, WireError, WireReader, WireType, WireValue};
+use std::fmt;
+
+const ITEM: u32 = 1;
+const TYPE_ID: u32 = 2;
+const MESSAGE: u32 = 3;
+
+/// An error that happened while reading a MessageSet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageSetError {
+    /// The input isn't valid wire format.
+    Wire(WireError),
+
+    /// An item doesn't have a type ID or a message.
+    IncompleteItem,
+
+    /// An item's type ID isn't a valid field number.
+    InvalidTypeId,
+}
+
+impl MessageSetError {
+    /// Returns a stable code for the error, e.g. for metrics labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Wire(e) => e.code(),
+            Self::IncompleteItem => "incomplete_item",
+            Self::InvalidTypeId => "invalid_type_id",
+        }
+    }
+}
+
+impl fmt::Display for MessageSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Wire(e) => write!(f, "{e}"),
+            Self::IncompleteItem => write!(f, "MessageSet item is missing its type ID or message"),
+            Self::InvalidTypeId => write!(f, "MessageSet item has an invalid type ID"),
+        }
+    }
+}
+
+impl From<WireError> for MessageSetError {
+    fn from(e: WireError) -> Self {
+        Self::Wire(e)
+    }
+}
+
+/// Iterates over the `(type_id, message)` items of a serialized MessageSet,
+/// in wire order. Fields other than items are skipped.
+///
+/// Stops after the first error.
+#[derive(Debug, Clone)]
+pub struct MessageSetItems<'a> {
+    reader: WireReader<'a>,
+}
+
+impl<'a> MessageSetItems<'a> {
+    /// Reads the items of the serialized MessageSet `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { reader: WireReader::new(data) }
+    }
+}
+
+impl<'a> Iterator for MessageSetItems<'a> {
+    type Item = Result<(u32, &'a [u8]), MessageSetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for field in &mut self.reader {
+            match field {
+                Ok((ITEM, WireValue::Group(item))) => {
+                    let item = read_item(item);
+                    if item.is_err() {
+                        self.reader = WireReader::new(&[]);
+                    }
+                    return Some(item);
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        None
+    }
+}
+
+fn read_item(item: &[u8]) -> Result<(u32, &[u8]), MessageSetError> {
+    let mut type_id = None;
+    let mut message = None;
+    for field in WireReader::new(item) {
+        match field? {
+            (TYPE_ID, WireValue::Varint(v)) => {
+                let v = u32::try_from(v).map_err(|_| MessageSetError::InvalidTypeId)?;
+                check_field_number(v).map_err(|_| MessageSetError::InvalidTypeId)?;
+                type_id = Some(v);
+            }
+            (MESSAGE, WireValue::Delimited(m)) => message = Some(m),
+            _ => {}
+        }
+    }
+    match (type_id, message) {
+        (Some(type_id), Some(message)) => Ok((type_id, message)),
+        _ => Err(MessageSetError::IncompleteItem),
+    }
+}
+
+/// Appends a MessageSet item holding the serialized extension `message` with
+/// field number `type_id` to `out`.
+pub fn write_message_set_item(out: &mut Vec<u8>, type_id: u32, message: &[u8]) {
+    let tag = |field: u32, wire_type: WireType| u64::from(field << 3 | u32::from(wire_type.bits()));
+    write_varint(out, tag(ITEM, WireType::StartGroup));
+    write_varint(out, tag(TYPE_ID, WireType::Varint));
+    write_varint(out, u64::from(type_id));
+    write_varint(out, tag(MESSAGE, WireType::Delimited));
+    write_varint(out, message.len() as u64);
+    out.extend_from_slice(message);
+    write_varint(out, tag(ITEM, WireType::EndGroup));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut data = Vec::new();
+        write_message_set_item(&mut data, 1000, b"\x08\x01");
+        write_message_set_item(&mut data, 7, b"");
+        assert_eq!(data, b"\x0b\x10\xe8\x07\x1a\x02\x08\x01\x0c\x0b\x10\x07\x1a\x00\x0c");
+
+        let items: Vec<_> = MessageSetItems::new(&data).map(Result::unwrap).collect();
+        assert_eq!(items, [(1000, &b"\x08\x01"[..]), (7, &b""[..])]);
+    }
+
+    #[test]
+    fn test_field_order() {
+        // The message may come before the type ID, and other fields are skipped.
+        let data = b"\x10\x05\x0b\x1a\x01x\x10\x03\x0c";
+        assert_eq!(MessageSetItems::new(data).collect::<Vec<_>>(), [Ok((3, &b"x"[..]))]);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut items = MessageSetItems::new(b"\x0b\x10\x03\x0c\x0b\x10\x04\x1a\x00\x0c");
+        assert_eq!(items.next(), Some(Err(MessageSetError::IncompleteItem)));
+        assert_eq!(items.next(), None);
+
+        assert_eq!(
+            MessageSetItems::new(b"\x0b\x10").next(),
+            Some(Err(MessageSetError::Wire(WireError::Truncated)))
+        );
+        // Type IDs are field numbers, so 0 and 2^32 are invalid.
+        let mut items = MessageSetItems::new(b"\x0b\x10\x00\x1a\x00\x0c");
+        assert_eq!(items.next(), Some(Err(MessageSetError::InvalidTypeId)));
+        assert_eq!(items.next(), None);
+        assert_eq!(
+            MessageSetItems::new(b"\x0b\x10\x80\x80\x80\x80\x10\x1a\x00\x0c").next(),
+            Some(Err(MessageSetError::InvalidTypeId))
+        );
+        assert_eq!(
+            MessageSetError::IncompleteItem.to_string(),
+            "MessageSet item is missing its type ID or message"
+        );
+    }
+}
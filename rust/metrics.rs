@@ -0,0 +1,121 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Reporting parse and serialize metrics per message type.
+//!
+//! Until a hook is registered, the only cost to generated code is one relaxed
+//! atomic load per call.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Which operation a [`MessageMetrics`] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageOp {
+    Parse,
+    Serialize,
+}
+
+/// Describes one call to a generated `deserialize()` or `serialize()`.
+///
+/// Generated methods that only serialize or parse as an implementation detail
+/// (e.g. `debug_string()` or `truncate_to_size()`) aren't recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageMetrics {
+    pub op: MessageOp,
+
+    /// The full name of the message type, e.g. `"my.pkg.MyMessage"`.
+    pub full_name: &'static str,
+
+    /// The size of the serialized message.
+    pub bytes: usize,
+
+    /// How long the call took.
+    pub duration: Duration,
+}
+
+// Shared so that it can be called after the lock is released: a hook that
+// serializes or parses any message records metrics itself.
+type Hook = Arc<dyn Fn(&MessageMetrics) + Send + Sync>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+static HOOK_SET: AtomicBool = AtomicBool::new(false);
+
+/// Registers `hook` to be called after every generated `deserialize()` and
+/// `serialize()` call, replacing any previously registered hook.
+///
+/// The hook runs on the calling thread, so it should be cheap (e.g. record
+/// into a histogram).
+pub fn set_message_metrics_hook(hook: impl Fn(&MessageMetrics) + Send + Sync + 'static) {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(hook));
+    HOOK_SET.store(true, Ordering::Relaxed);
+}
+
+/// Unregisters the hook set by [`set_message_metrics_hook`], if any.
+pub fn clear_message_metrics_hook() {
+    HOOK_SET.store(false, Ordering::Relaxed);
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Called by generated code around the body of `deserialize()` and
+/// `serialize()`. `bytes` computes the serialized size from `op`'s result.
+pub fn record_message_op<T>(
+    op: MessageOp,
+    full_name: &'static str,
+    f: impl FnOnce() -> T,
+    bytes: impl FnOnce(&T) -> usize,
+) -> T {
+    if !HOOK_SET.load(Ordering::Relaxed) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let duration = start.elapsed();
+    let hook = HOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(hook) = hook {
+        hook(&MessageMetrics { op, full_name, bytes: bytes(&result), duration });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    static RECORDED: Mutex<Vec<(MessageOp, &'static str, usize)>> = Mutex::new(Vec::new());
+
+    #[test]
+    fn test_record() {
+        assert_eq!(record_message_op(MessageOp::Parse, "pkg.Msg", || 1, |_| 5), 1);
+
+        set_message_metrics_hook(|m| RECORDED.lock().unwrap().push((m.op, m.full_name, m.bytes)));
+        let data = vec![0; 3];
+        assert_eq!(
+            record_message_op(MessageOp::Serialize, "pkg.Msg", || data, |d| d.len()),
+            [0; 3]
+        );
+        clear_message_metrics_hook();
+        record_message_op(MessageOp::Parse, "pkg.Msg", || (), |_| 5);
+
+        // The hook can record metrics of its own, even while another thread
+        // is waiting to replace it.
+        set_message_metrics_hook(|m| {
+            if m.full_name == "pkg.Outer" {
+                let writer = std::thread::spawn(|| set_message_metrics_hook(|_| {}));
+                std::thread::sleep(Duration::from_millis(10));
+                record_message_op(MessageOp::Serialize, "pkg.Inner", || (), |_| 0);
+                writer.join().unwrap();
+            }
+        });
+        record_message_op(MessageOp::Parse, "pkg.Outer", || (), |_| 5);
+        clear_message_metrics_hook();
+
+        assert_eq!(*RECORDED.lock().unwrap(), [(MessageOp::Serialize, "pkg.Msg", 3)]);
+    }
+}
@@ -9,6 +9,7 @@ use crate::__internal::Private;
 use crate::__runtime::InnerPrimitiveMut;
 use crate::vtable::{PrimitiveVTable, ProxiedWithRawVTable};
 use crate::{Mut, MutProxy, Proxied, SettableValue, View, ViewProxy};
+use std::fmt;
 
 #[derive(Debug)]
 pub struct PrimitiveMut<'a, T: ProxiedWithRawVTable> {
@@ -24,6 +25,24 @@ impl<'a, T: ProxiedWithRawVTable> PrimitiveMut<'a, T> {
 
 unsafe impl<'a, T: ProxiedWithRawVTable> Sync for PrimitiveMut<'a, T> {}
 
+/// An error returned by a generated `<field>_try_set()` when the value
+/// doesn't fit in the field's type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl OutOfRange {
+    /// Returns the error's stable code, `"out_of_range"`.
+    pub fn code(&self) -> &'static str {
+        "out_of_range"
+    }
+}
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Value is out of range for the field's type")
+    }
+}
+
 macro_rules! impl_singular_primitives {
   ($($t:ty),*) => {
       $(
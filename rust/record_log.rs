@@ -0,0 +1,398 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! An append-only log format for recording and replaying message streams.
+//!
+//! A log starts with an 8-byte magic and is followed by blocks. Each block has
+//! a 9-byte header (one byte of flags, then the stored payload length and the
+//! CRC-32 of the stored payload, both as little-endian `u32`s). The payload is
+//! a sequence of varint length-delimited records, optionally compressed as a
+//! whole by a [`Codec`].
+//!
+//! Records are opaque bytes: write `<Message>::serialize()` and replay with
+//! `<Message>::deserialize()`.
+
+use crate::wire::{write_varint, WireReader};
+use crate::Codec;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// The magic every log starts with. The last byte is the format version.
+const MAGIC: &[u8; 8] = b"pbrlog\x00\x01";
+
+/// The length of the flags, length and checksum prefix of every block.
+const BLOCK_HEADER_LEN: usize = 9;
+
+/// The block flag marking a compressed payload.
+const BLOCK_FLAG_COMPRESSED: u8 = 0x01;
+
+/// The default uncompressed block size, see [`RecordWriter::with_block_size`].
+const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Writes records to a log, buffering them into blocks.
+///
+/// Call [`RecordWriter::flush`] to make sure everything written so far is
+/// durable, and [`RecordWriter::finish`] when done. Pending records are lost
+/// if the writer is dropped without either.
+pub struct RecordWriter<W: Write> {
+    inner: W,
+    block: Vec<u8>,
+    block_size: usize,
+    codec: Option<Box<dyn Codec>>,
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// Starts a new log by writing the magic to `inner`.
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(MAGIC)?;
+        Ok(Self { inner, block: Vec::new(), block_size: DEFAULT_BLOCK_SIZE, codec: None })
+    }
+
+    /// Sets how many bytes of records are buffered before a block is written.
+    ///
+    /// Records are never split across blocks, so a record larger than this
+    /// gets a block of its own.
+    pub fn with_block_size(self, block_size: usize) -> Self {
+        Self { block_size, ..self }
+    }
+
+    /// Compresses every block with `codec`.
+    ///
+    /// The log doesn't record which codec wrote it, so the reader has to be
+    /// given the same codec.
+    pub fn with_codec(self, codec: Box<dyn Codec>) -> Self {
+        Self { codec: Some(codec), ..self }
+    }
+
+    /// Appends a record to the log.
+    pub fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        write_varint(&mut self.block, record.len() as u64);
+        self.block.extend_from_slice(record);
+        if self.block.len() >= self.block_size { self.write_block() } else { Ok(()) }
+    }
+
+    /// Writes out the pending block, if any, and flushes the underlying
+    /// writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+
+    /// Flushes the log and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let mut compressed = Vec::new();
+        let (flags, payload) = match &self.codec {
+            Some(codec) => {
+                codec.compress(&self.block, &mut compressed);
+                (BLOCK_FLAG_COMPRESSED, &compressed[..])
+            }
+            None => (0, &self.block[..]),
+        };
+        let len = u32::try_from(payload.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "record log block is too large")
+        })?;
+        let mut header = [0; BLOCK_HEADER_LEN];
+        header[0] = flags;
+        header[1..5].copy_from_slice(&len.to_le_bytes());
+        header[5..9].copy_from_slice(&crc32(payload).to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(payload)?;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> fmt::Debug for RecordWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecordWriter")
+            .field("pending", &self.block.len())
+            .field("block_size", &self.block_size)
+            .field("compressed", &self.codec.is_some())
+            .finish()
+    }
+}
+
+/// Reads the records of a log back in the order they were written.
+///
+/// Every block's checksum is verified before any of its records are
+/// returned. Iteration stops after the first error.
+pub struct RecordReader<R: Read> {
+    inner: R,
+    codec: Option<Box<dyn Codec>>,
+    block: Vec<u8>,
+    records: VecDeque<(usize, usize)>,
+    done: bool,
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Opens a log by reading and checking its magic.
+    pub fn new(mut inner: R) -> Result<Self, RecordLogError> {
+        let mut magic = [0; MAGIC.len()];
+        if read_full(&mut inner, &mut magic)? != magic.len() || &magic != MAGIC {
+            return Err(RecordLogError::BadMagic);
+        }
+        Ok(Self { inner, codec: None, block: Vec::new(), records: VecDeque::new(), done: false })
+    }
+
+    /// Decompresses compressed blocks with `codec`.
+    pub fn with_codec(self, codec: Box<dyn Codec>) -> Self {
+        Self { codec: Some(codec), ..self }
+    }
+
+    /// Reads the next block into `self.block`, returning `false` at a clean
+    /// end of the log.
+    fn read_block(&mut self) -> Result<bool, RecordLogError> {
+        let mut header = [0; BLOCK_HEADER_LEN];
+        match read_full(&mut self.inner, &mut header)? {
+            0 => return Ok(false),
+            BLOCK_HEADER_LEN => {}
+            _ => return Err(RecordLogError::Truncated),
+        }
+        let flags = header[0];
+        let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let crc = u32::from_le_bytes([header[5], header[6], header[7], header[8]]);
+        if flags & !BLOCK_FLAG_COMPRESSED != 0 {
+            return Err(RecordLogError::Corrupt);
+        }
+
+        let mut payload = Vec::new();
+        if (&mut self.inner).take(len as u64).read_to_end(&mut payload)? != len {
+            return Err(RecordLogError::Truncated);
+        }
+        if crc32(&payload) != crc {
+            return Err(RecordLogError::Corrupt);
+        }
+        if flags & BLOCK_FLAG_COMPRESSED != 0 {
+            let codec = self.codec.as_ref().ok_or(RecordLogError::MissingCodec)?;
+            self.block.clear();
+            codec.decompress(&payload, &mut self.block).map_err(|_| RecordLogError::Decompress)?;
+        } else {
+            self.block = payload;
+        }
+
+        let mut reader = WireReader::new(&self.block);
+        while !reader.is_empty() {
+            let record = reader.read_delimited().map_err(|_| RecordLogError::Corrupt)?;
+            let end = self.block.len() - reader.remaining().len();
+            self.records.push_back((end - record.len(), end));
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for RecordReader<R> {
+    type Item = Result<Vec<u8>, RecordLogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.records.is_empty() {
+            if self.done {
+                return None;
+            }
+            match self.read_block() {
+                Ok(true) => {}
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        let (start, end) = self.records.pop_front()?;
+        Some(Ok(self.block[start..end].to_vec()))
+    }
+}
+
+impl<R: Read> fmt::Debug for RecordReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecordReader")
+            .field("buffered", &self.records.len())
+            .field("compressed", &self.codec.is_some())
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+/// An error that happened while reading a record log.
+#[derive(Debug)]
+pub enum RecordLogError {
+    /// The underlying reader failed.
+    Io(io::Error),
+
+    /// The input doesn't start with the record log magic.
+    BadMagic,
+
+    /// The log ends in the middle of a block, for example because the writer
+    /// crashed. All records before the truncated block were returned.
+    Truncated,
+
+    /// A block's checksum doesn't match, or its contents are malformed.
+    Corrupt,
+
+    /// A block is compressed, but the reader has no codec.
+    MissingCodec,
+
+    /// The codec failed to decompress a block.
+    Decompress,
+}
+
+impl From<io::Error> for RecordLogError {
+    fn from(e: io::Error) -> Self {
+        RecordLogError::Io(e)
+    }
+}
+
+impl RecordLogError {
+    /// Returns a stable code for the error, e.g. for metrics labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RecordLogError::Io(_) => "io",
+            RecordLogError::BadMagic => "bad_magic",
+            RecordLogError::Truncated => "truncated",
+            RecordLogError::Corrupt => "corrupt",
+            RecordLogError::MissingCodec => "missing_codec",
+            RecordLogError::Decompress => "decompress",
+        }
+    }
+}
+
+impl fmt::Display for RecordLogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordLogError::Io(e) => write!(f, "Couldn't read record log: {e}"),
+            RecordLogError::BadMagic => write!(f, "Input is not a record log"),
+            RecordLogError::Truncated => write!(f, "Record log ends in the middle of a block"),
+            RecordLogError::Corrupt => write!(f, "Record log block is corrupt"),
+            RecordLogError::MissingCodec => {
+                write!(f, "Record log block is compressed, but no codec was given")
+            }
+            RecordLogError::Decompress => write!(f, "Couldn't decompress record log block"),
+        }
+    }
+}
+
+/// Reads into `buf` until it is full or the reader is exhausted, returning
+/// the number of bytes read.
+fn read_full(r: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// The CRC-32 (IEEE) lookup table.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |c, &b| CRC32_TABLE[((c ^ u32::from(b)) & 0xff) as usize] ^ (c >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::tests::Rle;
+
+    fn records() -> Vec<Vec<u8>> {
+        vec![b"\x08\x01".to_vec(), vec![], vec![0; 300], b"\x12\x03abc".to_vec()]
+    }
+
+    fn write_log(writer: RecordWriter<Vec<u8>>) -> Vec<u8> {
+        let mut writer = writer;
+        for record in records() {
+            writer.write_record(&record).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for block_size in [1, 16, DEFAULT_BLOCK_SIZE] {
+            let log = write_log(RecordWriter::new(Vec::new()).unwrap().with_block_size(block_size));
+            let read: Vec<_> =
+                RecordReader::new(&log[..]).unwrap().collect::<Result<_, _>>().unwrap();
+            assert_eq!(read, records());
+        }
+    }
+
+    #[test]
+    fn test_empty_log() {
+        let log = RecordWriter::new(Vec::new()).unwrap().finish().unwrap();
+        assert_eq!(log, MAGIC);
+        assert_eq!(RecordReader::new(&log[..]).unwrap().count(), 0);
+        assert!(matches!(RecordReader::new(&b"pbrlog"[..]), Err(RecordLogError::BadMagic)));
+    }
+
+    #[test]
+    fn test_compressed_roundtrip() {
+        let log = write_log(RecordWriter::new(Vec::new()).unwrap().with_codec(Box::new(Rle)));
+        assert!(log.len() < MAGIC.len() + BLOCK_HEADER_LEN + 300);
+        let read: Vec<_> = RecordReader::new(&log[..])
+            .unwrap()
+            .with_codec(Box::new(Rle))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, records());
+
+        let mut reader = RecordReader::new(&log[..]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(RecordLogError::MissingCodec))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let log = write_log(RecordWriter::new(Vec::new()).unwrap().with_block_size(1));
+        let mut reader = RecordReader::new(&log[..log.len() - 1]).unwrap();
+        for record in &records()[..3] {
+            assert_eq!(reader.next().unwrap().unwrap(), *record);
+        }
+        assert!(matches!(reader.next(), Some(Err(RecordLogError::Truncated))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let mut log = write_log(RecordWriter::new(Vec::new()).unwrap());
+        *log.last_mut().unwrap() ^= 1;
+        let mut reader = RecordReader::new(&log[..]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(RecordLogError::Corrupt))));
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,184 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Reading legacy data whose fields were renumbered.
+//!
+//! Renumbering a field breaks wire compatibility, but some data written with
+//! the old numbers can't be rewritten. Rewriting the old numbers to the new
+//! ones before parsing lets the current schema read it.
+
+use crate::wire::{
+    check_field_number, write_varint, WireError, WirePathError, WireReader, WireType, WireValue,
+};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Field number aliases for a message type and, optionally, for the types of
+/// its submessage fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldAliases {
+    aliases: BTreeMap<u32, u32>,
+    nested: BTreeMap<u32, FieldAliases>,
+}
+
+impl FieldAliases {
+    /// Constructs an empty set of aliases, which leaves every field as-is.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads field `old_number` as field `new_number`.
+    ///
+    /// # Panics
+    /// Panics if either field number is invalid.
+    pub fn alias(self, old_number: u32, new_number: u32) -> Self {
+        self.try_alias(old_number, new_number).expect("invalid field number")
+    }
+
+    /// Like `alias()`, but returns `WireError::InvalidFieldNumber` instead of
+    /// panicking if either field number is invalid.
+    pub fn try_alias(mut self, old_number: u32, new_number: u32) -> Result<Self, WireError> {
+        check_field_number(old_number)?;
+        check_field_number(new_number)?;
+        self.aliases.insert(old_number, new_number);
+        Ok(self)
+    }
+
+    /// Applies `nested` to the submessage (or group) field `field_number`.
+    /// `field_number` is the field's number after aliasing.
+    pub fn nested(mut self, field_number: u32, nested: FieldAliases) -> Self {
+        self.nested.insert(field_number, nested);
+        self
+    }
+
+    /// Returns `data` with aliased field numbers rewritten, ready to be passed
+    /// to `<Message>::deserialize()`. It is copied into a new buffer, unless no
+    /// field of `data` is aliased, in which case `data` itself is returned.
+    ///
+    /// Fails if `data`, or a submessage that has nested aliases, is
+    /// malformed. The error's path leads to the malformed submessage.
+    pub fn renumber<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>, WirePathError> {
+        // Only allocated once a field is rewritten, starting with a copy of
+        // the fields before it.
+        let mut out: Option<Vec<u8>> = None;
+        let mut reader = WireReader::new(data);
+        while !reader.is_empty() {
+            let start = reader.remaining();
+            let offset = data.len() - start.len();
+            let (old_number, wire_type) = reader.read_tag()?;
+            let tag_len = start.len() - reader.remaining().len();
+            let value = reader.read_value(old_number, wire_type)?;
+            let field = &start[..start.len() - reader.remaining().len()];
+
+            let field_number = self.aliases.get(&old_number).copied().unwrap_or(old_number);
+            let tag = |out: &mut Vec<u8>, wire_type: WireType| {
+                write_varint(out, u64::from(field_number) << 3 | u64::from(wire_type.bits()))
+            };
+            // The new contents of submessages and groups, if they change.
+            let renumbered = match (self.nested.get(&field_number), value) {
+                (Some(nested), WireValue::Delimited(sub) | WireValue::Group(sub)) => {
+                    match nested.renumber(sub).map_err(|e| e.in_field(field_number))? {
+                        Cow::Borrowed(_) => None,
+                        Cow::Owned(renumbered) => Some(renumbered),
+                    }
+                }
+                _ => None,
+            };
+            // What to write instead of `field`, or `None` to keep it as it is.
+            let replacement = match (renumbered, value) {
+                (Some(renumbered), WireValue::Delimited(_)) => {
+                    let mut field = Vec::new();
+                    tag(&mut field, wire_type);
+                    write_varint(&mut field, renumbered.len() as u64);
+                    field.extend_from_slice(&renumbered);
+                    Some(field)
+                }
+                (renumbered, WireValue::Group(sub))
+                    if renumbered.is_some() || field_number != old_number =>
+                {
+                    let mut field = Vec::new();
+                    tag(&mut field, wire_type);
+                    field.extend_from_slice(renumbered.as_deref().unwrap_or(sub));
+                    tag(&mut field, WireType::EndGroup);
+                    Some(field)
+                }
+                _ if field_number != old_number => {
+                    let mut replacement = Vec::new();
+                    tag(&mut replacement, wire_type);
+                    replacement.extend_from_slice(&field[tag_len..]);
+                    Some(replacement)
+                }
+                _ => None,
+            };
+            match (replacement, &mut out) {
+                (None, None) => {}
+                (None, Some(out)) => out.extend_from_slice(field),
+                (Some(replacement), out) => {
+                    let out = out.get_or_insert_with(|| {
+                        let mut out = Vec::with_capacity(data.len());
+                        out.extend_from_slice(&data[..offset]);
+                        out
+                    });
+                    out.extend_from_slice(&replacement);
+                }
+            }
+        }
+        Ok(out.map_or(Cow::Borrowed(data), Cow::Owned))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::WireError;
+
+    #[test]
+    fn test_alias() {
+        // 1: 1, 2: "ab", 17: 3
+        let data = b"\x08\x01\x12\x02ab\x88\x01\x03";
+        assert!(
+            matches!(FieldAliases::new().renumber(data).unwrap(), Cow::Borrowed(d) if d == data)
+        );
+        assert!(matches!(
+            FieldAliases::new().alias(3, 4).renumber(data).unwrap(),
+            Cow::Borrowed(d) if d == data
+        ));
+        assert_eq!(
+            &*FieldAliases::new().alias(17, 3).alias(2, 20).renumber(data).unwrap(),
+            b"\x08\x01\xa2\x01\x02ab\x18\x03"
+        );
+        assert!(FieldAliases::new().try_alias(17, 3).is_ok());
+        assert_eq!(FieldAliases::new().try_alias(0, 3), Err(WireError::InvalidFieldNumber));
+        assert_eq!(FieldAliases::new().try_alias(17, 1 << 29), Err(WireError::InvalidFieldNumber));
+    }
+
+    #[test]
+    fn test_nested() {
+        // 1: { 1: 1 }, 2: group { 1: 2 }, 3: group { 1: 3 }
+        let data = b"\x0a\x02\x08\x01\x13\x08\x02\x14\x1b\x08\x03\x1c";
+        let inner = FieldAliases::new().alias(1, 5);
+        let aliases =
+            FieldAliases::new().alias(3, 4).nested(1, inner.clone()).nested(2, inner.clone());
+        assert_eq!(
+            &*aliases.renumber(data).unwrap(),
+            b"\x0a\x02\x28\x01\x13\x28\x02\x14\x23\x08\x03\x24"
+        );
+
+        // Nested aliases apply to the new field number.
+        let aliases = FieldAliases::new().alias(3, 4).nested(4, inner);
+        assert_eq!(&*aliases.renumber(b"\x1b\x08\x03\x1c").unwrap(), b"\x23\x28\x03\x24");
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(FieldAliases::new().renumber(b"\x08"), Err(WireError::Truncated.into()));
+        assert_eq!(
+            FieldAliases::new().nested(1, FieldAliases::new()).renumber(b"\x0a\x01\xff"),
+            Err(WirePathError::from(WireError::Truncated).in_field(1))
+        );
+    }
+}
@@ -0,0 +1,177 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Reading and writing self-describing messages, which bundle a message with
+//! the descriptors needed to decode it:
+//!
+//! ```protobuf
+//! message SelfDescribingMessage {
+//!   google.protobuf.FileDescriptorSet descriptor_set = 1;
+//!   google.protobuf.Any message = 2;
+//! }
+//! ```
+//!
+//! Archived bundles stay decodable by schema-aware tools long after the code
+//! that wrote them is gone. The descriptor set is passed in serialized form,
+//! e.g. as written by `protoc --include_imports --descriptor_set_out`.
+
+use crate::wire::{write_varint, WireError, WireReader, WireType, WireValue};
+use std::fmt;
+
+const DESCRIPTOR_SET: u32 = 1;
+const MESSAGE: u32 = 2;
+const ANY_TYPE_URL: u32 = 1;
+const ANY_VALUE: u32 = 2;
+
+/// A message bundled with its descriptors.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfDescribingMessage {
+    /// A serialized `google.protobuf.FileDescriptorSet` that includes the
+    /// message's type and everything it depends on.
+    pub descriptor_set: Vec<u8>,
+
+    /// The message's type URL, e.g. `"type.googleapis.com/my.pkg.MyMessage"`.
+    pub type_url: String,
+
+    /// The serialized message, e.g. the output of `<Message>::serialize()`.
+    pub message: Vec<u8>,
+}
+
+/// An error that happened while reading a self-describing message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfDescribingError {
+    /// The input isn't valid wire format.
+    Wire(WireError),
+
+    /// The bundled message has no type URL, or it isn't UTF-8.
+    InvalidTypeUrl,
+}
+
+impl SelfDescribingError {
+    /// Returns a stable code for the error, e.g. for metrics labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Wire(e) => e.code(),
+            Self::InvalidTypeUrl => "invalid_type_url",
+        }
+    }
+}
+
+impl fmt::Display for SelfDescribingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Wire(e) => write!(f, "{e}"),
+            Self::InvalidTypeUrl => write!(f, "Bundled message has a missing or invalid type URL"),
+        }
+    }
+}
+
+impl From<WireError> for SelfDescribingError {
+    fn from(e: WireError) -> Self {
+        Self::Wire(e)
+    }
+}
+
+impl SelfDescribingMessage {
+    /// Serializes the bundle.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut any = Vec::new();
+        write_delimited(&mut any, ANY_TYPE_URL, self.type_url.as_bytes());
+        write_delimited(&mut any, ANY_VALUE, &self.message);
+
+        let mut out = Vec::new();
+        write_delimited(&mut out, DESCRIPTOR_SET, &self.descriptor_set);
+        write_delimited(&mut out, MESSAGE, &any);
+        out
+    }
+
+    /// Parses a serialized bundle.
+    ///
+    /// As for any message, repeated occurrences of the descriptor set are
+    /// merged, and the last type URL and message win.
+    pub fn parse(data: &[u8]) -> Result<Self, SelfDescribingError> {
+        let mut descriptor_set = Vec::new();
+        let mut type_url: &[u8] = &[];
+        let mut message: &[u8] = &[];
+        for field in WireReader::new(data) {
+            match field? {
+                (DESCRIPTOR_SET, WireValue::Delimited(set)) => {
+                    descriptor_set.extend_from_slice(set)
+                }
+                (MESSAGE, WireValue::Delimited(any)) => {
+                    for field in WireReader::new(any) {
+                        match field? {
+                            (ANY_TYPE_URL, WireValue::Delimited(url)) => type_url = url,
+                            (ANY_VALUE, WireValue::Delimited(value)) => message = value,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let type_url = match std::str::from_utf8(type_url) {
+            Ok(url) if !url.is_empty() => url.to_owned(),
+            _ => return Err(SelfDescribingError::InvalidTypeUrl),
+        };
+        Ok(Self { descriptor_set, type_url, message: message.to_vec() })
+    }
+}
+
+fn write_delimited(out: &mut Vec<u8>, field_number: u32, value: &[u8]) {
+    write_varint(out, u64::from(field_number << 3 | u32::from(WireType::Delimited.bits())));
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let bundle = SelfDescribingMessage {
+            descriptor_set: b"\x0a\x00".to_vec(),
+            type_url: "type.googleapis.com/pkg.M".to_owned(),
+            message: b"\x08\x01".to_vec(),
+        };
+        let data = bundle.serialize();
+        assert_eq!(
+            data,
+            b"\x0a\x02\x0a\x00\x12\x1f\x0a\x19type.googleapis.com/pkg.M\x12\x02\x08\x01"
+        );
+        assert_eq!(SelfDescribingMessage::parse(&data), Ok(bundle));
+    }
+
+    #[test]
+    fn test_merge() {
+        // descriptor_set: "a", message: { type_url: "x" }, descriptor_set: "b",
+        // message: { type_url: "y", value: "v" }
+        let data = b"\x0a\x01a\x12\x03\x0a\x01x\x0a\x01b\x12\x06\x0a\x01y\x12\x01v";
+        assert_eq!(
+            SelfDescribingMessage::parse(data),
+            Ok(SelfDescribingMessage {
+                descriptor_set: b"ab".to_vec(),
+                type_url: "y".to_owned(),
+                message: b"v".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(SelfDescribingMessage::parse(b""), Err(SelfDescribingError::InvalidTypeUrl));
+        assert_eq!(
+            SelfDescribingMessage::parse(b"\x12\x03\x0a\x01\xff"),
+            Err(SelfDescribingError::InvalidTypeUrl)
+        );
+        assert_eq!(
+            SelfDescribingMessage::parse(b"\x12\x03\x0a\x02"),
+            Err(SelfDescribingError::Wire(WireError::Truncated))
+        );
+    }
+}
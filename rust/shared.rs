@@ -9,6 +9,38 @@
 //!
 //! For kernel-specific logic this crate delegates to the respective `__runtime`
 //! crate.
+//!
+//! # Panics
+//! Malformed input never panics: parsing and the wire-level utilities return
+//! errors instead. What does panic is misuse that is a bug in the caller,
+//! such as an invalid field number passed to `WireWriter`. Those APIs say so
+//! in a `# Panics` section, and each has a `try_` variant (e.g.
+//! `WireWriter::try_write_field()`) that returns the error instead, for
+//! services whose field numbers come from configuration or requests and
+//! that must not abort on them.
+//!
+//! # Raw handles
+//! Code generated with `raw_handles=enabled` has `unsafe` conversions between
+//! messages and the kernel's pointers, for interop with existing C++ or upb
+//! code: `into_raw()` releases a message and `from_raw()` takes ownership of
+//! one. Whoever holds a released handle owns it, and must either pass it to
+//! `from_raw()` exactly once or free it in C++ (`delete`) or upb
+//! (`upb_Arena_Free` on its arena, which frees the message). `from_raw()` also
+//! requires that:
+//! - The handle points to a message of exactly the generated type: for the
+//!   C++ kernel a heap-allocated instance of its C++ class, not one owned by a
+//!   `proto2::Arena` or a parent message; for upb a message with that type's
+//!   `upb_MiniTable`, allocated on the arena passed alongside it.
+//! - The Rust value owns the handle (and for upb, the arena) exclusively:
+//!   nothing else may keep an alias to it and read, write or free through it
+//!   while the Rust value, or a view or mutator borrowed from it, is alive.
+//!   Generated messages are `Send` and `Sync`, so this has to hold across
+//!   threads too.
+//! - For upb, the arena isn't freed or fused with a shorter-lived arena
+//!   behind the Rust value's back.
+//!
+//! Repeated fields and maps will get conversions with the same rules once
+//! the runtime has types for them.
 #![deny(unsafe_op_in_unsafe_fn)]
 
 use std::fmt;
@@ -17,15 +49,158 @@ use std::fmt;
 /// These are the items protobuf users can access directly.
 #[doc(hidden)]
 pub mod __public {
+    pub use crate::__internal::{RawArena, RawMessage};
+    pub use crate::assert_message_api;
+    pub use crate::compression::{compress_serialized, decompress_serialized, Codec};
+    pub use crate::concat::{concat_serialized, split_fields, FieldChunk};
+    pub use crate::debug_string::{debug_string, DebugStringOptions};
+    pub use crate::delimited::{decode_delimited_parallel, DelimitedFrames, ParallelDecodeError};
+    pub use crate::deprecation::{clear_deprecated_field_hook, set_deprecated_field_hook};
+    pub use crate::descriptor_registry::{
+        register_file_descriptor, registered_file_descriptor, registered_file_descriptors,
+    };
+    pub use crate::envelope::{Envelope, EnvelopeError};
+    pub use crate::extensions::{ExtensionFields, ExtensionNumberAndValue};
+    pub use crate::extract::{ExtractError, FieldExtractor};
+    pub use crate::field_info::{
+        duplicate_fields, undeclared_fields, DuplicateField, FieldInfo, FieldKind, MessageType,
+        UndeclaredField,
+    };
+    pub use crate::filter::FieldFilter;
+    pub use crate::framing::{
+        encode_grpc_web_trailers, parse_grpc_web_trailers, FramingError, GrpcFrame, GrpcFrameKind,
+        GrpcFrames, GRPC_FRAME_HEADER_LEN,
+    };
+    pub use crate::hooks::{add_parse_hook, add_serialize_hook, clear_message_hooks};
+    pub use crate::lenient::{lenient_prefix, LenientWarning};
+    pub use crate::limits::{check_limits, LimitError, ParseLimits};
+    pub use crate::message_set::{write_message_set_item, MessageSetError, MessageSetItems};
+    pub use crate::metrics::{
+        clear_message_metrics_hook, set_message_metrics_hook, MessageMetrics, MessageOp,
+    };
     pub use crate::optional::{AbsentField, FieldEntry, Optional, PresentField};
-    pub use crate::primitive::PrimitiveMut;
+    pub use crate::packed::{
+        decode_packed_varints, decode_packed_zigzag, encode_packed_varints, encode_packed_zigzag,
+        unpack_serialized, zigzag_decode, zigzag_encode,
+    };
+    pub use crate::primitive::{OutOfRange, PrimitiveMut};
     pub use crate::proxied::{
         Mut, MutProxy, Proxied, ProxiedWithPresence, SettableValue, View, ViewProxy,
     };
+    pub use crate::record_log::{RecordLogError, RecordReader, RecordWriter};
+    pub use crate::renumber::FieldAliases;
+    pub use crate::self_describing::{SelfDescribingError, SelfDescribingMessage};
+    pub use crate::signing::{sign_detached, signing_payload, verify_detached, SignatureError};
+    pub use crate::size_report::{size_breakdown, FieldSize};
+    pub use crate::streaming::StreamingSerializer;
     pub use crate::string::{BytesMut, ProtoStr, ProtoStrMut};
+    pub use crate::truncate::{
+        truncate_serialized, DroppedField, TruncateError, Truncation, TruncationPolicy,
+    };
+    pub use crate::wire::{
+        check_field_number, match_tag, FieldNumber, WireError, WirePathError, WireReader, WireType,
+        WireValue, WireWriter, MAX_FIELD_NUMBER,
+    };
+    pub use crate::wire_corpus::{WireCorpusEntry, WIRE_CORPUS};
+
+    /// Only the upb kernel allocates messages on arenas.
+    #[cfg(upb_kernel)]
+    pub use crate::__runtime::ArenaStats;
 }
 pub use __public::*;
 
+// TODO: Rust Protobuf does not expose descriptors or reflection yet.
+// These features are blocked on it:
+// - Flattening a view into dotted-path/scalar-value pairs for analytics
+//   export. Field names and scalar kinds have to come from descriptors; the
+//   wire format alone cannot tell a submessage from a `bytes` field.
+// - Exporting repeated message fields to Arrow arrays column-by-column. This
+//   also needs repeated field support and an `arrow` dependency, which the
+//   runtime does not have.
+// - Extracting `FieldMask` paths from each message as CSV rows. Resolving a
+//   path like `a.b.c` and printing the scalar at it needs descriptors, and
+//   `FieldMask` itself needs well-known type support.
+// - Canonicalizing a message for cross-runtime digests (normalizing -0.0 and
+//   NaN payloads, stripping unknown fields, normalizing time types). Finding
+//   the floating point fields needs descriptors; the wire format can't tell a
+//   `double` from a `fixed64`. Map entry order is already covered by
+//   `<Message>::serialize_deterministic()`.
+// - Mapping a message descriptor to a SQL column schema and extracting row
+//   values from views. Column names, types and nullability all come from the
+//   descriptor.
+// - Diffing two views into the minimal `FieldMask` covering the changed
+//   fields. This needs to walk both messages' fields and the `FieldMask` well-
+//   known type.
+// - Checking presence along a path like `a.b.c` (`has_path(view, "a.b.c")`),
+//   for validating client-supplied `FieldMask` paths before applying them.
+//   Resolving each path segment to a field and reading its presence needs
+//   descriptors and reflective accessors.
+// - A descriptor pool that comes with the well-known types preloaded and
+//   resolves imports lazily through a callback from file name to serialized
+//   `FileDescriptorProto`, for schema registries that fetch dependencies on
+//   demand. There is no descriptor pool to extend yet; meanwhile,
+//   `<Message>::register_file_descriptor()` collects serialized files by name.
+// - Converting between descriptors and the `google.protobuf.Type` and
+//   `Field` well-known types, for API discovery endpoints. This needs both
+//   descriptor objects and gencode for the well-known types.
+// - A public `Value` enum (scalars, strings, bytes, enums, messages,
+//   repeated fields and maps) returned by reflective getters and accepted by
+//   reflective setters, as the single currency for field values in generic
+//   code.
+// - Reflective `get_repeated(field) -> RepeatedDyn` and `get_map(field) ->
+//   MapDyn` views with `len`, `get`, `iter`, `push` and `insert`. These also
+//   need the runtime's repeated field and map types, which don't exist yet.
+// - `FieldDescriptor::default_value()`. Until then, fields with an explicit
+//   default have a generated `<FIELD>_DEFAULT` constant instead.
+// - A message differencer with per-field comparison modes, e.g. treating
+//   a repeated field as a set, or as a map keyed by one of its subfields, to
+//   compare output of systems with nondeterministic list order. Telling
+//   which fields are repeated, and finding the key subfield, needs
+//   descriptors.
+// - `redact(msg, &FieldMask, ReplacementPolicy)`, clearing the masked fields
+//   or replacing their values (e.g. strings with "***") recursively. Paths
+//   are field names, and replacing a value depends on the field's type: a
+//   length-delimited value can be a string, bytes or a submessage. Until
+//   then, `FieldFilter` can drop fields by number.
+
+// TODO: Rust Protobuf does not support the proto3 JSON mapping yet.
+// These features are blocked on it:
+// - Reading and writing JSON Lines streams of messages. Once messages can be
+//   printed and parsed as JSON, this is one call per line.
+// - Oneof handling in the JSON (and text format) printers and parsers: only
+//   print the active case, reject input that sets several cases unless a
+//   tolerant last-wins option is set, and round-trip tests for both.
+// - Printer and parser options matching C++'s `JsonPrintOptions`, e.g.
+//   `preserve_proto_field_names` and `always_print_enums_as_ints`, so that
+//   services in different languages can agree on one JSON dialect.
+// - Transcoding streams of binary messages to JSON and back given only a
+//   descriptor pool and a type name, for gateways and debugging tools. This
+//   also needs descriptors and dynamic messages.
+
+// TODO: Rust Protobuf does not support the text format yet.
+// These features are blocked on it:
+// - Printing a diff of two messages' text formats that only shows the
+//   changed fields.
+
+// TODO: Rust Protobuf does not provide the well-known types yet.
+// These features are blocked on it:
+// - Packing and unpacking `google.rpc.Status` details (`ErrorInfo`,
+//   `BadRequest`, `RetryInfo`) through `Any`. This needs `Any` with
+//   `pack`/`unpack` helpers, as well as Rust gencode for the `google.rpc`
+//   protos.
+// - Builders for `Struct`, `Value` and `ListValue`: `From` conversions into
+//   `Value`, `FromIterator<(&str, Value)>` for `Struct`, and indexing with
+//   `struct["key"]["nested"]`. Indexing also needs map fields.
+// - `Value::null()`, `Value::is_null()` and `Empty::instance()`, and `From`
+//   conversions between `Option<T>` and the wrapper types or `Value`.
+// - `Add`/`Sub` between `Timestamp` and `Duration`, `Duration * i32`, and
+//   `Ord` for both, with checked and saturating variants. Results have to
+//   keep `seconds` and `nanos` normalized the way the C++ `TimeUtil` does.
+// - Helpers for `repeated google.protobuf.Any` fields: `unpack_all::<M>()`,
+//   partitioning the elements by type URL, and packing a list from an
+//   iterator of messages. These also need repeated message fields; meanwhile
+//   `<Message>::TYPE_URL` gives the URLs to match against.
+
 /// Everything in `__internal` is allowed to change without it being considered
 /// a breaking change for the protobuf library. Nothing in here should be
 /// exported in `protobuf.rs`.
@@ -42,17 +217,75 @@ pub mod __runtime;
 #[path = "upb.rs"]
 pub mod __runtime;
 
+// TODO: Once the runtime is also built with Cargo, select the kernel with
+// `cpp` and `upb` features that map to these cfgs. Optional integrations with
+// other crates are blocked on this too, since Bazel builds can't depend on
+// crates.io. Among them:
+// - Conversions from and to messages of the community `protobuf` crate,
+//   behind a feature, for codebases migrating between the two. These go
+//   through wire bytes (the schemas must match), i.e. `write_to_bytes()` on
+//   one side and `deserialize()` on the other, which already works by hand.
+// - Recording messages in `tracing` spans and events behind a `tracing`
+//   feature. Until then, `field = %msg.debug_string(&options)` records a
+//   bounded dump, with `DebugStringOptions` limiting its size.
+// - `to_array1()` and `from_array1()` between numeric repeated fields and
+//   `ndarray::Array1`, behind an `ndarray` feature, for embeddings and other
+//   tensors stored in repeated `float` fields. This also needs repeated field
+//   support; the copy should be a single `memcpy` of the kernel's storage.
+#[cfg(all(cpp_kernel, upb_kernel))]
+compile_error!("Both `cpp_kernel` and `upb_kernel` are set; select exactly one kernel.");
+#[cfg(not(any(cpp_kernel, upb_kernel)))]
+compile_error!("Neither `cpp_kernel` nor `upb_kernel` is set; select exactly one kernel.");
+
+mod api_check;
+mod compression;
+mod concat;
+mod debug_string;
+mod delimited;
+mod deprecation;
+mod descriptor_registry;
+mod envelope;
+mod extensions;
+mod extract;
+mod field_info;
+mod filter;
+mod framing;
+mod hooks;
+mod lenient;
+mod limits;
 mod macros;
+mod message_set;
+mod metrics;
 mod optional;
+mod packed;
 mod primitive;
 mod proxied;
+mod record_log;
+mod renumber;
+mod self_describing;
+mod signing;
+mod size_report;
+mod streaming;
 mod string;
+mod truncate;
 mod vtable;
+mod wire;
+mod wire_corpus;
 
 /// An error that happened during deserialization.
+// TODO: Neither kernel reports where parsing failed. Until they do, the
+// wire-level utilities (e.g. `FieldFilter`) report a `WirePathError` with the
+// field number path to the malformed submessage instead.
 #[derive(Debug, Clone)]
 pub struct ParseError;
 
+impl ParseError {
+    /// Returns the error's stable code, `"parse"`.
+    pub fn code(&self) -> &'static str {
+        "parse"
+    }
+}
+
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Couldn't deserialize given bytes into a proto")
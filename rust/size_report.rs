@@ -0,0 +1,141 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Attributing the serialized size of a message to its fields.
+
+use crate::wire::{WirePathError, WireReader, WireValue};
+use std::collections::BTreeMap;
+
+/// The serialized bytes attributable to one field number.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldSize {
+    /// The field number.
+    pub field_number: u32,
+
+    /// How many times the field occurs. Repeated fields and fields that were
+    /// merged on the wire occur more than once.
+    pub count: usize,
+
+    /// The total size of all occurrences, including tags and length prefixes.
+    pub bytes: usize,
+
+    /// The breakdown of the field's submessages, merged across occurrences.
+    /// Empty if the field wasn't broken down further.
+    pub nested: Vec<FieldSize>,
+}
+
+/// Breaks the serialized size of `data` (e.g. the output of
+/// `<Message>::serialize()`) down by field number, in field number order.
+///
+/// Submessages are broken down up to `max_depth` levels deep; `0` only
+/// reports top-level fields. The wire format doesn't say whether a
+/// length-delimited field is a submessage, so one is broken down if all of
+/// its occurrences parse as messages. A string or bytes field can be mistaken
+/// for a submessage that way, but its `bytes` are correct either way.
+pub fn size_breakdown(data: &[u8], max_depth: usize) -> Result<Vec<FieldSize>, WirePathError> {
+    let mut fields: BTreeMap<u32, (FieldSize, Vec<&[u8]>)> = BTreeMap::new();
+    let mut reader = WireReader::new(data);
+    while !reader.is_empty() {
+        let before = reader.remaining().len();
+        let (field_number, value) = reader.read_field()?;
+        let (size, payloads) = fields
+            .entry(field_number)
+            .or_insert_with(|| (FieldSize { field_number, ..FieldSize::default() }, Vec::new()));
+        size.count += 1;
+        size.bytes += before - reader.remaining().len();
+        if let WireValue::Delimited(payload) | WireValue::Group(payload) = value {
+            payloads.push(payload);
+        }
+    }
+
+    Ok(fields
+        .into_values()
+        .map(|(mut size, payloads)| {
+            if max_depth > 0 && payloads.len() == size.count {
+                size.nested = merge_breakdowns(&payloads, max_depth - 1).unwrap_or_default();
+            }
+            size
+        })
+        .collect())
+}
+
+/// Breaks down every payload and merges the results, or returns `None` if one
+/// of them doesn't parse.
+fn merge_breakdowns(payloads: &[&[u8]], max_depth: usize) -> Option<Vec<FieldSize>> {
+    let mut merged: BTreeMap<u32, FieldSize> = BTreeMap::new();
+    for payload in payloads {
+        for size in size_breakdown(payload, max_depth).ok()? {
+            match merged.get_mut(&size.field_number) {
+                Some(existing) => merge(existing, size),
+                None => {
+                    merged.insert(size.field_number, size);
+                }
+            }
+        }
+    }
+    Some(merged.into_values().collect())
+}
+
+fn merge(into: &mut FieldSize, from: FieldSize) {
+    into.count += from.count;
+    into.bytes += from.bytes;
+    for size in from.nested {
+        match into.nested.iter_mut().find(|s| s.field_number == size.field_number) {
+            Some(existing) => merge(existing, size),
+            None => into.nested.push(size),
+        }
+    }
+    into.nested.sort_by_key(|s| s.field_number);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::WireError;
+
+    fn size(field_number: u32, count: usize, bytes: usize, nested: Vec<FieldSize>) -> FieldSize {
+        FieldSize { field_number, count, bytes, nested }
+    }
+
+    #[test]
+    fn test_top_level() {
+        // 2: 1, 1: "abc", 2: 300
+        let data = b"\x10\x01\x0a\x03abc\x10\xac\x02";
+        assert_eq!(
+            size_breakdown(data, 0).unwrap(),
+            [size(1, 1, 5, vec![]), size(2, 2, 5, vec![])]
+        );
+        assert_eq!(size_breakdown(b"", 3).unwrap(), []);
+    }
+
+    #[test]
+    fn test_nested() {
+        // 1: { 1: 1 }, 1: { 2: "xy", 1: 2 }, 2: "\xff"
+        let data = b"\x0a\x02\x08\x01\x0a\x06\x12\x02xy\x08\x02\x12\x01\xff";
+        assert_eq!(
+            size_breakdown(data, 1).unwrap(),
+            [
+                size(1, 2, 12, vec![size(1, 2, 4, vec![]), size(2, 1, 4, vec![])]),
+                // Doesn't parse as a message, so it isn't broken down.
+                size(2, 1, 3, vec![]),
+            ]
+        );
+        assert_eq!(size_breakdown(data, 0).unwrap()[0].nested, []);
+    }
+
+    #[test]
+    fn test_groups() {
+        // 1: group { 2: 1 }
+        let data = b"\x0b\x10\x01\x0c";
+        assert_eq!(size_breakdown(data, 1).unwrap(), [size(1, 1, 4, vec![size(2, 1, 2, vec![])])]);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(size_breakdown(b"\x0a\x05ab", 1), Err(WireError::Truncated.into()));
+    }
+}
@@ -0,0 +1,178 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Serializing huge messages straight to a writer, with repeated fields
+//! supplied by iterators while encoding.
+//!
+//! A message with a multi-GB repeated field would otherwise have to hold all
+//! of its elements, and then all of its serialized bytes, in memory. Here the
+//! cheap fields are serialized as usual (e.g. `msg.serialize()` with the
+//! repeated field left empty), and each repeated element is produced, written
+//! and dropped one at a time, so peak memory stays at about one element.
+
+use crate::wire::{varint_len, write_varint, WireType, WireWriter};
+use std::io::{self, Write};
+
+/// How many bytes of packed elements are encoded before they are written.
+const CHUNK_LEN: usize = 8 * 1024;
+
+/// Writes the fields of one message to an [`io::Write`].
+///
+/// Fields may be written in any order and any number of times, with the
+/// usual merging semantics (see [`WireWriter`]). Every call writes to the
+/// underlying writer, so wrap unbuffered ones (e.g. a `File`) in an
+/// [`io::BufWriter`].
+///
+/// All methods panic if the field number is invalid.
+#[derive(Debug)]
+pub struct StreamingSerializer<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> StreamingSerializer<W> {
+    /// Constructs a serializer that writes to `inner`.
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Writes serialized fields as-is, e.g. the output of
+    /// `<Message>::serialize()` or of a [`WireWriter`].
+    pub fn write_serialized(&mut self, data: &[u8]) -> io::Result<()> {
+        self.inner.write_all(data)
+    }
+
+    /// Writes each of `values` as one element of a repeated string, bytes or
+    /// (serialized) submessage field. Elements are produced, written and
+    /// dropped one at a time, e.g.
+    /// `rows.map(|row| make_row_message(row).serialize())`.
+    pub fn write_repeated_delimited<I>(&mut self, field_number: u32, values: I) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        for value in values {
+            let value = value.as_ref();
+            self.write_header(field_number, value.len())?;
+            self.inner.write_all(value)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `values` as a packed repeated varint field. Nothing is written
+    /// if `values` is empty.
+    ///
+    /// `values` is iterated twice: once to size the field, once to write it.
+    pub fn write_packed_varints<I>(&mut self, field_number: u32, values: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = u64>,
+        I::IntoIter: Clone,
+    {
+        let values = values.into_iter();
+        let len: usize = values.clone().map(varint_len).sum();
+        self.write_packed(field_number, len, values, write_varint)
+    }
+
+    /// Writes `values` as a packed repeated `fixed32`, `sfixed32` or `float`
+    /// field. Nothing is written if `values` is empty.
+    pub fn write_packed_fixed32<I>(&mut self, field_number: u32, values: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = u32>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let values = values.into_iter();
+        self.write_packed(field_number, values.len() * 4, values, |chunk, v| {
+            chunk.extend_from_slice(&v.to_le_bytes())
+        })
+    }
+
+    /// Writes `values` as a packed repeated `fixed64`, `sfixed64` or
+    /// `double` field. Nothing is written if `values` is empty.
+    pub fn write_packed_fixed64<I>(&mut self, field_number: u32, values: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = u64>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let values = values.into_iter();
+        self.write_packed(field_number, values.len() * 8, values, |chunk, v| {
+            chunk.extend_from_slice(&v.to_le_bytes())
+        })
+    }
+
+    fn write_packed<T>(
+        &mut self,
+        field_number: u32,
+        len: usize,
+        values: impl Iterator<Item = T>,
+        encode: impl Fn(&mut Vec<u8>, T),
+    ) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        self.write_header(field_number, len)?;
+        let mut chunk = Vec::with_capacity(CHUNK_LEN.min(len) + 10);
+        for v in values {
+            encode(&mut chunk, v);
+            if chunk.len() >= CHUNK_LEN {
+                self.inner.write_all(&chunk)?;
+                chunk.clear();
+            }
+        }
+        self.inner.write_all(&chunk)
+    }
+
+    /// Writes the tag and length of a length-delimited field.
+    fn write_header(&mut self, field_number: u32, len: usize) -> io::Result<()> {
+        let mut header = WireWriter::new();
+        header.write_tag(field_number, WireType::Delimited);
+        let mut header = header.into_vec();
+        write_varint(&mut header, len as u64);
+        self.inner.write_all(&header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_wire_writer() {
+        let mut expected = WireWriter::new();
+        expected.write_varint(1, 5);
+        expected.write_delimited(2, b"a");
+        expected.write_delimited(2, b"");
+        expected.write_packed_varints(3, [1, 300, 0]);
+        expected.write_packed_fixed32(4, [1, 2]);
+        expected.write_packed_fixed64(5, [3]);
+
+        let mut head = WireWriter::new();
+        head.write_varint(1, 5);
+        let mut serializer = StreamingSerializer::new(Vec::new());
+        serializer.write_serialized(head.as_bytes()).unwrap();
+        serializer.write_repeated_delimited(2, ["a", ""]).unwrap();
+        serializer.write_packed_varints(3, [1, 300, 0]).unwrap();
+        serializer.write_packed_varints(3, []).unwrap();
+        serializer.write_packed_fixed32(4, [1, 2]).unwrap();
+        serializer.write_packed_fixed64(5, [3]).unwrap();
+        assert_eq!(serializer.into_inner(), expected.into_vec());
+    }
+
+    #[test]
+    fn test_large_packed_field() {
+        let mut serializer = StreamingSerializer::new(Vec::new());
+        serializer.write_packed_fixed32(1, 0..10_000).unwrap();
+        let out = serializer.into_inner();
+        // Tag, 3-byte length, then the elements.
+        assert_eq!(out.len(), 1 + 3 + 40_000);
+        assert_eq!(&out[4..12], b"\x00\x00\x00\x00\x01\x00\x00\x00");
+        assert_eq!(&out[out.len() - 4..], 9_999u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,55 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Times parsing and serialization of the shapes in `shapes.rs`.
+//!
+//! Usage: `parse_serialize_benchmark_{cpp,upb} [iterations]`. Prints one line
+//! per shape and operation, in nanoseconds per iteration.
+
+use std::hint::black_box;
+use std::time::Instant;
+use unittest_proto::proto2_unittest::{NestedTestAllTypes, TestAllTypes};
+
+fn bench(name: &str, op: &str, iterations: u32, mut f: impl FnMut()) {
+    // Warm up caches and the allocator.
+    for _ in 0..iterations / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let ns = start.elapsed().as_nanos() as f64 / f64::from(iterations);
+    println!("{name:<24} {op:<12} {ns:>12.1} ns/iter");
+}
+
+macro_rules! bench_shape {
+    ($msg:ty, $name:expr, $bytes:expr, $iterations:expr) => {{
+        let bytes = $bytes;
+        let mut msg = <$msg>::new();
+        bench($name, "parse", $iterations, || {
+            let mut msg = <$msg>::new();
+            msg.deserialize(black_box(&bytes)).unwrap();
+            black_box(&msg);
+        });
+        msg.deserialize(&bytes).unwrap();
+        bench($name, "serialize", $iterations, || {
+            black_box(black_box(&msg).serialize());
+        });
+    }};
+}
+
+fn main() {
+    let iterations = std::env::args().nth(1).map_or(10_000, |arg| arg.parse().expect("iterations"));
+
+    bench_shape!(TestAllTypes, "scalars(13)", shapes::scalars(13), iterations);
+    bench_shape!(NestedTestAllTypes, "nested(4, 13)", shapes::nested(4, 13), iterations);
+    bench_shape!(NestedTestAllTypes, "nested(32, 2)", shapes::nested(32, 2), iterations);
+    bench_shape!(TestAllTypes, "packed_int64s(1000)", shapes::packed_int64s(1000), iterations);
+    bench_shape!(TestAllTypes, "strings(100, 16)", shapes::strings(100, 16), iterations);
+    bench_shape!(TestAllTypes, "strings(4, 4096)", shapes::strings(4, 4096), iterations);
+}
@@ -0,0 +1,105 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Generators of serialized messages with representative shapes, for
+//! benchmarking parsing and serialization.
+//!
+//! All shapes are valid `protobuf_unittest.TestAllTypes` or
+//! `protobuf_unittest.NestedTestAllTypes` messages, as noted on each function.
+//! Fields the Rust API doesn't expose yet (e.g. repeated fields) are still
+//! parsed and serialized by the kernels, so they still count.
+
+/// A serialized `TestAllTypes` with its first `width` singular scalar fields
+/// (at most 13, `optional_int32` through `optional_bool`) set.
+pub fn scalars(width: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    for field in 1..=width.min(13) as u64 {
+        match field {
+            // optional_fixed32, optional_sfixed32, optional_float
+            7 | 9 | 11 => {
+                tag(&mut out, field, 5);
+                out.extend_from_slice(&(field as u32 * 1000).to_le_bytes());
+            }
+            // optional_fixed64, optional_sfixed64, optional_double
+            8 | 10 | 12 => {
+                tag(&mut out, field, 1);
+                out.extend_from_slice(&(field * 1_000_000).to_le_bytes());
+            }
+            // optional_bool
+            13 => {
+                tag(&mut out, field, 0);
+                out.push(1);
+            }
+            _ => {
+                tag(&mut out, field, 0);
+                varint(&mut out, field * 1000);
+            }
+        }
+    }
+    out
+}
+
+/// A serialized `NestedTestAllTypes` whose `child` chain is `depth` messages
+/// deep, with every level's `payload` set to `scalars(width)`.
+pub fn nested(depth: usize, width: usize) -> Vec<u8> {
+    let payload = scalars(width);
+    let mut out = Vec::new();
+    for _ in 0..=depth {
+        let mut level = Vec::new();
+        // payload
+        delimited(&mut level, 2, &payload);
+        // child
+        if !out.is_empty() {
+            delimited(&mut level, 1, &out);
+        }
+        out = level;
+    }
+    out
+}
+
+/// A serialized `TestAllTypes` with `len` values in a packed
+/// `repeated_int64`, spread over all varint lengths.
+pub fn packed_int64s(len: usize) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for i in 0..len as u64 {
+        varint(&mut payload, i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (i % 64));
+    }
+    let mut out = Vec::new();
+    // repeated_int64
+    delimited(&mut out, 32, &payload);
+    out
+}
+
+/// A serialized `TestAllTypes` with `count` values of `len` bytes each in
+/// `repeated_string`.
+pub fn strings(count: usize, len: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    for i in 0..count {
+        let value: Vec<u8> = (0..len).map(|j| b'a' + ((i + j) % 26) as u8).collect();
+        // repeated_string
+        delimited(&mut out, 44, &value);
+    }
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn tag(out: &mut Vec<u8>, field: u64, wire_type: u64) {
+    varint(out, field << 3 | wire_type);
+}
+
+fn delimited(out: &mut Vec<u8>, field: u64, payload: &[u8]) {
+    tag(out, field, 2);
+    varint(out, payload.len() as u64);
+    out.extend_from_slice(payload);
+}
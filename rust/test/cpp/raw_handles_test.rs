@@ -0,0 +1,22 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use googletest::prelude::*;
+use raw_handles_proto::raw_handles::RawHandles;
+
+#[test]
+fn test_into_raw_from_raw_round_trip() {
+    let mut msg = RawHandles::new();
+    msg.num_set(Some(42));
+    msg.payload_mut().set(b"hello world");
+
+    let raw = msg.into_raw();
+    // SAFETY: `raw` was just released by `into_raw()` and nothing else uses it.
+    let msg = unsafe { RawHandles::from_raw(raw) };
+    assert_that!(msg.num(), eq(42));
+    assert_that!(msg.payload(), eq(b"hello world"));
+}
@@ -26,6 +26,24 @@ fn test_fixed32_accessors() {
     assert_eq!(msg.optional_fixed32_mut().get(), 0);
 }
 
+#[test]
+fn test_fields_with_presence() {
+    let mut msg = TestAllTypes::new();
+    let set_fields = |msg: &TestAllTypes| -> Vec<_> {
+        msg.fields_with_presence()
+            .filter(|(_, is_set)| *is_set == Some(true))
+            .map(|(info, _)| info.name)
+            .collect()
+    };
+    assert_eq!(set_fields(&msg), Vec::<&str>::new());
+
+    // Without presence, fields are set unless they hold the zero value.
+    msg.optional_fixed32_mut().set(42);
+    msg.optional_double_mut().set(-0.0);
+    msg.optional_bytes_mut().set(b"");
+    assert_eq!(set_fields(&msg), ["optional_fixed32", "optional_double"]);
+}
+
 #[test]
 fn test_bool_accessors() {
     let mut msg = TestAllTypes::new();
@@ -204,3 +222,46 @@ fn test_oneof_accessors() {
     // This should show it set to the OneofBytes but its not supported yet.
     assert!(matches!(msg.oneof_field(), not_set(_)));
 }
+
+#[test]
+fn test_copy_matching_fields() {
+    // optional_int32: 7, optional_string: "hi", optional_foreign_enum (22): 5,
+    // optional_nested_message (18): {bb: 1}
+    let mut src = TestAllTypes::new();
+    src.deserialize(b"\x08\x07\x72\x02hi\xb0\x01\x05\x92\x01\x02\x08\x01").unwrap();
+
+    let mut dst = TestProto3Optional::new();
+    dst.optional_int64_set(Some(9));
+    dst.singular_int32_mut().set(3);
+    dst.copy_matching_fields_from(&src.serialize(), TestAllTypes::FIELDS).unwrap();
+    assert_eq!(dst.optional_int32_opt(), Optional::Set(7));
+    assert_eq!(dst.optional_string(), "hi");
+    assert_eq!(dst.optional_int64_opt(), Optional::Set(9));
+    // Field 22 is an enum in `src` but an `int32` in `dst`, so it isn't copied.
+    assert_eq!(dst.singular_int32(), 3);
+    // Field 18 is a submessage of another type in `dst`, so it isn't either.
+    let is_set = dst.fields_with_presence().find(|(info, _)| info.number == 18).unwrap().1;
+    assert_eq!(is_set, Some(false));
+
+    // If the merged fields don't parse, `dst` is left as it was.
+    let mut dst = TestAllTypes::new();
+    dst.optional_int32_mut().set(1);
+    assert!(dst.copy_matching_fields_from(b"\x92\x01\x01\xff", TestAllTypes::FIELDS).is_err());
+    assert_eq!(dst.optional_int32(), 1);
+}
+
+#[test]
+fn test_resolved_features() {
+    let field = |name| TestAllTypes::FIELDS.iter().find(|f| f.name == name).unwrap();
+    assert!(!field("optional_int32").has_presence);
+    assert!(field("optional_nested_message").has_presence);
+    assert!(field("optional_string").validates_utf8);
+    assert!(!field("optional_bytes").validates_utf8);
+    // proto3 enums are open, and repeated scalars are packed by default.
+    assert!(!field("optional_nested_enum").is_closed_enum);
+    assert!(field("repeated_int32").is_packed);
+
+    let field = |name| TestProto3Optional::FIELDS.iter().find(|f| f.name == name).unwrap();
+    assert!(field("optional_int32").has_presence);
+    assert!(!field("singular_int32").has_presence);
+}
@@ -8,9 +8,22 @@
 //! Tests covering accessors for singular bool, int32, int64, and bytes fields.
 
 use googletest::prelude::*;
-use protobuf::Optional;
+use protobuf::{Optional, OutOfRange};
 use unittest_proto::proto2_unittest::{TestAllTypes, TestAllTypes_};
 
+// Fails to compile if these generated items change.
+protobuf::assert_message_api!(TestAllTypes {
+    type TestAllTypes_::NestedMessageView<'static>;
+    const FULL_NAME: &'static str;
+    const FIELDS: &'static [protobuf::FieldInfo];
+    fn new() -> TestAllTypes;
+    fn optional_int32(&self) -> i32;
+    fn optional_int32_opt(&self) -> Optional<i32>;
+    fn optional_int32_set(&mut self, Option<i32>);
+    fn optional_int32_try_set(&mut self, i64) -> Result<(), OutOfRange>;
+    fn deserialize(&mut self, &[u8]) -> Result<(), protobuf::ParseError>;
+});
+
 #[test]
 fn test_default_accessors() {
     let msg = TestAllTypes::new();
@@ -34,6 +47,78 @@ fn test_default_accessors() {
     );
 }
 
+#[test]
+fn test_default_constants() {
+    assert_that!(TestAllTypes::DEFAULT_INT32_DEFAULT, eq(41));
+    assert_that!(TestAllTypes::DEFAULT_UINT64_DEFAULT, eq(44));
+    assert_that!(TestAllTypes::DEFAULT_SINT32_DEFAULT, eq(-45));
+    assert_that!(TestAllTypes::DEFAULT_FLOAT_DEFAULT, eq(51.5));
+    assert_that!(TestAllTypes::DEFAULT_DOUBLE_DEFAULT, eq(52000.0));
+    assert_that!(TestAllTypes::DEFAULT_BOOL_DEFAULT, eq(true));
+    assert_that!(TestAllTypes::DEFAULT_STRING_DEFAULT, eq("hello"));
+    assert_that!(TestAllTypes::DEFAULT_BYTES_DEFAULT, eq(b"world"));
+}
+
+#[test]
+fn test_unset_optional_has_declared_default() {
+    let mut msg = TestAllTypes::new();
+    assert_that!(msg.default_int32_opt(), eq(Optional::Unset(41)));
+    assert_that!(msg.default_double_opt(), eq(Optional::Unset(52000.0)));
+    assert_that!(msg.default_bool_opt(), eq(Optional::Unset(true)));
+
+    msg.default_int32_set(Some(7));
+    assert_that!(msg.default_int32_opt(), eq(Optional::Set(7)));
+    msg.default_int32_set(None);
+    assert_that!(msg.default_int32_opt(), eq(Optional::Unset(41)));
+}
+
+#[test]
+fn test_fields_with_presence() {
+    use protobuf::{FieldInfo, FieldKind};
+
+    assert_that!(
+        TestAllTypes::FIELDS[0],
+        eq(FieldInfo {
+            number: 1,
+            name: "optional_int32",
+            kind: FieldKind::Int32,
+            is_repeated: false,
+            is_packed: false,
+            has_presence: true,
+            is_closed_enum: false,
+            validates_utf8: false,
+            message_type: None,
+        })
+    );
+    let nested_message =
+        TestAllTypes::FIELDS.iter().find(|f| f.name == "optional_nested_message").unwrap();
+    let message_type = nested_message.message_type.unwrap();
+    assert_that!(message_type.full_name, eq("protobuf_unittest.TestAllTypes.NestedMessage"));
+    assert_that!(message_type.fields.unwrap()()[0].name, eq("bb"));
+    // proto2 enums are closed.
+    let nested_enum = TestAllTypes::FIELDS.iter().find(|f| f.name == "optional_nested_enum");
+    assert_that!(nested_enum.unwrap().is_closed_enum, eq(true));
+
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(1));
+    msg.optional_string_mut().set("set");
+    let is_set =
+        |number| msg.fields_with_presence().find(|(info, _)| info.number == number).unwrap().1;
+    assert_that!(is_set(1), eq(Some(true)));
+    assert_that!(is_set(2), eq(Some(false)));
+    assert_that!(is_set(14), eq(Some(true)));
+    assert_that!(is_set(18), eq(Some(false)));
+    // Repeated fields can't tell yet.
+    assert_that!(is_set(31), eq(None));
+
+    let set: Vec<_> = msg
+        .fields_with_presence()
+        .filter(|(_, is_set)| *is_set == Some(true))
+        .map(|(info, _)| info.name)
+        .collect();
+    assert_that!(set, eq(vec!["optional_int32", "optional_string"]));
+}
+
 #[test]
 fn test_optional_fixed32_accessors() {
     let mut msg = TestAllTypes::new();
@@ -139,6 +224,20 @@ fn test_optional_uint32_accessors() {
     assert_that!(msg.optional_uint32(), eq(0));
 }
 
+#[test]
+fn test_checked_setters() {
+    let mut msg = TestAllTypes::new();
+    assert_that!(msg.optional_int32_try_set(-7), eq(Ok(())));
+    assert_that!(msg.optional_int32_opt(), eq(Optional::Set(-7)));
+    assert_that!(msg.optional_int32_try_set(i64::from(i32::MAX) + 1), eq(Err(OutOfRange)));
+    assert_that!(msg.optional_int32(), eq(-7));
+
+    assert_that!(msg.optional_uint32_try_set(i64::from(u32::MAX)), eq(Ok(())));
+    assert_that!(msg.optional_uint32(), eq(u32::MAX));
+    assert_that!(msg.optional_uint32_try_set(-1), eq(Err(OutOfRange)));
+    assert_that!(msg.optional_uint32(), eq(u32::MAX));
+}
+
 #[test]
 fn test_optional_uint64_accessors() {
     let mut msg = TestAllTypes::new();
@@ -327,6 +426,19 @@ fn test_optional_string_accessors() {
     assert_that!(msg.optional_string_opt(), eq(Optional::Set("".into())));
 }
 
+#[test]
+fn test_string_unchecked_accessor() {
+    let mut msg = TestAllTypes::new();
+    // SAFETY: every value set below is UTF-8.
+    unsafe {
+        assert_that!(msg.optional_string_unchecked(), eq(""));
+        assert_that!(msg.default_string_unchecked(), eq("hello"));
+
+        msg.optional_string_mut().set("ศไทย中华Việt Nam");
+        assert_that!(msg.optional_string_unchecked(), eq("ศไทย中华Việt Nam"));
+    }
+}
+
 #[test]
 fn test_nonempty_default_string_accessors() {
     let mut msg = TestAllTypes::new();
@@ -397,3 +509,84 @@ fn test_oneof_accessors() {
     // This should show it set to the OneofBytes but its not supported yet.
     assert_that!(msg.oneof_field(), matches_pattern!(not_set(_)));
 }
+
+#[test]
+fn test_oneof_visit() {
+    #[derive(Default)]
+    struct Visited {
+        uint32: Option<u32>,
+        not_set: bool,
+    }
+    impl TestAllTypes_::OneofFieldVisitor<'_> for Visited {
+        fn visit_oneof_uint32(&mut self, value: u32) {
+            self.uint32 = Some(value);
+        }
+        fn visit_not_set(&mut self) {
+            self.not_set = true;
+        }
+    }
+
+    let mut msg = TestAllTypes::new();
+    let mut visited = Visited::default();
+    msg.oneof_field_visit(&mut visited);
+    assert_that!(visited.uint32, eq(None));
+    assert_that!(visited.not_set, eq(true));
+
+    msg.oneof_uint32_set(Some(7));
+    let mut visited = Visited::default();
+    msg.oneof_field_visit(&mut visited);
+    assert_that!(visited.uint32, eq(Some(7)));
+    assert_that!(visited.not_set, eq(false));
+}
+
+#[test]
+fn test_prune_defaults() {
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(0));
+    msg.optional_int64_set(Some(5));
+    msg.default_int32_set(Some(41));
+    msg.default_float_set(Some(51.5));
+    msg.default_double_set(Some(-1.0));
+    msg.default_string_mut().set("hello");
+    msg.optional_bytes_mut().set(b"");
+    msg.oneof_uint32_set(Some(0));
+
+    msg.prune_defaults();
+    assert_that!(msg.optional_int32_opt(), eq(Optional::Unset(0)));
+    assert_that!(msg.optional_int64_opt(), eq(Optional::Set(5)));
+    assert_that!(msg.default_int32_opt(), eq(Optional::Unset(41)));
+    assert_that!(msg.default_float_opt(), eq(Optional::Unset(51.5)));
+    assert_that!(msg.default_double_opt(), eq(Optional::Set(-1.0)));
+    assert_that!(msg.default_string_opt(), eq(Optional::Unset("hello".into())));
+    assert_that!(msg.optional_bytes_opt().is_set(), eq(false));
+    // Oneof fields are kept, since they also select their oneof's case.
+    assert_that!(msg.oneof_uint32_opt(), eq(Optional::Set(0)));
+}
+
+#[test]
+fn test_is_default() {
+    let mut msg = TestAllTypes::new();
+    assert_that!(msg.is_default(), eq(true));
+    assert_that!(msg.optional_int32_is_default(), eq(true));
+    assert_that!(msg.default_int32_is_default(), eq(true));
+    assert_that!(msg.default_string_is_default(), eq(true));
+
+    // Setting a field to its default value leaves it reading as the default,
+    // but the message is no longer a new one.
+    msg.default_int32_set(Some(41));
+    assert_that!(msg.default_int32_is_default(), eq(true));
+    assert_that!(msg.is_default(), eq(false));
+
+    msg.default_int32_set(Some(0));
+    msg.default_float_set(Some(-0.0));
+    msg.optional_double_set(Some(-0.0));
+    msg.default_string_mut().set("");
+    assert_that!(msg.default_int32_is_default(), eq(false));
+    assert_that!(msg.default_float_is_default(), eq(false));
+    assert_that!(msg.optional_double_is_default(), eq(false));
+    assert_that!(msg.default_string_is_default(), eq(false));
+
+    let mut msg = TestAllTypes::new();
+    msg.deserialize(b"\xc0\xb8\x02\x01").unwrap();
+    assert_that!(msg.is_default(), eq(false));
+}
@@ -0,0 +1,43 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use googletest::prelude::*;
+use std::sync::Mutex;
+use unittest_proto::proto2_unittest::TestDeprecatedFields;
+
+static REPORTED: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+#[test]
+fn deprecated_field_use_is_reported_once() {
+    protobuf::set_deprecated_field_hook(|name| REPORTED.lock().unwrap().push(name));
+
+    let mut msg = TestDeprecatedFields::new();
+    assert_that!(msg.deprecated_int32(), eq(0));
+    msg.deprecated_int32_set(Some(1));
+    assert_that!(msg.deprecated_int32(), eq(1));
+    // Every other accessor of the field shares the same report.
+    assert!(!msg.deprecated_int32_is_default());
+    assert!(msg.deprecated_int32_opt().is_set());
+    assert!(msg.deprecated_int32_try_set(2).is_ok());
+
+    let _ = msg.deprecated_message();
+    assert!(msg.deprecated_message_set_serialized(b"").is_ok());
+    let _ = msg.deprecated_message();
+
+    msg.deprecated_int32_in_oneof_set(Some(4));
+    assert_that!(msg.deprecated_int32_in_oneof(), eq(4));
+    protobuf::clear_deprecated_field_hook();
+
+    assert_that!(
+        *REPORTED.lock().unwrap(),
+        eq(vec![
+            "protobuf_unittest.TestDeprecatedFields.deprecated_int32",
+            "protobuf_unittest.TestDeprecatedFields.deprecated_message",
+            "protobuf_unittest.TestDeprecatedFields.deprecated_int32_in_oneof",
+        ])
+    );
+}
@@ -0,0 +1,33 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use field_num_proto::field_num::{
+    Lonely, Lonely_, NestedFieldNum, NestedFieldNum_, OneofFieldNum, OneofFieldNum_,
+    SimilarNames_::FieldNum,
+};
+use googletest::prelude::*;
+use protobuf::FieldNumber;
+
+#[test]
+fn test_similar_names_get_unique_variants() {
+    assert_that!(FieldNum::FooBar.number(), eq(1));
+    assert_that!(FieldNum::FooBar_2.number(), eq(2));
+    assert_that!(FieldNum::Foo2.number(), eq(3));
+    assert_that!(FieldNum::Foo2_4.number(), eq(4));
+    assert_that!(FieldNum::Self_5.number(), eq(5));
+    assert_that!(FieldNum::from_number(4), eq(Some(FieldNum::Foo2_4)));
+}
+
+#[test]
+fn test_clashing_names_are_kept() {
+    // `FieldNum` is left out rather than clash with these.
+    let _: NestedFieldNum_::FieldNum = NestedFieldNum_::FieldNum::new();
+    let mut msg = OneofFieldNum::new();
+    msg.b_set(Some(1));
+    assert_that!(msg.field_num(), matches_pattern!(OneofFieldNum_::FieldNum::B(eq(1))));
+    let _ = (NestedFieldNum::new(), Lonely::new(), Lonely_::new());
+}
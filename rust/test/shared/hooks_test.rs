@@ -0,0 +1,72 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use googletest::prelude::*;
+use std::sync::Mutex;
+use std::time::Duration;
+use unittest_proto::proto2_unittest::{ForeignMessage, TestAllTypes};
+
+static SERIALIZED: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+#[test]
+fn hooks_run_on_parse_and_serialize() {
+    // Fill in `optional_int64` if it's missing, and reject negative
+    // `optional_int32` values.
+    protobuf::add_parse_hook(|msg: &mut TestAllTypes| {
+        if !msg.optional_int64_opt().is_set() {
+            msg.optional_int64_set(Some(7));
+        }
+        Ok(())
+    });
+    protobuf::add_parse_hook(|msg: &mut TestAllTypes| {
+        if msg.optional_int32() < 0 {
+            Err(protobuf::ParseError)
+        } else {
+            Ok(())
+        }
+    });
+    protobuf::add_serialize_hook(|msg: &TestAllTypes| {
+        SERIALIZED.lock().unwrap().push(msg.optional_int32());
+    });
+
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(1));
+    let serialized = msg.serialize();
+    let mut parsed = TestAllTypes::new();
+    parsed.deserialize(&serialized).unwrap();
+    assert_that!(parsed.optional_int64(), eq(7));
+
+    msg.optional_int32_set(Some(-1));
+    assert!(parsed.deserialize(&msg.serialize()).is_err());
+    msg.serialize_unpacked();
+
+    // Internal round trips run neither kind of hook.
+    msg.debug_string(&Default::default());
+    assert!(msg.copy_matching_fields_from(b"", TestAllTypes::FIELDS).is_ok());
+    assert!(msg.optional_nested_message_set_serialized(b"").is_ok());
+    assert!(!msg.optional_int64_opt().is_set());
+
+    protobuf::clear_message_hooks();
+    msg.serialize();
+
+    assert_that!(*SERIALIZED.lock().unwrap(), eq(vec![1, -1, -1]));
+
+    // A hook can serialize another message, which runs hooks itself, while
+    // another thread is waiting to add a hook.
+    protobuf::add_parse_hook(|msg: &mut TestAllTypes| {
+        let writer =
+            std::thread::spawn(|| protobuf::add_parse_hook(|_: &mut ForeignMessage| Ok(())));
+        std::thread::sleep(Duration::from_millis(10));
+        let mut foreign = ForeignMessage::new();
+        foreign.c_set(Some(msg.optional_int32()));
+        foreign.serialize();
+        writer.join().unwrap();
+        Ok(())
+    });
+    assert!(parsed.deserialize(&serialized).is_ok());
+    protobuf::clear_message_hooks();
+}
@@ -0,0 +1,47 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use googletest::prelude::*;
+use method_names_proto::method_names::{Checkable, Clashing};
+
+#[test]
+fn test_is_default_checks_fields_and_unknown_fields() {
+    let mut msg = Checkable::new();
+    assert_that!(msg.is_default(), eq(true));
+    msg.num_set(Some(0));
+    assert_that!(msg.is_default(), eq(false));
+
+    // An empty submessage is set, but is a default one itself.
+    let mut msg = Checkable::new();
+    msg.deserialize(b"\x1a\x00").unwrap();
+    assert_that!(msg.is_default(), eq(false));
+    assert_that!(msg.child().is_default(), eq(true));
+
+    let mut msg = Checkable::new();
+    msg.deserialize(b"\x20\x00").unwrap();
+    assert_that!(msg.is_default(), eq(false));
+
+    // Field 15 isn't declared, so it's kept as an unknown field.
+    let mut msg = Checkable::new();
+    msg.deserialize(b"\x78\x01").unwrap();
+    assert_that!(msg.is_default(), eq(false));
+}
+
+#[test]
+fn test_accessors_win_over_generated_methods() {
+    let mut msg = Clashing::new();
+    msg.is_default_set(Some(1));
+    assert_that!(msg.is_default(), eq(1));
+    msg.debug_string_mut().set("text");
+    assert_that!(msg.debug_string(), eq("text"));
+    // `foo_is_default()` is the getter of a field rather than the default
+    // check of `foo`; the fields' other default checks are still generated.
+    msg.foo_is_default_set(Some(2));
+    assert_that!(msg.foo_is_default(), eq(2));
+    assert_that!(msg.is_default_is_default(), eq(false));
+    assert_that!(msg.foo_is_default_is_default(), eq(false));
+}
@@ -0,0 +1,38 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+use googletest::prelude::*;
+use protobuf::MessageOp;
+use std::sync::Mutex;
+use unittest_proto::proto2_unittest::TestAllTypes;
+
+static RECORDED: Mutex<Vec<(MessageOp, &'static str, usize)>> = Mutex::new(Vec::new());
+
+#[test]
+fn parse_and_serialize_are_recorded() {
+    protobuf::set_message_metrics_hook(|m| {
+        RECORDED.lock().unwrap().push((m.op, m.full_name, m.bytes));
+    });
+    let mut msg = TestAllTypes::new();
+    msg.optional_int64_set(Some(42));
+    let serialized = msg.serialize();
+    msg.deserialize(&serialized).unwrap();
+    // These serialize internally, but aren't serialize calls of their own.
+    msg.debug_string(&Default::default());
+    assert!(!msg.is_default());
+    let policy = protobuf::TruncationPolicy::new();
+    assert!(msg.truncate_to_size(100, &policy).is_ok());
+    protobuf::clear_message_metrics_hook();
+
+    assert_that!(
+        *RECORDED.lock().unwrap(),
+        eq(vec![
+            (MessageOp::Serialize, "protobuf_unittest.TestAllTypes", 2),
+            (MessageOp::Parse, "protobuf_unittest.TestAllTypes", 2),
+        ])
+    );
+}
@@ -6,7 +6,7 @@
 // https://developers.google.com/open-source/licenses/bsd
 
 use googletest::prelude::*;
-use unittest_proto::proto2_unittest::TestAllTypes;
+use unittest_proto::proto2_unittest::{NestedTestAllTypes, TestAllExtensions, TestAllTypes};
 
 #[test]
 fn serialize_deserialize_message() {
@@ -25,6 +25,28 @@ fn serialize_deserialize_message() {
     assert_that!(msg.optional_bytes(), eq(msg2.optional_bytes()));
 }
 
+#[test]
+fn serialize_deterministic_matches_serialize() {
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(7));
+    msg.optional_bytes_mut().set(b"deterministic");
+
+    // Without map fields, the deterministic and default serializers agree.
+    assert_that!(msg.serialize_deterministic().to_vec(), eq(msg.serialize().to_vec()));
+}
+
+#[test]
+fn serialize_unpacked() {
+    use unittest_proto::proto2_unittest::TestPackedTypes;
+
+    // `packed_int32` is field 90, declared packed.
+    assert_that!(TestPackedTypes::FIELDS[0].is_packed, eq(true));
+    let mut msg = TestPackedTypes::new();
+    msg.deserialize(b"\xd2\x05\x02\x01\x02").unwrap();
+    assert_that!(&*msg.serialize(), eq(b"\xd2\x05\x02\x01\x02"));
+    assert_that!(&*msg.serialize_unpacked(), eq(b"\xd0\x05\x01\xd0\x05\x02"));
+}
+
 #[test]
 fn deserialize_empty() {
     let mut msg = TestAllTypes::new();
@@ -37,3 +59,402 @@ fn deserialize_error() {
     let data = b"not a serialized proto";
     assert!(msg.deserialize(&*data).is_err());
 }
+
+#[test]
+fn unknown_fields_keep_their_order() {
+    // 5001: 1, 1: 7, 5000: "x"
+    let mut msg = TestAllTypes::new();
+    msg.deserialize(b"\xc8\xb8\x02\x01\x08\x07\xc2\xb8\x02\x01x").unwrap();
+    msg.optional_int64_set(Some(42));
+
+    // Known fields come first, in field number order, followed by the unknown
+    // fields in their original order.
+    assert_that!(
+        msg.serialize().to_vec(),
+        eq(b"\x08\x07\x10\x2a\xc8\xb8\x02\x01\xc2\xb8\x02\x01x".to_vec())
+    );
+}
+
+#[test]
+fn parse_on_one_thread_read_on_another() {
+    let mut src = TestAllTypes::new();
+    src.optional_int32_set(Some(7));
+    src.optional_string_mut().set("moved");
+    let serialized = src.serialize().to_vec();
+
+    let msg = std::thread::spawn(move || {
+        let mut msg = TestAllTypes::new();
+        msg.deserialize(&serialized).unwrap();
+        msg
+    })
+    .join()
+    .unwrap();
+    assert_that!(msg.optional_int32(), eq(7));
+    assert_that!(msg.optional_string(), eq("moved"));
+}
+
+#[test]
+fn move_message_with_spliced_submessage_across_threads() {
+    let mut msg = TestAllTypes::new();
+    msg.optional_nested_message_set_serialized(b"\x08\x05").unwrap();
+
+    // The spliced submessage lives in `msg`'s arena (with upb), so it moves
+    // along with `msg` and can be read and replaced on the new thread.
+    let msg = std::thread::spawn(move || {
+        let mut msg = msg;
+        assert_that!(msg.optional_nested_message().bb(), eq(5));
+        msg.optional_nested_message_set_serialized(b"\x08\x06").unwrap();
+        msg
+    })
+    .join()
+    .unwrap();
+    assert_that!(msg.optional_nested_message().bb(), eq(6));
+}
+
+#[test]
+fn append_fields_with_wire_writer() {
+    use protobuf::{WireReader, WireWriter};
+
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(1));
+
+    // Appended fields merge into the message: the singular field is replaced
+    // and the repeated field `repeated_int32` gets elements from an iterator.
+    let mut writer = WireWriter::from_vec(msg.serialize().to_vec());
+    writer.write_varint(1, 5);
+    writer.write_packed_varints(31, (0..3).map(|v: i32| v as u64));
+
+    let mut merged = TestAllTypes::new();
+    merged.deserialize(writer.as_bytes()).unwrap();
+    assert_that!(merged.optional_int32(), eq(5));
+    let serialized = merged.serialize();
+    let repeated = WireReader::new(&serialized).filter(|f| f.unwrap().0 == 31).count();
+    assert_that!(repeated, eq(3));
+}
+
+#[test]
+fn streaming_serializer() {
+    use protobuf::{StreamingSerializer, WireReader};
+    use unittest_proto::proto2_unittest::TestAllTypes_::NestedMessage;
+
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(1));
+
+    // `repeated_nested_message` is field 48, `repeated_int64` field 32.
+    let mut serializer = StreamingSerializer::new(Vec::new());
+    serializer.write_serialized(&msg.serialize()).unwrap();
+    serializer
+        .write_repeated_delimited(48, (0..3).map(|_| NestedMessage::new().serialize()))
+        .unwrap();
+    serializer.write_packed_varints(32, 0..1000).unwrap();
+    let out = serializer.into_inner();
+
+    let mut parsed = TestAllTypes::new();
+    parsed.deserialize(&out).unwrap();
+    assert_that!(parsed.optional_int32(), eq(1));
+    let reserialized = parsed.serialize();
+    let nested = WireReader::new(&reserialized).filter(|f| f.unwrap().0 == 48).count();
+    assert_that!(nested, eq(3));
+}
+
+#[test]
+fn set_submessage_from_serialized() {
+    use protobuf::{WireReader, WireValue};
+
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(1));
+    // `optional_nested_message` is field 18; `bb: 5` replaces `bb: 2`.
+    msg.optional_nested_message_set_serialized(b"\x08\x02").unwrap();
+    msg.optional_nested_message_set_serialized(b"\x08\x05").unwrap();
+    assert_that!(msg.optional_int32(), eq(1));
+    let serialized = msg.serialize();
+    let nested: Vec<_> = WireReader::new(&serialized).filter(|f| f.unwrap().0 == 18).collect();
+    assert_that!(nested, eq(vec![Ok((18, WireValue::Delimited(b"\x08\x05")))]));
+
+    assert!(msg.optional_nested_message_set_serialized(b"\x08").is_err());
+    assert_that!(msg.serialize().to_vec(), eq(serialized.to_vec()));
+}
+
+#[test]
+fn concat_and_split() {
+    let mut a = TestAllTypes::new();
+    a.optional_int32_set(Some(1));
+    a.optional_int64_set(Some(2));
+    let mut b = TestAllTypes::new();
+    b.optional_int32_set(Some(3));
+
+    let mut merged = TestAllTypes::new();
+    merged.deserialize(&protobuf::concat_serialized([a.serialize(), b.serialize()])).unwrap();
+    assert_that!(merged.optional_int32(), eq(3));
+    assert_that!(merged.optional_int64(), eq(2));
+
+    let chunks = protobuf::split_fields(&a.serialize()).unwrap();
+    let numbers: Vec<_> = chunks.iter().map(|c| c.field_number).collect();
+    assert_that!(numbers, eq(vec![1, 2]));
+}
+
+#[test]
+fn deserialize_filtered() {
+    let mut msg = TestAllTypes::new();
+    msg.optional_int64_set(Some(42));
+    msg.optional_bytes_mut().set(b"large field");
+
+    // Field 2 is `optional_int64`.
+    let filter = protobuf::FieldFilter::new().allow(2);
+    let mut msg2 = TestAllTypes::new();
+    assert!(msg2.deserialize_filtered(&msg.serialize(), &filter).is_ok());
+    assert_that!(msg2.optional_int64(), eq(42));
+    assert_that!(msg2.optional_bytes(), eq(b""));
+
+    assert!(msg2.deserialize_filtered(b"\x10", &filter).is_err());
+}
+
+#[test]
+fn deserialize_renumbered() {
+    // Data written when `optional_int64` was field 17.
+    let aliases = protobuf::FieldAliases::new().alias(17, 2);
+    let mut msg = TestAllTypes::new();
+    msg.deserialize_renumbered(b"\x88\x01\x2a", &aliases).unwrap();
+    assert_that!(msg.optional_int64(), eq(42));
+}
+
+#[test]
+fn deserialize_with_report() {
+    use protobuf::UndeclaredField;
+
+    // 1: 7, 5000: 1, 5000: 2
+    let mut msg = TestAllTypes::new();
+    let report = msg.deserialize_with_report(b"\x08\x07\xc0\xb8\x02\x01\xc0\xb8\x02\x02").unwrap();
+    assert_that!(report, eq(vec![UndeclaredField { field_number: 5000, count: 2, bytes: 8 }]));
+    assert_that!(msg.optional_int32(), eq(7));
+    // The undeclared fields are kept.
+    assert_that!(msg.serialize().len(), eq(10));
+}
+
+#[test]
+fn deserialize_with_duplicates() {
+    use protobuf::{DuplicateField, WireReader, WireValue};
+
+    // 1: 7, 1: 8, 18: {1: 2}, 18: {}, 31: 1, 31: 2
+    let data = b"\x08\x07\x08\x08\x92\x01\x02\x08\x02\x92\x01\x00\xf8\x01\x01\xf8\x01\x02";
+    let mut msg = TestAllTypes::new();
+    let duplicates = msg.deserialize_with_duplicates(data).unwrap();
+    assert_that!(
+        duplicates,
+        eq(vec![
+            DuplicateField { field_number: 1, count: 2 },
+            DuplicateField { field_number: 18, count: 2 },
+        ])
+    );
+    // The last scalar wins, and the submessages are merged.
+    assert_that!(msg.optional_int32(), eq(8));
+    let serialized = msg.serialize();
+    let nested: Vec<_> = WireReader::new(&serialized).filter(|f| f.unwrap().0 == 18).collect();
+    assert_that!(nested, eq(vec![Ok((18, WireValue::Delimited(b"\x08\x02")))]));
+}
+
+#[test]
+fn deserialize_with_limits() {
+    use protobuf::ParseLimits;
+
+    let mut msg = TestAllTypes::new();
+    msg.optional_string_mut().set("four");
+    let serialized = msg.serialize();
+
+    let mut msg2 = TestAllTypes::new();
+    let limits = ParseLimits::new().max_string_len(4);
+    assert!(msg2.deserialize_with_limits(&serialized, &limits).is_ok());
+    assert_that!(msg2.optional_string(), eq("four"));
+    let limits = ParseLimits::new().max_string_len(3);
+    assert!(msg2.deserialize_with_limits(&serialized, &limits).is_err());
+
+    // `repeated_int32` is field 31.
+    let limits = ParseLimits::new().max_repeated_len(2);
+    assert!(msg2.deserialize_with_limits(b"\xfa\x01\x03\x01\x02\x03", &limits).is_err());
+
+    // The caps apply to submessages too: child { payload { optional_string: "four" } }.
+    let nested = b"\x0a\x08\x12\x06\x72\x04four";
+    let mut msg3 = NestedTestAllTypes::new();
+    assert!(msg3.deserialize_with_limits(nested, &ParseLimits::new().max_string_len(4)).is_ok());
+    assert_that!(&*msg3.serialize(), eq(nested));
+    assert!(msg3.deserialize_with_limits(nested, &ParseLimits::new().max_string_len(3)).is_err());
+}
+
+#[test]
+fn deserialize_lenient() {
+    let mut msg = TestAllTypes::new();
+    msg.optional_int64_set(Some(42));
+    msg.optional_bytes_mut().set(b"cut off");
+    let serialized = msg.serialize();
+
+    let mut msg2 = TestAllTypes::new();
+    let warning = msg2.deserialize_lenient(&serialized[..serialized.len() - 1]).unwrap();
+    assert!(warning.is_some());
+    assert_that!(msg2.optional_int64(), eq(42));
+    assert_that!(msg2.optional_bytes(), eq(b""));
+
+    assert_that!(msg2.deserialize_lenient(&serialized).unwrap(), eq(None));
+    assert_that!(msg2.optional_bytes(), eq(b"cut off"));
+}
+
+#[test]
+fn debug_string() {
+    let mut msg = TestAllTypes::new();
+    msg.optional_int64_set(Some(42));
+    msg.optional_bytes_mut().set(b"0123456789");
+    let options = protobuf::DebugStringOptions { max_bytes_len: 4, ..Default::default() };
+    assert_that!(msg.debug_string(&options), eq("2: 42\n15: \"0123\" # 6 more bytes\n"));
+}
+
+#[test]
+fn full_name_and_type_url() {
+    use unittest_proto::proto2_unittest::TestAllTypes_::NestedMessage;
+
+    assert_that!(TestAllTypes::FULL_NAME, eq("protobuf_unittest.TestAllTypes"));
+    assert_that!(
+        NestedMessage::TYPE_URL,
+        eq("type.googleapis.com/protobuf_unittest.TestAllTypes.NestedMessage")
+    );
+}
+
+#[test]
+fn schema_fingerprint() {
+    use unittest_proto::proto2_unittest::TestAllTypes_::NestedMessage;
+
+    assert_that!(TestAllTypes::SCHEMA_FINGERPRINT, not(eq(NestedMessage::SCHEMA_FINGERPRINT)));
+    assert_that!(TestAllTypes::SCHEMA_FINGERPRINT, not(eq(TestAllExtensions::SCHEMA_FINGERPRINT)));
+}
+
+#[test]
+fn truncate_to_size() {
+    use protobuf::{DroppedField, TruncateError, Truncation, TruncationPolicy};
+
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(1));
+    msg.optional_bytes_mut().set(&[0; 100]);
+    assert_that!(msg.serialize().len(), eq(104));
+
+    let policy = TruncationPolicy::new().droppable(15);
+    assert_that!(msg.truncate_to_size(104, &policy), eq(Ok(Truncation::default())));
+    assert_that!(msg.truncate_to_size(1, &policy), eq(Err(TruncateError::DoesNotFit { size: 2 })));
+    assert_that!(msg.optional_bytes().len(), eq(100));
+
+    assert_that!(
+        msg.truncate_to_size(10, &policy),
+        eq(Ok(Truncation {
+            dropped: vec![DroppedField { field_number: 15, count: 1, bytes: 102 }]
+        }))
+    );
+    assert_that!(msg.optional_int32(), eq(1));
+    assert_that!(msg.optional_bytes_opt().is_set(), eq(false));
+}
+
+#[test]
+fn reserved_fields() {
+    use unittest_proto::proto2_unittest::TestReservedFields;
+
+    assert_that!(TestReservedFields::RESERVED_RANGES, eq(&[2..3, 15..16, 9..12][..]));
+    assert_that!(TestReservedFields::RESERVED_NAMES, eq(&["bar", "baz"][..]));
+    assert_that!(TestAllTypes::RESERVED_NAMES.len(), eq(0));
+}
+
+#[test]
+fn extension_fields() {
+    use protobuf::{ExtensionFields, ExtensionNumberAndValue, WireValue};
+
+    assert_that!(TestAllTypes::EXTENSION_RANGES.len(), eq(0));
+    assert_that!(TestAllExtensions::EXTENSION_RANGES, eq(&[1..536870912][..]));
+    assert_that!(TestAllTypes::RESERVED_RANGES.len(), eq(0));
+
+    // 1: 7 (`optional_int32_extension`), 1000: "hi" (an unknown extension)
+    let mut msg = TestAllExtensions::new();
+    msg.deserialize(b"\x08\x07\xc2\x3e\x02hi").unwrap();
+    let serialized = msg.serialize();
+    let found: Vec<_> = ExtensionFields::new(&serialized, TestAllExtensions::EXTENSION_RANGES)
+        .map(Result::unwrap)
+        .collect();
+    assert_that!(
+        found,
+        eq(vec![
+            ExtensionNumberAndValue { number: 1, value: WireValue::Varint(7) },
+            ExtensionNumberAndValue { number: 1000, value: WireValue::Delimited(b"hi") },
+        ])
+    );
+}
+
+#[test]
+fn match_tag() {
+    use protobuf::{match_tag, FieldNumber, WireReader, WireValue};
+    use unittest_proto::proto2_unittest::TestAllTypes_::FieldNum;
+
+    assert_that!(FieldNum::OptionalInt32.number(), eq(1));
+    assert_that!(FieldNum::from_number(14), eq(Some(FieldNum::OptionalString)));
+    assert_that!(FieldNum::from_number(5000), eq(None));
+
+    let mut msg = TestAllTypes::new();
+    msg.optional_int32_set(Some(7));
+    msg.optional_string_mut().set("hi");
+    let serialized = msg.serialize();
+    let fields: Vec<_> =
+        WireReader::new(&serialized).map(|field| match_tag::<FieldNum>(field.unwrap())).collect();
+    assert_that!(
+        fields,
+        eq(vec![
+            (Some(FieldNum::OptionalInt32), WireValue::Varint(7)),
+            (Some(FieldNum::OptionalString), WireValue::Delimited(b"hi")),
+        ])
+    );
+}
+
+/// A toy codec that stores every byte twice.
+struct Doubling;
+
+impl protobuf::Codec for Doubling {
+    fn compress(&self, input: &[u8], out: &mut Vec<u8>) {
+        out.extend(input.iter().flat_map(|&b| [b, b]));
+    }
+
+    fn decompress(&self, input: &[u8], out: &mut Vec<u8>) -> Result<(), protobuf::ParseError> {
+        let pairs = input.chunks_exact(2);
+        if !pairs.remainder().is_empty() || pairs.clone().any(|p| p[0] != p[1]) {
+            return Err(protobuf::ParseError);
+        }
+        out.extend(pairs.map(|p| p[0]));
+        Ok(())
+    }
+}
+
+#[test]
+fn serialize_deserialize_compressed() {
+    let mut msg = TestAllTypes::new();
+    msg.optional_int64_set(Some(42));
+    msg.optional_bytes_mut().set(b"compressed");
+
+    let compressed = msg.serialize_compressed(&Doubling);
+    assert_that!(compressed.len(), eq(msg.serialize().len() * 2));
+
+    let mut msg2 = TestAllTypes::new();
+    assert!(msg2.deserialize_compressed(&compressed, &Doubling).is_ok());
+    assert_that!(msg2.optional_int64(), eq(42));
+    assert_that!(msg2.optional_bytes(), eq(b"compressed"));
+
+    assert!(msg2.deserialize_compressed(b"\x08", &Doubling).is_err());
+}
+
+#[test]
+fn file_descriptor_bytes() {
+    let bytes = TestAllTypes::file_descriptor_bytes();
+    // Field 1 of `FileDescriptorProto` is the file's name.
+    assert!(bytes.starts_with(b"\x0a\x1egoogle/protobuf/unittest.proto"));
+    // Nested messages are declared by the same file.
+    assert_that!(
+        unittest_proto::proto2_unittest::TestAllTypes_::NestedMessage::file_descriptor_bytes(),
+        eq(bytes)
+    );
+
+    TestAllTypes::register_file_descriptor();
+    assert_that!(
+        protobuf::registered_file_descriptor("google/protobuf/unittest.proto"),
+        eq(Some(bytes))
+    );
+}
@@ -0,0 +1,255 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Shrinking messages to fit a size budget, e.g. for logging pipelines with
+//! a maximum record size.
+//!
+//! Only whole fields are dropped, so the result is always a valid message
+//! (unless a required field is dropped). The size that counts is the
+//! serialized size.
+
+use crate::wire::{check_field_number, WireError, WireReader};
+use std::fmt;
+
+/// Which fields may be dropped to fit a size budget, and in which order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TruncationPolicy {
+    droppable: Vec<u32>,
+}
+
+impl TruncationPolicy {
+    /// Constructs a policy that doesn't allow dropping anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows dropping top-level field `field_number`, e.g. a repeated field
+    /// or a large bytes field.
+    ///
+    /// Fields are dropped in the order they were allowed, and all of one
+    /// field's occurrences are dropped before the next field's. The
+    /// occurrences of a field are dropped last to first, so a repeated field
+    /// keeps its leading elements.
+    ///
+    /// A packed repeated field is a single occurrence on the wire, holding
+    /// all of its elements, so it is dropped whole rather than element by
+    /// element.
+    ///
+    /// # Panics
+    /// Panics if `field_number` is invalid.
+    pub fn droppable(self, field_number: u32) -> Self {
+        self.try_droppable(field_number).expect("invalid field number")
+    }
+
+    /// Like `droppable()`, but returns `WireError::InvalidFieldNumber` instead
+    /// of panicking if `field_number` is invalid.
+    pub fn try_droppable(mut self, field_number: u32) -> Result<Self, WireError> {
+        check_field_number(field_number)?;
+        self.droppable.push(field_number);
+        Ok(self)
+    }
+}
+
+/// The occurrences of a field that were dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DroppedField {
+    pub field_number: u32,
+
+    /// How many occurrences (e.g. repeated elements) were dropped.
+    pub count: usize,
+
+    /// How many serialized bytes the dropped occurrences took.
+    pub bytes: usize,
+}
+
+/// What was dropped to fit a message into its budget.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Truncation {
+    /// The fields that were dropped, in the order they were dropped. Empty if
+    /// the message already fit.
+    pub dropped: Vec<DroppedField>,
+}
+
+/// An error that happened while truncating a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruncateError {
+    /// The input isn't valid wire format.
+    Wire(WireError),
+
+    /// Dropping every droppable field still leaves the message at `size`
+    /// bytes, over the budget.
+    DoesNotFit { size: usize },
+
+    /// The truncated message doesn't parse, e.g. because a required field was
+    /// dropped.
+    Parse,
+}
+
+impl TruncateError {
+    /// Returns a stable code for the error, e.g. for metrics labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Wire(e) => e.code(),
+            Self::DoesNotFit { .. } => "does_not_fit",
+            Self::Parse => "parse",
+        }
+    }
+}
+
+impl fmt::Display for TruncateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Wire(e) => write!(f, "{e}"),
+            Self::DoesNotFit { size } => {
+                write!(f, "Message is still {size} bytes after dropping all droppable fields")
+            }
+            Self::Parse => write!(f, "Truncated message doesn't parse"),
+        }
+    }
+}
+
+impl From<WireError> for TruncateError {
+    fn from(e: WireError) -> Self {
+        Self::Wire(e)
+    }
+}
+
+/// Drops fields from the serialized message `data` as allowed by `policy`
+/// until it is at most `max_bytes` long.
+///
+/// Returns the truncated message, which is `data` itself if it already fits,
+/// and what was dropped.
+pub fn truncate_serialized(
+    data: &[u8],
+    max_bytes: usize,
+    policy: &TruncationPolicy,
+) -> Result<(Vec<u8>, Truncation), TruncateError> {
+    // Every field's number and byte range, in wire order.
+    let mut fields = Vec::new();
+    let mut reader = WireReader::new(data);
+    while !reader.is_empty() {
+        let start = data.len() - reader.remaining().len();
+        let (field_number, _) = reader.read_field()?;
+        fields.push((field_number, start..data.len() - reader.remaining().len()));
+    }
+
+    let mut size = data.len();
+    let mut keep = vec![true; fields.len()];
+    let mut truncation = Truncation::default();
+    for &droppable in &policy.droppable {
+        if size <= max_bytes {
+            break;
+        }
+        let mut dropped = DroppedField { field_number: droppable, count: 0, bytes: 0 };
+        for (i, (field_number, range)) in fields.iter().enumerate().rev() {
+            if size <= max_bytes {
+                break;
+            }
+            if *field_number == droppable && keep[i] {
+                keep[i] = false;
+                size -= range.len();
+                dropped.count += 1;
+                dropped.bytes += range.len();
+            }
+        }
+        if dropped.count > 0 {
+            truncation.dropped.push(dropped);
+        }
+    }
+    if size > max_bytes {
+        return Err(TruncateError::DoesNotFit { size });
+    }
+
+    let mut out = Vec::with_capacity(size);
+    for ((_, range), keep) in fields.iter().zip(keep) {
+        if keep {
+            out.extend_from_slice(&data[range.clone()]);
+        }
+    }
+    Ok((out, truncation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1: 1, 2: "abcdef", 1: 2, 1: 3
+    const DATA: &[u8] = b"\x08\x01\x12\x06abcdef\x08\x02\x08\x03";
+
+    #[test]
+    fn test_fits() {
+        let policy = TruncationPolicy::new();
+        assert_eq!(
+            truncate_serialized(DATA, DATA.len(), &policy),
+            Ok((DATA.to_vec(), Truncation::default()))
+        );
+    }
+
+    #[test]
+    fn test_drop_in_policy_order() {
+        // Dropping the last element of field 1 is enough.
+        let policy = TruncationPolicy::new().droppable(1).droppable(2);
+        assert_eq!(
+            truncate_serialized(DATA, 12, &policy),
+            Ok((
+                b"\x08\x01\x12\x06abcdef\x08\x02".to_vec(),
+                Truncation { dropped: vec![DroppedField { field_number: 1, count: 1, bytes: 2 }] }
+            ))
+        );
+
+        // All of field 1 goes before any of field 2.
+        assert_eq!(
+            truncate_serialized(DATA, 2, &policy),
+            Ok((
+                b"".to_vec(),
+                Truncation {
+                    dropped: vec![
+                        DroppedField { field_number: 1, count: 3, bytes: 6 },
+                        DroppedField { field_number: 2, count: 1, bytes: 8 },
+                    ]
+                }
+            ))
+        );
+        assert_eq!(
+            truncate_serialized(DATA, 6, &TruncationPolicy::new().droppable(2)),
+            Ok((
+                b"\x08\x01\x08\x02\x08\x03".to_vec(),
+                Truncation { dropped: vec![DroppedField { field_number: 2, count: 1, bytes: 8 }] }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let policy = TruncationPolicy::new().droppable(1);
+        assert_eq!(
+            truncate_serialized(DATA, 7, &policy),
+            Err(TruncateError::DoesNotFit { size: 8 })
+        );
+        assert_eq!(truncate_serialized(&DATA[..5], 1, &policy), Err(WireError::Truncated.into()));
+        assert_eq!(
+            TruncateError::DoesNotFit { size: 8 }.to_string(),
+            "Message is still 8 bytes after dropping all droppable fields"
+        );
+        assert_eq!(TruncateError::DoesNotFit { size: 8 }.code(), "does_not_fit");
+        assert_eq!(TruncationPolicy::new().try_droppable(0), Err(WireError::InvalidFieldNumber));
+    }
+
+    #[test]
+    fn test_packed_fields_are_dropped_whole() {
+        // 1: 1, 3: [1, 2, 3] packed
+        let data = b"\x08\x01\x1a\x03\x01\x02\x03";
+        let policy = TruncationPolicy::new().droppable(3);
+        assert_eq!(
+            truncate_serialized(data, 6, &policy),
+            Ok((
+                b"\x08\x01".to_vec(),
+                Truncation { dropped: vec![DroppedField { field_number: 3, count: 1, bytes: 5 }] }
+            ))
+        );
+    }
+}
@@ -37,10 +37,32 @@ pub struct Arena {
     _not_sync: PhantomData<UnsafeCell<()>>,
 }
 
+/// Statistics about an arena and the arenas fused with it, e.g. for services
+/// that monitor their memory use and tune initial block sizes.
+///
+/// Must match `upb_ArenaStats` in `upb/mem/arena.h`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArenaStats {
+    /// How many arenas are fused together, at least 1.
+    pub fused_count: usize,
+
+    /// How many blocks all of them allocated.
+    pub block_count: usize,
+
+    /// How many bytes those blocks take.
+    pub space_allocated: usize,
+
+    /// How many bytes are left in this arena's current block. Space left
+    /// over in earlier blocks isn't tracked.
+    pub current_block_unused: usize,
+}
+
 extern "C" {
     // `Option<NonNull<T: Sized>>` is ABI-compatible with `*mut T`
     fn upb_Arena_New() -> Option<RawArena>;
     fn upb_Arena_Free(arena: RawArena);
+    fn upb_Arena_GetStats(arena: RawArena, stats: *mut ArenaStats);
     fn upb_Arena_Malloc(arena: RawArena, size: usize) -> *mut u8;
     fn upb_Arena_Realloc(arena: RawArena, ptr: *mut u8, old: usize, new: usize) -> *mut u8;
 }
@@ -70,6 +92,35 @@ impl Arena {
         self.raw
     }
 
+    /// Releases ownership of the arena without freeing it. The caller has to
+    /// free it with `upb_Arena_Free`, or pass it back to `from_raw()`.
+    #[inline]
+    pub fn into_raw(self) -> RawArena {
+        let raw = self.raw;
+        std::mem::forget(self);
+        raw
+    }
+
+    /// Takes ownership of an arena, freeing it when dropped.
+    ///
+    /// # Safety
+    ///
+    /// - `raw` must be a valid UPB arena that the caller owns, e.g. from
+    ///   `upb_Arena_New` or `into_raw()`, and nothing else may free it.
+    #[inline]
+    pub unsafe fn from_raw(raw: RawArena) -> Self {
+        Self { raw, _not_sync: PhantomData }
+    }
+
+    /// Returns statistics about this arena and the arenas fused with it.
+    pub fn stats(&self) -> ArenaStats {
+        let mut stats = ArenaStats::default();
+        // SAFETY: `self.raw` is a valid UPB arena, and `stats` matches
+        // `upb_ArenaStats`.
+        unsafe { upb_Arena_GetStats(self.raw, &mut stats) };
+        stats
+    }
+
     /// Allocates some memory on the arena.
     ///
     /// # Safety
@@ -134,6 +185,11 @@ impl Drop for Arena {
     }
 }
 
+/// The `upb_Encode` option that writes map entries in key order.
+///
+/// Must match `kUpb_EncodeOption_Deterministic` in `upb/wire/encode.h`.
+pub const ENCODE_OPTION_DETERMINISTIC: i32 = 1;
+
 /// Serialized Protobuf wire format data.
 ///
 /// It's typically produced by `<Message>::serialize()`.
@@ -171,6 +227,30 @@ impl Deref for SerializedData {
     }
 }
 
+impl AsRef<[u8]> for SerializedData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for SerializedData {
+    /// Copies `data` into a new arena.
+    fn from(data: Vec<u8>) -> Self {
+        let arena = Arena::new();
+        // SAFETY: the alignment of `[u8]` is less than `UPB_MALLOC_ALIGN`.
+        let new_alloc = unsafe { arena.alloc(Layout::for_value(&data[..])) };
+        let start: *mut u8 = new_alloc.as_mut_ptr().cast();
+        // SAFETY:
+        // - `new_alloc` is writeable for `data.len()` bytes.
+        // - After the copy, `arena` owns `data.len()` initialized bytes at
+        //   `start`, which nothing mutates.
+        unsafe {
+            data.as_ptr().copy_to_nonoverlapping(start, data.len());
+            Self::from_raw_parts(arena, NonNull::new_unchecked(start), data.len())
+        }
+    }
+}
+
 impl fmt::Debug for SerializedData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Debug::fmt(self.deref(), f)
@@ -233,6 +313,21 @@ impl<'msg> MutatorMessageRef<'msg> {
     }
 }
 
+extern "C" {
+    fn upb_Message_GetUnknown(msg: RawMessage, len: *mut usize) -> *const u8;
+}
+
+/// Returns whether `msg` holds unknown fields, for generated `is_default()`.
+///
+/// # Safety
+/// `msg` must point to a valid upb message.
+pub unsafe fn has_unknown_fields(msg: RawMessage) -> bool {
+    let mut len = 0;
+    // SAFETY: `msg` is valid, as promised by the caller.
+    unsafe { upb_Message_GetUnknown(msg, &mut len) };
+    len != 0
+}
+
 pub fn copy_bytes_in_arena_if_needed_by_runtime<'a>(
     msg_ref: MutatorMessageRef<'a>,
     val: &'a [u8],
@@ -261,6 +356,35 @@ mod tests {
         drop(arena);
     }
 
+    #[test]
+    fn test_serialized_data_from_vec() {
+        assert_eq!(&*SerializedData::from(b"Hello world".to_vec()), b"Hello world");
+    }
+
+    #[test]
+    fn test_arena_stats() {
+        let arena = Arena::new();
+        let before = arena.stats();
+        assert_eq!(before.fused_count, 1);
+        // Larger than any default block, so it needs a new one.
+        unsafe { arena.alloc(Layout::from_size_align(1 << 20, 8).unwrap()) };
+        let after = arena.stats();
+        assert_eq!(after.block_count, before.block_count + 1);
+        assert!(after.space_allocated >= before.space_allocated + (1 << 20));
+    }
+
+    #[test]
+    fn test_arena_raw_roundtrip() {
+        let arena = Arena::new();
+        unsafe { arena.alloc(Layout::from_size_align(1 << 20, 8).unwrap()) };
+        let stats = arena.stats();
+        let raw = arena.into_raw();
+        // SAFETY: `raw` was released by `into_raw()` and isn't freed elsewhere.
+        let arena = unsafe { Arena::from_raw(raw) };
+        assert_eq!(arena.raw(), raw);
+        assert_eq!(arena.stats(), stats);
+    }
+
     #[test]
     fn test_serialized_data_roundtrip() {
         let arena = Arena::new();
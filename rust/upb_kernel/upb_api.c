@@ -9,3 +9,4 @@
 #define UPB_BUILD_API
 
 #include "upb/mem/arena.h" // IWYU pragma: keep
+#include "upb/message/message.h" // IWYU pragma: keep
@@ -0,0 +1,785 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Low-level access to the protobuf wire format.
+//!
+//! Wire format bytes don't say what a field means: a `Delimited` value may be
+//! a string, bytes, a submessage or a packed repeated field, and a `Fixed64`
+//! may be a `double` or a `fixed64`. Interpreting values is up to the caller.
+//!
+//! The reader never panics on malformed input, never allocates and never
+//! recurses. Its limits are:
+//! - Varints are at most 10 bytes long. As in the kernels, bits past the 64th
+//!   are dropped rather than reported.
+//! - Field numbers are between 1 and [`MAX_FIELD_NUMBER`].
+//! - A length prefix can't exceed the remaining input, so no length, however
+//!   large, makes the reader allocate or read out of bounds.
+//! - Groups nest at most 100 deep.
+//!
+//! `WIRE_CORPUS` lists the inputs these limits are tested with.
+
+use std::fmt;
+
+/// The largest valid field number.
+pub const MAX_FIELD_NUMBER: u32 = (1 << 29) - 1;
+
+/// Returns `WireError::InvalidFieldNumber` unless `field_number` is between 1
+/// and [`MAX_FIELD_NUMBER`]. The APIs that panic on an invalid field number
+/// check it with this first.
+pub fn check_field_number(field_number: u32) -> Result<(), WireError> {
+    if (1..=MAX_FIELD_NUMBER).contains(&field_number) {
+        Ok(())
+    } else {
+        Err(WireError::InvalidFieldNumber)
+    }
+}
+
+/// How deeply groups may nest before [`WireReader`] gives up, matching the
+/// default recursion limit of the kernels.
+const MAX_GROUP_DEPTH: usize = 100;
+
+/// Appends `v` to `out` as a base-128 varint.
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+/// The encoding of a field's value, as given by the low 3 bits of its tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireType {
+    Varint,
+    Fixed64,
+    Delimited,
+    StartGroup,
+    EndGroup,
+    Fixed32,
+}
+
+impl WireType {
+    /// Converts the low 3 bits of a tag into a wire type.
+    pub fn from_bits(bits: u8) -> Result<Self, WireError> {
+        Ok(match bits {
+            0 => WireType::Varint,
+            1 => WireType::Fixed64,
+            2 => WireType::Delimited,
+            3 => WireType::StartGroup,
+            4 => WireType::EndGroup,
+            5 => WireType::Fixed32,
+            _ => return Err(WireError::InvalidWireType(bits)),
+        })
+    }
+
+    /// Returns the low 3 bits of a tag with this wire type.
+    pub fn bits(self) -> u8 {
+        match self {
+            WireType::Varint => 0,
+            WireType::Fixed64 => 1,
+            WireType::Delimited => 2,
+            WireType::StartGroup => 3,
+            WireType::EndGroup => 4,
+            WireType::Fixed32 => 5,
+        }
+    }
+}
+
+/// A single field value, borrowing from the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    /// The payload of a length-delimited field, without its length prefix.
+    Delimited(&'a [u8]),
+    /// The fields of a group, without its start and end tags.
+    Group(&'a [u8]),
+}
+
+impl WireValue<'_> {
+    /// Returns the wire type this value is encoded with.
+    pub fn wire_type(&self) -> WireType {
+        match self {
+            WireValue::Varint(_) => WireType::Varint,
+            WireValue::Fixed64(_) => WireType::Fixed64,
+            WireValue::Fixed32(_) => WireType::Fixed32,
+            WireValue::Delimited(_) => WireType::Delimited,
+            WireValue::Group(_) => WireType::StartGroup,
+        }
+    }
+}
+
+/// Reads fields from serialized message bytes.
+///
+/// Iterating yields `(field_number, value)` pairs in wire order, and stops
+/// after the first error.
+#[derive(Debug, Clone)]
+pub struct WireReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> WireReader<'a> {
+    /// Reads the fields of `data`, e.g. the output of `<Message>::serialize()`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Returns whether all input has been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the input that hasn't been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Reads a base-128 varint.
+    pub fn read_varint(&mut self) -> Result<u64, WireError> {
+        let mut v = 0;
+        for (i, &b) in self.data.iter().take(10).enumerate() {
+            v |= u64::from(b & 0x7f) << (7 * i);
+            if b < 0x80 {
+                self.data = &self.data[i + 1..];
+                return Ok(v);
+            }
+        }
+        Err(if self.data.len() < 10 { WireError::Truncated } else { WireError::MalformedVarint })
+    }
+
+    /// Reads a tag, returning its field number and wire type.
+    pub fn read_tag(&mut self) -> Result<(u32, WireType), WireError> {
+        let tag = self.read_varint()?;
+        let wire_type = WireType::from_bits((tag & 7) as u8)?;
+        match u32::try_from(tag >> 3) {
+            Ok(field_number @ 1..=MAX_FIELD_NUMBER) => Ok((field_number, wire_type)),
+            _ => Err(WireError::InvalidFieldNumber),
+        }
+    }
+
+    /// Reads a little-endian `u32`.
+    pub fn read_fixed32(&mut self) -> Result<u32, WireError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    /// Reads a little-endian `u64`.
+    pub fn read_fixed64(&mut self) -> Result<u64, WireError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    /// Reads a varint length prefix and the bytes it covers.
+    pub fn read_delimited(&mut self) -> Result<&'a [u8], WireError> {
+        let len = self.read_varint()?;
+        let len = usize::try_from(len).map_err(|_| WireError::Truncated)?;
+        self.read_bytes(len)
+    }
+
+    /// Reads the value of a field whose tag has just been read.
+    ///
+    /// An `EndGroup` tag can't start a value and is reported as unmatched.
+    pub fn read_value(
+        &mut self,
+        field_number: u32,
+        wire_type: WireType,
+    ) -> Result<WireValue<'a>, WireError> {
+        Ok(match wire_type {
+            WireType::Varint => WireValue::Varint(self.read_varint()?),
+            WireType::Fixed64 => WireValue::Fixed64(self.read_fixed64()?),
+            WireType::Fixed32 => WireValue::Fixed32(self.read_fixed32()?),
+            WireType::Delimited => WireValue::Delimited(self.read_delimited()?),
+            WireType::StartGroup => WireValue::Group(self.read_group(field_number)?),
+            WireType::EndGroup => return Err(WireError::UnmatchedEndGroup),
+        })
+    }
+
+    /// Reads a whole field.
+    pub fn read_field(&mut self) -> Result<(u32, WireValue<'a>), WireError> {
+        let (field_number, wire_type) = self.read_tag()?;
+        Ok((field_number, self.read_value(field_number, wire_type)?))
+    }
+
+    /// Skips the value of a field whose tag has just been read.
+    pub fn skip_value(&mut self, field_number: u32, wire_type: WireType) -> Result<(), WireError> {
+        self.read_value(field_number, wire_type).map(|_| ())
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        if len > self.data.len() {
+            return Err(WireError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], WireError> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.read_bytes(N)?);
+        Ok(out)
+    }
+
+    /// Reads the contents of a group up to the `EndGroup` tag matching
+    /// `field_number`, which is consumed.
+    fn read_group(&mut self, field_number: u32) -> Result<&'a [u8], WireError> {
+        let start = self.data;
+        // The field numbers of the groups that are still open, innermost last.
+        let mut open = [0; MAX_GROUP_DEPTH];
+        open[0] = field_number;
+        let mut depth = 1;
+        loop {
+            let before = self.data;
+            let (number, wire_type) = self.read_tag()?;
+            match wire_type {
+                WireType::StartGroup => {
+                    if depth == MAX_GROUP_DEPTH {
+                        return Err(WireError::RecursionLimit);
+                    }
+                    open[depth] = number;
+                    depth += 1;
+                }
+                WireType::EndGroup => {
+                    depth -= 1;
+                    if open[depth] != number {
+                        return Err(WireError::UnmatchedEndGroup);
+                    }
+                    if depth == 0 {
+                        return Ok(&start[..start.len() - before.len()]);
+                    }
+                }
+                _ => self.skip_value(number, wire_type)?,
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for WireReader<'a> {
+    type Item = Result<(u32, WireValue<'a>), WireError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let field = self.read_field();
+        if field.is_err() {
+            self.data = &[];
+        }
+        Some(field)
+    }
+}
+
+/// Writes fields in the wire format, e.g. to append fields to the output of
+/// `<Message>::serialize()`.
+///
+/// Appending a field to a serialized message has the same effect as merging
+/// it in: a singular field replaces the earlier value, a repeated field gets
+/// more elements and a submessage field is merged. The packed writers take
+/// any cloneable iterator, so large computed arrays can be emitted as
+/// repeated fields without first being copied into a message.
+///
+/// All methods except `try_write_field()` panic if the field number is
+/// invalid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WireWriter {
+    out: Vec<u8>,
+}
+
+impl WireWriter {
+    /// Constructs a writer with an empty output.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a writer that appends to `out`.
+    pub fn from_vec(out: Vec<u8>) -> Self {
+        Self { out }
+    }
+
+    /// Returns the output written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.out
+    }
+
+    /// Returns the output.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.out
+    }
+
+    /// Appends already serialized fields.
+    pub(crate) fn write_raw(&mut self, data: &[u8]) {
+        self.out.extend_from_slice(data);
+    }
+
+    /// Writes a tag.
+    pub fn write_tag(&mut self, field_number: u32, wire_type: WireType) {
+        check_field_number(field_number).expect("invalid field number");
+        write_varint(&mut self.out, u64::from(field_number) << 3 | u64::from(wire_type.bits()));
+    }
+
+    /// Writes a field with any wire type, e.g. one read by
+    /// `WireReader::read_field()`.
+    pub fn write_field(&mut self, field_number: u32, value: WireValue<'_>) {
+        match value {
+            WireValue::Varint(v) => self.write_varint(field_number, v),
+            WireValue::Fixed64(v) => self.write_fixed64(field_number, v),
+            WireValue::Fixed32(v) => self.write_fixed32(field_number, v),
+            WireValue::Delimited(v) => self.write_delimited(field_number, v),
+            WireValue::Group(fields) => {
+                self.write_tag(field_number, WireType::StartGroup);
+                self.write_raw(fields);
+                self.write_tag(field_number, WireType::EndGroup);
+            }
+        }
+    }
+
+    /// Like `write_field()`, but returns `WireError::InvalidFieldNumber`
+    /// instead of panicking if `field_number` is invalid, for field numbers
+    /// that come from untrusted input. Nothing is written then.
+    pub fn try_write_field(
+        &mut self,
+        field_number: u32,
+        value: WireValue<'_>,
+    ) -> Result<(), WireError> {
+        check_field_number(field_number)?;
+        self.write_field(field_number, value);
+        Ok(())
+    }
+
+    /// Writes a varint field. Negative `int32` and `int64` values are passed
+    /// sign-extended (`v as i64 as u64`), `sint32` and `sint64` ones
+    /// zigzag-encoded.
+    pub fn write_varint(&mut self, field_number: u32, value: u64) {
+        self.write_tag(field_number, WireType::Varint);
+        write_varint(&mut self.out, value);
+    }
+
+    /// Writes a `fixed32`, `sfixed32` or `float` (as bits) field.
+    pub fn write_fixed32(&mut self, field_number: u32, value: u32) {
+        self.write_tag(field_number, WireType::Fixed32);
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a `fixed64`, `sfixed64` or `double` (as bits) field.
+    pub fn write_fixed64(&mut self, field_number: u32, value: u64) {
+        self.write_tag(field_number, WireType::Fixed64);
+        self.out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a string, bytes or (serialized) submessage field.
+    pub fn write_delimited(&mut self, field_number: u32, value: &[u8]) {
+        self.write_tag(field_number, WireType::Delimited);
+        write_varint(&mut self.out, value.len() as u64);
+        self.out.extend_from_slice(value);
+    }
+
+    /// Writes `values` as a packed repeated varint field. Nothing is written
+    /// if `values` is empty.
+    ///
+    /// `values` is iterated twice: once to size the field, once to write it.
+    pub fn write_packed_varints<I>(&mut self, field_number: u32, values: I)
+    where
+        I: IntoIterator<Item = u64>,
+        I::IntoIter: Clone,
+    {
+        let values = values.into_iter();
+        let len: usize = values.clone().map(varint_len).sum();
+        if len > 0 {
+            self.write_tag(field_number, WireType::Delimited);
+            write_varint(&mut self.out, len as u64);
+            self.out.reserve(len);
+            values.for_each(|v| write_varint(&mut self.out, v));
+        }
+    }
+
+    /// Writes `values` as a packed repeated `fixed32`, `sfixed32` or `float`
+    /// field. Nothing is written if `values` is empty.
+    pub fn write_packed_fixed32<I>(&mut self, field_number: u32, values: I)
+    where
+        I: IntoIterator<Item = u32>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let values = values.into_iter();
+        self.write_packed_fixed(field_number, values.len() * 4, values.map(u32::to_le_bytes));
+    }
+
+    /// Writes `values` as a packed repeated `fixed64`, `sfixed64` or
+    /// `double` field. Nothing is written if `values` is empty.
+    pub fn write_packed_fixed64<I>(&mut self, field_number: u32, values: I)
+    where
+        I: IntoIterator<Item = u64>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let values = values.into_iter();
+        self.write_packed_fixed(field_number, values.len() * 8, values.map(u64::to_le_bytes));
+    }
+
+    fn write_packed_fixed<const N: usize>(
+        &mut self,
+        field_number: u32,
+        len: usize,
+        values: impl Iterator<Item = [u8; N]>,
+    ) {
+        if len > 0 {
+            self.write_tag(field_number, WireType::Delimited);
+            write_varint(&mut self.out, len as u64);
+            self.out.reserve(len);
+            values.for_each(|v| self.out.extend_from_slice(&v));
+        }
+    }
+}
+
+/// Returns how many bytes `v` takes as a varint.
+pub(crate) fn varint_len(v: u64) -> usize {
+    // Every 7 significant bits take a byte, and 0 still takes one.
+    (64 - (v | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// Copies the serialized message `data` with every occurrence of field
+/// `field_number` dropped and `value` appended as the field's only
+/// (length-delimited) occurrence. Called by generated `<field>_set_serialized()`
+/// accessors.
+pub fn replace_delimited_field(
+    data: &[u8],
+    field_number: u32,
+    value: &[u8],
+) -> Result<Vec<u8>, WireError> {
+    let mut out = WireWriter::from_vec(Vec::with_capacity(data.len() + value.len() + 10));
+    let mut reader = WireReader::new(data);
+    while !reader.is_empty() {
+        let start = reader.remaining();
+        let (number, _) = reader.read_field()?;
+        if number != field_number {
+            out.write_raw(&start[..start.len() - reader.remaining().len()]);
+        }
+    }
+    out.write_delimited(field_number, value);
+    Ok(out.into_vec())
+}
+
+/// The field numbers of a message type, implemented by the generated
+/// `FieldNum` enum in the message's module (e.g. `MyMessage_::FieldNum`).
+pub trait FieldNumber: Copy {
+    /// Returns the field with number `number`, if the message has one.
+    fn from_number(number: u32) -> Option<Self>;
+
+    /// Returns the field's number.
+    fn number(self) -> u32;
+}
+
+/// Looks up the field number of a field read by [`WireReader`] in `F`, so
+/// that partial decoders can match on named fields instead of raw numbers:
+///
+/// ```ignore
+/// for field in WireReader::new(data) {
+///     match match_tag::<MyMessage_::FieldNum>(field?) {
+///         (Some(FieldNum::Id), WireValue::Varint(id)) => ...,
+///         _ => {}
+///     }
+/// }
+/// ```
+///
+/// Fields that `F` doesn't know, e.g. unknown fields or extensions, are
+/// `None`.
+pub fn match_tag<F: FieldNumber>(
+    (field_number, value): (u32, WireValue<'_>),
+) -> (Option<F>, WireValue<'_>) {
+    (F::from_number(field_number), value)
+}
+
+/// An error that happened while reading wire format bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    /// The input ends in the middle of a field.
+    Truncated,
+
+    /// A varint is longer than 10 bytes.
+    MalformedVarint,
+
+    /// A tag has field number 0 or one above [`MAX_FIELD_NUMBER`].
+    InvalidFieldNumber,
+
+    /// A tag has wire type 6 or 7.
+    InvalidWireType(u8),
+
+    /// An `EndGroup` tag doesn't close the innermost open group.
+    UnmatchedEndGroup,
+
+    /// Groups are nested too deeply.
+    RecursionLimit,
+}
+
+impl WireError {
+    /// Returns a short code for the error that never changes, e.g.
+    /// `"truncated"`, to use as a metrics label or to match on in logs. The
+    /// errors wrapping a `WireError` report its code as theirs.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Truncated => "truncated",
+            Self::MalformedVarint => "malformed_varint",
+            Self::InvalidFieldNumber => "invalid_field_number",
+            Self::InvalidWireType(_) => "invalid_wire_type",
+            Self::UnmatchedEndGroup => "unmatched_end_group",
+            Self::RecursionLimit => "recursion_limit",
+        }
+    }
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WireError::Truncated => write!(f, "Input ends in the middle of a field"),
+            WireError::MalformedVarint => write!(f, "Varint is longer than 10 bytes"),
+            WireError::InvalidFieldNumber => write!(f, "Tag has an invalid field number"),
+            WireError::InvalidWireType(bits) => write!(f, "Tag has invalid wire type {bits}"),
+            WireError::UnmatchedEndGroup => write!(f, "End group tag doesn't match a start group"),
+            WireError::RecursionLimit => write!(f, "Groups are nested too deeply"),
+        }
+    }
+}
+
+/// A [`WireError`] along with the path of field numbers leading to the
+/// submessage it happened in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WirePathError {
+    error: WireError,
+    path: Vec<u32>,
+}
+
+impl WirePathError {
+    /// Returns the underlying error.
+    pub fn error(&self) -> WireError {
+        self.error
+    }
+
+    /// Returns the underlying error's code, see [`WireError::code`].
+    pub fn code(&self) -> &'static str {
+        self.error.code()
+    }
+
+    /// Returns the field numbers of the submessages enclosing the error,
+    /// outermost first. Empty if the error is in the top-level message.
+    pub fn path(&self) -> &[u32] {
+        &self.path
+    }
+
+    /// Records that the error happened inside field `field_number`, as the
+    /// error bubbles up out of a submessage.
+    pub(crate) fn in_field(mut self, field_number: u32) -> Self {
+        self.path.insert(0, field_number);
+        self
+    }
+}
+
+impl From<WireError> for WirePathError {
+    fn from(error: WireError) -> Self {
+        Self { error, path: Vec::new() }
+    }
+}
+
+impl fmt::Display for WirePathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some((first, rest)) = self.path.split_first() {
+            write!(f, " at field path {first}")?;
+            for field_number in rest {
+                write!(f, ".{field_number}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_fields() {
+        // 1: 150, 2: "ab", 3: fixed32 1, 4: fixed64 2
+        let data = b"\x08\x96\x01\x12\x02ab\x1d\x01\0\0\0\x21\x02\0\0\0\0\0\0\0";
+        let fields: Vec<_> = WireReader::new(data).collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            fields,
+            [
+                (1, WireValue::Varint(150)),
+                (2, WireValue::Delimited(b"ab")),
+                (3, WireValue::Fixed32(1)),
+                (4, WireValue::Fixed64(2)),
+            ]
+        );
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum TestFieldNum {
+        Id = 1,
+        Name = 2,
+    }
+
+    impl FieldNumber for TestFieldNum {
+        fn from_number(number: u32) -> Option<Self> {
+            match number {
+                1 => Some(Self::Id),
+                2 => Some(Self::Name),
+                _ => None,
+            }
+        }
+
+        fn number(self) -> u32 {
+            self as u32
+        }
+    }
+
+    #[test]
+    fn test_match_tag() {
+        // 1: 150, 2: "ab", 3: 1
+        let data = b"\x08\x96\x01\x12\x02ab\x18\x01";
+        let fields: Vec<_> =
+            WireReader::new(data).map(|field| match_tag::<TestFieldNum>(field.unwrap())).collect();
+        assert_eq!(
+            fields,
+            [
+                (Some(TestFieldNum::Id), WireValue::Varint(150)),
+                (Some(TestFieldNum::Name), WireValue::Delimited(b"ab")),
+                (None, WireValue::Varint(1)),
+            ]
+        );
+        assert_eq!(TestFieldNum::Name.number(), 2);
+    }
+
+    #[test]
+    fn test_write_fields() {
+        let mut writer = WireWriter::new();
+        writer.write_varint(1, 150);
+        writer.write_delimited(2, b"ab");
+        writer.write_fixed32(3, 1);
+        writer.write_fixed64(4, 2);
+        assert_eq!(
+            writer.as_bytes(),
+            b"\x08\x96\x01\x12\x02ab\x1d\x01\0\0\0\x21\x02\0\0\0\0\0\0\0"
+        );
+
+        let mut writer = WireWriter::from_vec(b"\x08\x01".to_vec());
+        writer.write_varint(1, -1i64 as u64);
+        assert_eq!(writer.into_vec(), b"\x08\x01\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01");
+
+        let mut writer = WireWriter::new();
+        writer.write_field(1, WireValue::Fixed32(1));
+        assert_eq!(writer.try_write_field(2, WireValue::Group(b"\x08\x01")), Ok(()));
+        assert_eq!(
+            writer.try_write_field(0, WireValue::Varint(1)),
+            Err(WireError::InvalidFieldNumber)
+        );
+        assert_eq!(
+            writer.try_write_field(MAX_FIELD_NUMBER + 1, WireValue::Varint(1)),
+            Err(WireError::InvalidFieldNumber)
+        );
+        assert_eq!(writer.as_bytes(), b"\x0d\x01\0\0\0\x13\x08\x01\x14");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid field number")]
+    fn test_write_invalid_field_number() {
+        WireWriter::new().write_varint(0, 1);
+    }
+
+    #[test]
+    fn test_write_packed() {
+        let values = [1u32, 300, 0];
+        let mut writer = WireWriter::new();
+        writer.write_packed_varints(1, values.iter().map(|&v| u64::from(v)));
+        writer.write_packed_fixed32(2, values);
+        writer.write_packed_fixed64(3, [7]);
+        writer.write_packed_varints(4, []);
+        writer.write_packed_fixed32(5, []);
+        assert_eq!(
+            writer.as_bytes(),
+            b"\x0a\x04\x01\xac\x02\x00\
+              \x12\x0c\x01\0\0\0\x2c\x01\0\0\0\0\0\0\
+              \x1a\x08\x07\0\0\0\0\0\0\0"
+        );
+
+        let mut decoded = Vec::new();
+        crate::packed::decode_packed_varints(&writer.as_bytes()[2..6], &mut decoded).unwrap();
+        assert_eq!(decoded, [1, 300, 0]);
+    }
+
+    #[test]
+    fn test_varint_len() {
+        for v in [0, 1, 127, 128, 16383, 16384, u64::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, v);
+            assert_eq!(varint_len(v), out.len(), "{v}");
+        }
+    }
+
+    #[test]
+    fn test_replace_delimited_field() {
+        // 1: "a", 2: 1, 1: "b"
+        let data = b"\x0a\x01a\x10\x01\x0a\x01b";
+        assert_eq!(replace_delimited_field(data, 1, b"xy"), Ok(b"\x10\x01\x0a\x02xy".to_vec()));
+        assert_eq!(replace_delimited_field(b"", 1, b""), Ok(b"\x0a\x00".to_vec()));
+        assert_eq!(replace_delimited_field(b"\x0a\x05", 1, b""), Err(WireError::Truncated));
+    }
+
+    #[test]
+    fn test_read_varint() {
+        assert_eq!(WireReader::new(b"\0").read_varint(), Ok(0));
+        assert_eq!(
+            WireReader::new(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01").read_varint(),
+            Ok(u64::MAX)
+        );
+        assert_eq!(WireReader::new(b"\x80").read_varint(), Err(WireError::Truncated));
+        assert_eq!(WireReader::new(&[0x80; 11]).read_varint(), Err(WireError::MalformedVarint));
+    }
+
+    #[test]
+    fn test_groups() {
+        // 1: { 2: { 3: 1 } }, 4: 5
+        let data = b"\x0b\x13\x18\x01\x14\x0c\x20\x05";
+        let mut reader = WireReader::new(data);
+        assert_eq!(reader.next(), Some(Ok((1, WireValue::Group(b"\x13\x18\x01\x14")))));
+        assert_eq!(reader.next(), Some(Ok((4, WireValue::Varint(5)))));
+        assert_eq!(reader.next(), None);
+
+        assert_eq!(WireReader::new(b"\x0b\x14").read_field(), Err(WireError::UnmatchedEndGroup));
+        assert_eq!(WireReader::new(b"\x0c").read_field(), Err(WireError::UnmatchedEndGroup));
+        assert_eq!(WireReader::new(b"\x0b\x08\x01").read_field(), Err(WireError::Truncated));
+        assert_eq!(WireReader::new(&[0x0b; 101]).read_field(), Err(WireError::RecursionLimit));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(WireReader::new(b"\x00").read_tag(), Err(WireError::InvalidFieldNumber));
+        assert_eq!(WireReader::new(b"\x0e").read_tag(), Err(WireError::InvalidWireType(6)));
+        assert_eq!(
+            WireReader::new(b"\xf8\xff\xff\xff\x1f").read_tag(),
+            Err(WireError::InvalidFieldNumber)
+        );
+        assert_eq!(WireReader::new(b"\x12\x05ab").read_field(), Err(WireError::Truncated));
+        assert_eq!(WireReader::new(b"\x1d\x01").read_field(), Err(WireError::Truncated));
+
+        let mut reader = WireReader::new(b"\x08\x01\x12");
+        assert_eq!(reader.next(), Some(Ok((1, WireValue::Varint(1)))));
+        assert_eq!(reader.next(), Some(Err(WireError::Truncated)));
+        assert_eq!(reader.next(), None);
+
+        assert_eq!(WireError::InvalidWireType(6).code(), "invalid_wire_type");
+        assert_eq!(WirePathError::from(WireError::Truncated).in_field(1).code(), "truncated");
+    }
+
+    #[test]
+    fn test_path_error() {
+        let e = WirePathError::from(WireError::Truncated);
+        assert_eq!(e.path(), []);
+        assert_eq!(e.to_string(), "Input ends in the middle of a field");
+
+        let e = e.in_field(1).in_field(3);
+        assert_eq!(e.error(), WireError::Truncated);
+        assert_eq!(e.path(), [3, 1]);
+        assert_eq!(e.to_string(), "Input ends in the middle of a field at field path 3.1");
+    }
+}
@@ -0,0 +1,151 @@
+// Protocol Buffers - Google's data interchange format
+// Copyright 2023 Google LLC.  All rights reserved.
+//
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file or at
+// https://developers.google.com/open-source/licenses/bsd
+
+//! Malformed and pathological wire format inputs, for testing decoders.
+//!
+//! [`WireReader`](crate::WireReader) is tested against every entry, and the
+//! corpus is public so that decoders built on top of it can run the same
+//! inputs through their own code.
+
+use crate::wire::WireError;
+
+/// An input and how [`WireReader`](crate::WireReader) handles it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireCorpusEntry {
+    /// A short description of the input, e.g. `"varint_11_bytes"`.
+    pub name: &'static str,
+
+    pub data: &'static [u8],
+
+    /// The first error reading every top-level field of `data` reports, or
+    /// `None` if `data` is valid (if odd).
+    pub error: Option<WireError>,
+}
+
+/// `depth` nested groups of field 1.
+const fn nested_groups<const N: usize>(depth: usize) -> [u8; N] {
+    let mut out = [0x0c; N];
+    let mut i = 0;
+    while i < depth {
+        out[i] = 0x0b;
+        i += 1;
+    }
+    out
+}
+
+const GROUPS_AT_LIMIT: [u8; 200] = nested_groups(100);
+const GROUPS_PAST_LIMIT: [u8; 202] = nested_groups(101);
+
+const fn entry(
+    name: &'static str,
+    data: &'static [u8],
+    error: Option<WireError>,
+) -> WireCorpusEntry {
+    WireCorpusEntry { name, data, error }
+}
+
+/// The corpus. Entries are only ever added, so downstream expectations keyed
+/// by `name` stay valid.
+pub const WIRE_CORPUS: &[WireCorpusEntry] = &[
+    entry("empty", b"", None),
+    entry("tag_truncated", b"\x80", Some(WireError::Truncated)),
+    entry("tag_field_number_zero", b"\x00", Some(WireError::InvalidFieldNumber)),
+    entry(
+        "tag_field_number_too_large",
+        b"\x80\x80\x80\x80\x20",
+        Some(WireError::InvalidFieldNumber),
+    ),
+    entry(
+        "tag_field_number_over_u32",
+        b"\xf8\xff\xff\xff\xff\x01",
+        Some(WireError::InvalidFieldNumber),
+    ),
+    entry("wire_type_6", b"\x0e", Some(WireError::InvalidWireType(6))),
+    entry("wire_type_7", b"\x0f", Some(WireError::InvalidWireType(7))),
+    entry("varint_truncated", b"\x08\xff", Some(WireError::Truncated)),
+    entry(
+        "varint_11_bytes",
+        b"\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01",
+        Some(WireError::MalformedVarint),
+    ),
+    entry("varint_10_bytes_max", b"\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01", None),
+    // Bits past the 64th are dropped, as the kernels do.
+    entry("varint_10th_byte_overflows", b"\x08\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f", None),
+    entry("varint_overlong_zero", b"\x08\x80\x80\x80\x80\x00", None),
+    entry("delimited_past_end", b"\x12\x05ab", Some(WireError::Truncated)),
+    entry("delimited_length_2gib", b"\x12\x80\x80\x80\x80\x08", Some(WireError::Truncated)),
+    entry(
+        "delimited_length_u64_max",
+        b"\x12\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01",
+        Some(WireError::Truncated),
+    ),
+    entry("delimited_empty", b"\x12\x00", None),
+    entry("fixed32_truncated", b"\x1d\x01\x02\x03", Some(WireError::Truncated)),
+    entry("fixed64_truncated", b"\x19\x01", Some(WireError::Truncated)),
+    entry("end_group_at_top_level", b"\x0c", Some(WireError::UnmatchedEndGroup)),
+    entry("end_group_mismatched", b"\x0b\x14", Some(WireError::UnmatchedEndGroup)),
+    entry("group_unterminated", b"\x0b\x08\x01", Some(WireError::Truncated)),
+    entry("groups_at_depth_limit", &GROUPS_AT_LIMIT, None),
+    entry("groups_past_depth_limit", &GROUPS_PAST_LIMIT, Some(WireError::RecursionLimit)),
+    entry("valid_then_truncated", b"\x08\x01\x12\x01", Some(WireError::Truncated)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::WireReader;
+
+    fn first_error(data: &[u8]) -> Option<WireError> {
+        WireReader::new(data).find_map(Result::err)
+    }
+
+    #[test]
+    fn test_corpus() {
+        for entry in WIRE_CORPUS {
+            assert_eq!(first_error(entry.data), entry.error, "{}", entry.name);
+        }
+        let mut names: Vec<_> = WIRE_CORPUS.iter().map(|e| e.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), WIRE_CORPUS.len());
+    }
+
+    /// Runs the wire-level utilities on corpus entries with bytes flipped,
+    /// truncated and appended, checking that none of them panics.
+    #[test]
+    fn test_mutations_dont_panic() {
+        // xorshift64, so failures reproduce.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for entry in WIRE_CORPUS {
+            for _ in 0..200 {
+                let mut data = entry.data.to_vec();
+                match next() % 3 {
+                    0 if !data.is_empty() => {
+                        let i = next() as usize % data.len();
+                        data[i] ^= 1 << (next() % 8);
+                    }
+                    1 => data.truncate(next() as usize % (data.len() + 1)),
+                    _ => data.push(next() as u8),
+                }
+                let _ = first_error(&data);
+                let _ = crate::lenient::lenient_prefix(&data);
+                let _ = crate::undeclared_fields(&data, &[], &[]);
+                let _ = crate::FieldFilter::new().allow(1).filter(&data);
+                let _ = crate::FieldAliases::new().alias(1, 2).renumber(&data);
+                let _ = crate::size_breakdown(&data, 3);
+                let _ = crate::unpack_serialized(&data, &[]);
+                let _ = crate::decode_packed_varints(&data, &mut Vec::new());
+            }
+        }
+    }
+}
@@ -19,6 +19,15 @@ namespace protobuf {
 namespace compiler {
 namespace rust {
 
+// Emits the private associated function that reports the use of `field` to
+// the hook registered with `set_deprecated_field_hook()`, if the field is
+// deprecated. It has to be emitted once per field, into the message's impl.
+void EmitDeprecatedUseReporter(Context<FieldDescriptor> field);
+
+// Emits a statement that calls the function emitted by
+// `EmitDeprecatedUseReporter()`, if the field is deprecated.
+void EmitDeprecatedUse(Context<FieldDescriptor> field);
+
 class AccessorGenerator {
  public:
   AccessorGenerator() = default;
@@ -34,8 +43,13 @@ class AccessorGenerator {
   // Returns `nullptr` if there is no known generator for this field.
   static std::unique_ptr<AccessorGenerator> For(Context<FieldDescriptor> field);
 
+  // Accessors can use `$deprecated_use$` at the start of their bodies to
+  // report uses of deprecated fields.
   void GenerateMsgImpl(Context<FieldDescriptor> field) const {
-    InMsgImpl(field);
+    field.Emit({{"deprecated_use", [&] { EmitDeprecatedUse(field); }},
+                {"reporter", [&] { EmitDeprecatedUseReporter(field); }},
+                {"accessors", [&] { InMsgImpl(field); }}},
+               "$reporter$\n$accessors$");
   }
   void GenerateExternC(Context<FieldDescriptor> field) const {
     InExternC(field);
@@ -11,6 +11,7 @@
 
 #include "google/protobuf/compiler/rust/accessors/accessor_generator.h"
 #include "google/protobuf/compiler/rust/context.h"
+#include "google/protobuf/compiler/rust/naming.h"
 #include "google/protobuf/descriptor.h"
 #include "google/protobuf/descriptor.pb.h"
 
@@ -29,6 +30,38 @@ std::unique_ptr<AccessorGenerator> AccessorGeneratorFor(
     return std::make_unique<UnsupportedField>();
   }
 
+  // TODO: Repeated fields are not supported yet, so there is no
+  // `RepeatedFieldRef` or `RepeatedView` in the runtime. When adding them:
+  // - Keep the raw pointer private and only construct refs from a borrow of
+  //   the owning message, so downstream code can't create dangling refs.
+  // - Make an unset `RepeatedView` point at a static empty array (as upb
+  //   supports) instead of holding an `Option`, so accessors don't branch.
+  // - Offer `RepeatedView::to_array::<N>()` for fields that model
+  //   fixed-size vectors, failing on a length mismatch.
+  // - Have iterators over repeated message fields yield `View<'msg, M>`,
+  //   tied to the parent view's lifetime rather than the iterator's.
+  // - Consider an inline small-capacity mode for an owned `Repeated<T>` of
+  //   scalars, behind a feature, that only spills to the arena after N
+  //   elements, since most repeated fields hold 0 to 3 elements.
+  // - Give `RepeatedMut<M>` of messages a `sort_by_key(|view| ...)` that
+  //   reorders the element pointers instead of copying messages, to
+  //   canonicalize nested lists before deterministic serialization.
+  // - Export the iterator types under stable public names (e.g.
+  //   `protobuf::RepeatedIter<'msg, T>`, and likewise for maps) and derive
+  //   `Clone` and `Debug` for them, so downstream code can store them in
+  //   structs and name them in signatures instead of using `impl Iterator`.
+  // - Add `RepeatedView<ProtoStr>::parse_all::<T: FromStr>()`, returning a
+  //   `Vec<T>` or the index and error of the first element that doesn't
+  //   parse, for schemas that carry numbers in repeated string fields.
+  // - Add `RepeatedView::to_vec()` that copies all elements out with one
+  //   pre-sized allocation. For scalars this should be a single thunk call
+  //   copying the kernel's contiguous array, not one call per element.
+  // - Add a columnar setter for repeated message fields that builds all
+  //   elements from parallel slices of their scalar subfields (e.g.
+  //   `ids: &[i64], scores: &[f32]`) with one thunk call and one
+  //   reservation, for converting analytics batches. Until then, each
+  //   element can be appended to the parent's serialized bytes with
+  //   `WireWriter::write_delimited()` and the result deserialized once.
   if (desc.is_repeated()) {
     return std::make_unique<UnsupportedField>();
   }
@@ -54,6 +87,17 @@ std::unique_ptr<AccessorGenerator> AccessorGeneratorFor(
     case FieldDescriptor::TYPE_MESSAGE:
       return std::make_unique<SingularMessage>();
 
+    // TODO: Enum fields are not supported yet. Once they are:
+    // - Offer an opt-in bitflags-style wrapper (`contains`, `insert`,
+    //   `union`) over the underlying `i32` for enums whose values are all
+    //   powers of two.
+    // - Open (proto3) enums must keep unknown values, including in repeated
+    //   fields and map values, so a parse/serialize round trip doesn't drop
+    //   elements on either kernel. Expose them through the open enum type.
+    // - Make the generated `i32` conversions `const fn`s backed by const
+    //   lookup tables, so the enums can be used in downstream `const` and
+    //   `static` initializers. `TryFrom` can't be called in const context,
+    //   so this needs inherent `const fn from_i32()` alongside it.
     default:
       return std::make_unique<UnsupportedField>();
   }
@@ -61,6 +105,30 @@ std::unique_ptr<AccessorGenerator> AccessorGeneratorFor(
 
 }  // namespace
 
+void EmitDeprecatedUseReporter(Context<FieldDescriptor> field) {
+  if (!field.desc().options().deprecated()) return;
+  field.Emit({{"field", FieldAccessorName(field)},
+              {"full_name", field.desc().full_name()}},
+             R"rs(
+    // All accessors of the field share this flag, so that the field is
+    // reported once rather than once per accessor. Fields without accessors
+    // yet (e.g. repeated fields) don't call it.
+    #[allow(dead_code)]
+    fn r#$field$_deprecated_use() {
+      static REPORTED: $std$::sync::atomic::AtomicBool =
+        $std$::sync::atomic::AtomicBool::new(false);
+      $pbi$::report_deprecated_field_use(&REPORTED, "$full_name$");
+    }
+  )rs");
+}
+
+void EmitDeprecatedUse(Context<FieldDescriptor> field) {
+  if (!field.desc().options().deprecated()) return;
+  field.Emit({{"field", FieldAccessorName(field)}}, R"rs(
+    Self::r#$field$_deprecated_use();
+  )rs");
+}
+
 void GenerateAccessorMsgImpl(Context<FieldDescriptor> field) {
   AccessorGeneratorFor(field.desc())->GenerateMsgImpl(field);
 }
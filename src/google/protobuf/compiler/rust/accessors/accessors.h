@@ -8,6 +8,8 @@
 #ifndef GOOGLE_PROTOBUF_COMPILER_RUST_ACCESSORS_ACCESSORS_H__
 #define GOOGLE_PROTOBUF_COMPILER_RUST_ACCESSORS_ACCESSORS_H__
 
+#include <string>
+
 #include "google/protobuf/compiler/rust/context.h"
 #include "google/protobuf/descriptor.h"
 
@@ -20,6 +22,10 @@ void GenerateAccessorMsgImpl(Context<FieldDescriptor> field);
 void GenerateAccessorExternC(Context<FieldDescriptor> field);
 void GenerateAccessorThunkCc(Context<FieldDescriptor> field);
 
+// Returns the Rust literal for the default value of the singular scalar
+// `field`, e.g. `41` or `f32::NAN`.
+std::string DefaultValueLiteral(const FieldDescriptor& field);
+
 }  // namespace rust
 }  // namespace compiler
 }  // namespace protobuf
@@ -5,6 +5,8 @@
 // license that can be found in the LICENSE file or at
 // https://developers.google.com/open-source/licenses/bsd
 
+#include <string>
+
 #include "absl/strings/match.h"
 #include "absl/strings/string_view.h"
 #include "absl/strings/strip.h"
@@ -31,13 +33,35 @@ void SingularMessage::InMsgImpl(Context<FieldDescriptor> field) const {
   field.Emit(
       {
           {"prefix", prefix},
-          {"field", field.desc().name()},
+          {"field", FieldAccessorName(field)},
           {"getter_thunk", Thunk(field, "get")},
+          {"number", std::to_string(field.desc().number())},
       },
+      // TODO: Keep the bytes passed to `_set_serialized()` as they
+      // are and emit them verbatim on serialize, validating them lazily. This
+      // needs lazy field support in both kernels; until then the whole message
+      // is round-tripped and `data` parsed eagerly.
       R"rs(
           pub fn r#$field$(&self) -> $prefix$View {
+            $deprecated_use$
             $prefix$View::new($pbi$::Private, unsafe { $getter_thunk$(self.inner.msg) } )
           }
+
+          /// Sets the field to the already serialized submessage `data`,
+          /// replacing any previous value, e.g. to splice in a submessage
+          /// received from elsewhere without having its type at hand. On
+          /// error, `self` is unchanged.
+          pub fn r#$field$_set_serialized(&mut self, data: &[u8])
+              -> Result<(), $pb$::ParseError> {
+            $deprecated_use$
+            let current = $pbi$::KernelMessage::kernel_serialize(self);
+            let spliced = $pbi$::replace_delimited_field(&current, $number$, data)
+                .map_err(|_| $pb$::ParseError)?;
+            let mut msg = Self::new();
+            $pbi$::KernelMessage::kernel_deserialize(&mut msg, &spliced)?;
+            *self = msg;
+            Ok(())
+          }
         )rs");
 }
 
@@ -45,9 +69,11 @@ void SingularMessage::InExternC(Context<FieldDescriptor> field) const {
   field.Emit(
       {
           {"getter_thunk", Thunk(field, "get")},
+          {"hazzer_thunk", Thunk(field, "has")},
       },
       R"rs(
                   fn $getter_thunk$(raw_msg: $pbi$::RawMessage) -> $pbi$::RawMessage;
+                  fn $hazzer_thunk$(raw_msg: $pbi$::RawMessage) -> bool;
                )rs");
 }
 
@@ -55,11 +81,15 @@ void SingularMessage::InThunkCc(Context<FieldDescriptor> field) const {
   field.Emit({{"QualifiedMsg",
                cpp::QualifiedClassName(field.desc().containing_type())},
               {"getter_thunk", Thunk(field, "get")},
+              {"hazzer_thunk", Thunk(field, "has")},
               {"field", cpp::FieldName(&field.desc())}},
              R"cc(
                const void* $getter_thunk$($QualifiedMsg$* msg) {
                  return static_cast<const void*>(&msg->$field$());
                }
+               bool $hazzer_thunk$($QualifiedMsg$* msg) {
+                 return msg->has_$field$();
+               }
              )cc");
 }
 
@@ -5,43 +5,159 @@
 // license that can be found in the LICENSE file or at
 // https://developers.google.com/open-source/licenses/bsd
 
+#include <cmath>
+#include <string>
+
+#include "absl/log/absl_log.h"
+#include "absl/strings/ascii.h"
+#include "absl/strings/str_cat.h"
 #include "absl/strings/string_view.h"
 #include "google/protobuf/compiler/cpp/helpers.h"
 #include "google/protobuf/compiler/rust/accessors/accessor_generator.h"
 #include "google/protobuf/compiler/rust/context.h"
 #include "google/protobuf/compiler/rust/naming.h"
 #include "google/protobuf/descriptor.h"
+#include "google/protobuf/io/strtod.h"
 
 namespace google {
 namespace protobuf {
 namespace compiler {
 namespace rust {
+namespace {
+
+template <typename T>
+std::string FloatLiteral(T value, absl::string_view type) {
+  if (std::isnan(value)) return absl::StrCat(type, "::NAN");
+  if (std::isinf(value)) {
+    return absl::StrCat(type, value > 0 ? "::INFINITY" : "::NEG_INFINITY");
+  }
+  // The suffix makes the literal a float even if it has no decimal point.
+  return absl::StrCat(io::SimpleDtoa(value), type);
+}
+
+}  // namespace
+
+std::string DefaultValueLiteral(const FieldDescriptor& field) {
+  switch (field.cpp_type()) {
+    case FieldDescriptor::CPPTYPE_INT32:
+      return absl::StrCat(field.default_value_int32());
+    case FieldDescriptor::CPPTYPE_INT64:
+      return absl::StrCat(field.default_value_int64());
+    case FieldDescriptor::CPPTYPE_UINT32:
+      return absl::StrCat(field.default_value_uint32());
+    case FieldDescriptor::CPPTYPE_UINT64:
+      return absl::StrCat(field.default_value_uint64());
+    case FieldDescriptor::CPPTYPE_FLOAT:
+      return FloatLiteral(field.default_value_float(), "f32");
+    case FieldDescriptor::CPPTYPE_DOUBLE:
+      return FloatLiteral(field.default_value_double(), "f64");
+    case FieldDescriptor::CPPTYPE_BOOL:
+      return field.default_value_bool() ? "true" : "false";
+    default:
+      break;
+  }
+  ABSL_LOG(FATAL) << "Unsupported scalar type: " << field.type_name();
+  return "";
+}
+
+namespace {
+
+bool IsFloat(const FieldDescriptor& field) {
+  return field.cpp_type() == FieldDescriptor::CPPTYPE_FLOAT ||
+         field.cpp_type() == FieldDescriptor::CPPTYPE_DOUBLE;
+}
+
+}  // namespace
 
 void SingularScalar::InMsgImpl(Context<FieldDescriptor> field) const {
   field.Emit(
       {
-          {"field", field.desc().name()},
+          {"field", FieldAccessorName(field)},
           {"Scalar", PrimitiveRsTypeName(field.desc())},
           {"hazzer_thunk", Thunk(field, "has")},
+          {"default_const",
+           [&] {
+             if (!field.desc().has_default_value()) return;
+             field.Emit(
+                 {{"DEFAULT",
+                   absl::StrCat(absl::AsciiStrToUpper(FieldAccessorName(field)),
+                                "_DEFAULT")},
+                  {"default_value", DefaultValueLiteral(field.desc())}},
+                 R"rs(
+                  /// The default value declared for `$field$`.
+                  pub const $DEFAULT$: $Scalar$ = $default_value$;
+                )rs");
+           }},
           {"getter",
            [&] {
              field.Emit({}, R"rs(
                   pub fn r#$field$(&self) -> $Scalar$ {
+                    $deprecated_use$
                     unsafe { $getter_thunk$(self.inner.msg) }
                   }
                 )rs");
            }},
+          {"is_default",
+           [&] {
+             if (ClashesWithAccessor(
+                     field.WithDesc(field.desc().containing_type()),
+                     absl::StrCat(FieldAccessorName(field), "_is_default"),
+                     &field.desc())) {
+               return;
+             }
+             std::string default_value = DefaultValueLiteral(field.desc());
+             std::string cmp = absl::StrCat("value == ", default_value);
+             // Floats compare by bits, so that a NaN default matches itself
+             // and -0.0 doesn't match 0.0.
+             if (IsFloat(field.desc())) {
+               cmp = absl::StrCat("value.to_bits() == (", default_value,
+                                  ").to_bits()");
+             }
+             field.Emit(
+                 {{"cmp", cmp}},
+                 R"rs(
+                  /// Returns whether `$field$` reads as its default value,
+                  /// whether or not it's set.
+                  pub fn r#$field$_is_default(&self) -> bool {
+                    $deprecated_use$
+                    let value = unsafe { $getter_thunk$(self.inner.msg) };
+                    $cmp$
+                  }
+                )rs");
+           }},
           {"getter_opt",
            [&] {
              if (!field.desc().is_optional()) return;
-             if (!field.desc().has_presence()) return;
+             if (!field.desc().has_presence()) {
+               if (!field.opts().implicit_presence_as_option) return;
+               // Floats compare by bits, so that -0.0 counts as set, as it
+               // does when serializing.
+               absl::string_view is_set = "value != 0";
+               if (IsFloat(field.desc())) {
+                 is_set = "value.to_bits() != 0";
+               } else if (field.desc().cpp_type() ==
+                          FieldDescriptor::CPPTYPE_BOOL) {
+                 is_set = "value";
+               }
+               field.Emit(
+                   {{"is_set", is_set}},
+                   R"rs(
+                  /// Returns `None` if the field holds its default value.
+                  pub fn r#$field$_opt(&self) -> Option<$Scalar$> {
+                    $deprecated_use$
+                    let value = unsafe { $getter_thunk$(self.inner.msg) };
+                    ($is_set$).then_some(value)
+                  }
+                  )rs");
+               return;
+             }
              field.Emit({}, R"rs(
                   pub fn r#$field$_opt(&self) -> $pb$::Optional<$Scalar$> {
-                    if !unsafe { $hazzer_thunk$(self.inner.msg) } {
-                      return $pb$::Optional::Unset(<$Scalar$>::default());
-                    }
+                    $deprecated_use$
+                    // An unset field reads as its declared default.
                     let value = unsafe { $getter_thunk$(self.inner.msg) };
-                    $pb$::Optional::Set(value)
+                    $pb$::Optional::new(
+                      value, unsafe { $hazzer_thunk$(self.inner.msg) })
                   }
                   )rs");
            }},
@@ -53,13 +169,48 @@ void SingularScalar::InMsgImpl(Context<FieldDescriptor> field) const {
              if (field.desc().has_presence()) {
                field.Emit({}, R"rs(
                   pub fn r#$field$_set(&mut self, val: Option<$Scalar$>) {
+                    $deprecated_use$
                     match val {
                       Some(val) => unsafe { $setter_thunk$(self.inner.msg, val) },
                       None => unsafe { $clearer_thunk$(self.inner.msg) },
                     }
                   }
                 )rs");
+             } else if (field.opts().implicit_presence_as_option) {
+               field.Emit({}, R"rs(
+                  /// Sets the field to `val`, or to its default value if
+                  /// `None`.
+                  pub fn r#$field$_set(&mut self, val: Option<$Scalar$>) {
+                    $deprecated_use$
+                    match val {
+                      Some(val) => unsafe { $setter_thunk$(self.inner.msg, val) },
+                      None => unsafe { $clearer_thunk$(self.inner.msg) },
+                    }
+                  }
+                )rs");
+             }
+           }},
+          {"field_try_setter",
+           [&] {
+             // Only fields narrower than the `i64` argument need checking.
+             switch (field.desc().cpp_type()) {
+               case FieldDescriptor::CPPTYPE_INT32:
+               case FieldDescriptor::CPPTYPE_UINT32:
+                 break;
+               default:
+                 return;
              }
+             field.Emit({}, R"rs(
+                  /// Sets the field to `val` if it fits in `$Scalar$`. On
+                  /// error, the field is unchanged.
+                  pub fn r#$field$_try_set(&mut self, val: i64)
+                      -> Result<(), $pb$::OutOfRange> {
+                    $deprecated_use$
+                    let val = $Scalar$::try_from(val).map_err(|_| $pb$::OutOfRange)?;
+                    unsafe { $setter_thunk$(self.inner.msg, val) };
+                    Ok(())
+                  }
+                )rs");
            }},
           {"field_mutator_getter",
            [&] {
@@ -69,6 +220,7 @@ void SingularScalar::InMsgImpl(Context<FieldDescriptor> field) const {
              } else {
                field.Emit({}, R"rs(
                   pub fn r#$field$_mut(&mut self) -> $pb$::PrimitiveMut<'_, $Scalar$> {
+                    $deprecated_use$
                     static VTABLE: $pbi$::PrimitiveVTable<$Scalar$> =
                       $pbi$::PrimitiveVTable::new(
                         $pbi$::Private,
@@ -94,9 +246,12 @@ void SingularScalar::InMsgImpl(Context<FieldDescriptor> field) const {
            }},
       },
       R"rs(
+          $default_const$
           $getter$
           $getter_opt$
+          $is_default$
           $field_setter$
+          $field_try_setter$
           $field_mutator_getter$
         )rs");
 }
@@ -7,18 +7,39 @@
 
 #include <string>
 
+#include "absl/strings/ascii.h"
 #include "absl/strings/escaping.h"
+#include "absl/strings/str_cat.h"
 #include "absl/strings/string_view.h"
 #include "google/protobuf/compiler/cpp/helpers.h"
 #include "google/protobuf/compiler/rust/accessors/accessor_generator.h"
 #include "google/protobuf/compiler/rust/context.h"
 #include "google/protobuf/compiler/rust/naming.h"
 #include "google/protobuf/descriptor.h"
+#include "utf8_validity.h"
 
 namespace google {
 namespace protobuf {
 namespace compiler {
 namespace rust {
+namespace {
+
+// Returns the body of a Rust string literal holding the UTF-8 `value`.
+// Unlike C, Rust only allows hex escapes for ASCII in string literals, so
+// non-ASCII characters are copied as-is.
+std::string RustStrEscape(absl::string_view value) {
+  std::string escaped;
+  for (char c : value) {
+    if (static_cast<unsigned char>(c) >= 0x80) {
+      escaped.push_back(c);
+    } else {
+      absl::StrAppend(&escaped, absl::CHexEscape(absl::string_view(&c, 1)));
+    }
+  }
+  return escaped;
+}
+
+}  // namespace
 
 void SingularString::InMsgImpl(Context<FieldDescriptor> field) const {
   std::string hazzer_thunk = Thunk(field, "has");
@@ -37,12 +58,73 @@ void SingularString::InMsgImpl(Context<FieldDescriptor> field) const {
   };
   field.Emit(
       {
-          {"field", field.desc().name()},
+          {"field", FieldAccessorName(field)},
           {"hazzer_thunk", hazzer_thunk},
           {"getter_thunk", getter_thunk},
           {"setter_thunk", setter_thunk},
           {"proxied_type", proxied_type},
           {"transform_view", transform_view},
+          {"default_const",
+           [&] {
+             if (!field.desc().has_default_value()) return;
+             absl::string_view default_value =
+                 field.desc().default_value_string();
+             bool is_string =
+                 field.desc().type() == FieldDescriptor::TYPE_STRING;
+             // A string default that isn't UTF-8 can't be a Rust `str`.
+             if (is_string && !utf8_range::IsStructurallyValid(default_value)) {
+               return;
+             }
+             field.Emit(
+                 {{"DEFAULT",
+                   absl::StrCat(absl::AsciiStrToUpper(FieldAccessorName(field)),
+                                "_DEFAULT")},
+                  {"default_type", is_string ? "str" : "[u8]"},
+                  {"default_literal",
+                   is_string
+                       ? absl::StrCat("\"", RustStrEscape(default_value), "\"")
+                       : absl::StrCat("b\"", absl::CHexEscape(default_value),
+                                      "\"")}},
+                 R"rs(
+                  /// The default value declared for `$field$`.
+                  pub const $DEFAULT$: &'static $default_type$ = $default_literal$;
+                )rs");
+           }},
+          {"is_default",
+           [&] {
+             if (ClashesWithAccessor(
+                     field.WithDesc(field.desc().containing_type()),
+                     absl::StrCat(FieldAccessorName(field), "_is_default"),
+                     &field.desc())) {
+               return;
+             }
+             field.Emit(
+                 {{"default",
+                   absl::CHexEscape(field.desc().default_value_string())}},
+                 R"rs(
+            /// Returns whether `$field$` reads as its default value, whether
+            /// or not it's set.
+            pub fn r#$field$_is_default(&self) -> bool {
+              $deprecated_use$
+              unsafe { $getter_thunk$(self.inner.msg).as_ref() } == b"$default$"
+            }
+          )rs");
+           }},
+          {"unchecked_getter",
+           [&] {
+             if (field.desc().type() != FieldDescriptor::TYPE_STRING) return;
+             field.Emit(R"rs(
+            /// Returns `$field$` as a `&str` without checking that it's
+            /// valid UTF-8, for hot paths reading trusted data.
+            ///
+            /// # Safety
+            /// The field must hold valid UTF-8.
+            pub unsafe fn r#$field$_unchecked(&self) -> &str {
+              // SAFETY: the caller promises that the field is UTF-8.
+              unsafe { self.r#$field$().to_str_unchecked() }
+            }
+          )rs");
+           }},
           {"field_optional_getter",
            [&] {
              if (!field.desc().is_optional()) return;
@@ -52,6 +134,7 @@ void SingularString::InMsgImpl(Context<FieldDescriptor> field) const {
                          {"transform_view", transform_view}},
                         R"rs(
             pub fn $field$_opt(&self) -> $pb$::Optional<&$proxied_type$> {
+              $deprecated_use$
               unsafe {
                 let view = $getter_thunk$(self.inner.msg).as_ref();
                 $pb$::Optional::new(
@@ -67,7 +150,7 @@ void SingularString::InMsgImpl(Context<FieldDescriptor> field) const {
              if (field.desc().has_presence()) {
                field.Emit(
                    {
-                       {"field", field.desc().name()},
+                       {"field", FieldAccessorName(field)},
                        {"proxied_type", proxied_type},
                        {"default_val",
                         absl::CHexEscape(field.desc().default_value_string())},
@@ -92,6 +175,7 @@ void SingularString::InMsgImpl(Context<FieldDescriptor> field) const {
                    },
                    R"rs(
             pub fn $field$_mut(&mut self) -> $pb$::FieldEntry<'_, $proxied_type$> {
+              $deprecated_use$
               static VTABLE: $pbi$::BytesOptionalMutVTable = unsafe {
                 $pbi$::BytesOptionalMutVTable::new(
                   $pbi$::Private,
@@ -115,12 +199,13 @@ void SingularString::InMsgImpl(Context<FieldDescriptor> field) const {
             }
           )rs");
              } else {
-               field.Emit({{"field", field.desc().name()},
+               field.Emit({{"field", FieldAccessorName(field)},
                            {"proxied_type", proxied_type},
                            {"getter_thunk", getter_thunk},
                            {"setter_thunk", setter_thunk}},
                           R"rs(
               pub fn $field$_mut(&mut self) -> $pb$::Mut<'_, $proxied_type$> {
+                $deprecated_use$
                 static VTABLE: $pbi$::BytesMutVTable = unsafe {
                   $pbi$::BytesMutVTable::new(
                     $pbi$::Private,
@@ -145,11 +230,16 @@ void SingularString::InMsgImpl(Context<FieldDescriptor> field) const {
            }},
       },
       R"rs(
+        $default_const$
+
         pub fn r#$field$(&self) -> &$proxied_type$ {
+          $deprecated_use$
           let view = unsafe { $getter_thunk$(self.inner.msg).as_ref() };
           $transform_view$
         }
 
+        $unchecked_getter$
+        $is_default$
         $field_optional_getter$
         $field_mutator_getter$
       )rs");
@@ -14,6 +14,7 @@
 #include "absl/algorithm/container.h"
 #include "absl/status/status.h"
 #include "absl/status/statusor.h"
+#include "absl/strings/str_split.h"
 #include "absl/strings/string_view.h"
 #include "absl/strings/substitute.h"
 #include "google/protobuf/compiler/code_generator.h"
@@ -64,6 +65,57 @@ absl::StatusOr<Options> Options::Parse(absl::string_view param) {
                          kernel_arg->second));
   }
 
+  for (const auto& arg : args) {
+    if (arg.first != "accessor_name") continue;
+    std::pair<std::string, std::string> rename =
+        absl::StrSplit(arg.second, absl::MaxSplits(':', 1));
+    if (rename.first.empty() || rename.second.empty()) {
+      return absl::InvalidArgumentError(absl::Substitute(
+          "Invalid `accessor_name` value `$0`, please specify "
+          "`<full field name>:<accessor name>`.",
+          arg.second));
+    }
+    opts.accessor_names[rename.first] = rename.second;
+  }
+
+  auto nested_names_arg = absl::c_find_if(
+      args, [](auto& arg) { return arg.first == "nested_names"; });
+  if (nested_names_arg != args.end()) {
+    if (nested_names_arg->second == "flat") {
+      opts.flat_nested_names = true;
+    } else if (nested_names_arg->second != "modules") {
+      return absl::InvalidArgumentError(
+          absl::Substitute("Unknown nested_names `$0`, please specify `flat` "
+                           "or `modules`.",
+                           nested_names_arg->second));
+    }
+  }
+
+  auto implicit_presence_arg = absl::c_find_if(
+      args, [](auto& arg) { return arg.first == "implicit_presence"; });
+  if (implicit_presence_arg != args.end()) {
+    if (implicit_presence_arg->second == "option") {
+      opts.implicit_presence_as_option = true;
+    } else if (implicit_presence_arg->second != "plain") {
+      return absl::InvalidArgumentError(absl::Substitute(
+          "Unknown implicit_presence `$0`, please specify `option` or "
+          "`plain`.",
+          implicit_presence_arg->second));
+    }
+  }
+
+  auto raw_handles_arg = absl::c_find_if(
+      args, [](auto& arg) { return arg.first == "raw_handles"; });
+  if (raw_handles_arg != args.end()) {
+    if (raw_handles_arg->second == "enabled") {
+      opts.raw_handles = true;
+    } else if (raw_handles_arg->second != "disabled") {
+      return absl::InvalidArgumentError(absl::Substitute(
+          "Unknown raw_handles `$0`, please specify `enabled` or `disabled`.",
+          raw_handles_arg->second));
+    }
+  }
+
   return opts;
 }
 
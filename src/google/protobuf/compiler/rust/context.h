@@ -8,6 +8,9 @@
 #ifndef GOOGLE_PROTOBUF_COMPILER_RUST_CONTEXT_H__
 #define GOOGLE_PROTOBUF_COMPILER_RUST_CONTEXT_H__
 
+#include <string>
+
+#include "absl/container/flat_hash_map.h"
 #include "absl/log/absl_log.h"
 #include "absl/status/statusor.h"
 #include "absl/strings/string_view.h"
@@ -40,6 +43,28 @@ inline absl::string_view KernelRsName(Kernel kernel) {
 struct Options {
   Kernel kernel;
 
+  // Overrides for the names of generated accessors, keyed by the full name of
+  // the field. Set with `accessor_name=<full field name>:<accessor name>`,
+  // e.g. to rename a field that conflicts with a generated method like
+  // `serialize`.
+  absl::flat_hash_map<std::string, std::string> accessor_names;
+
+  // Whether to also emit flattened aliases (`Outer_Inner`) for nested
+  // messages, which are always emitted in nested modules (`Outer_::Inner`).
+  // Set with `nested_names=flat`.
+  bool flat_nested_names = false;
+
+  // Whether to also emit `Option`-based `<field>_opt()` and `<field>_set()`
+  // accessors for implicit-presence scalar fields, treating the default value
+  // as `None`, e.g. to keep the API shape of other generators during a
+  // migration. Set with `implicit_presence=option`.
+  bool implicit_presence_as_option = false;
+
+  // Whether to emit `unsafe` `into_raw()` and `from_raw()` conversions between
+  // messages and the kernel's raw handles, for interop with existing upb or
+  // C++ code. Set with `raw_handles=enabled`.
+  bool raw_handles = false;
+
   static absl::StatusOr<Options> Parse(absl::string_view param);
 };
 
@@ -17,6 +17,7 @@
 #include "absl/container/flat_hash_set.h"
 #include "absl/memory/memory.h"
 #include "absl/status/statusor.h"
+#include "absl/strings/escaping.h"
 #include "absl/strings/str_split.h"
 #include "absl/strings/string_view.h"
 #include "absl/types/span.h"
@@ -143,6 +144,20 @@ void EmitPublicImports(
   }
 }
 
+// Emits the serialized `FileDescriptorProto` of `file`, which the
+// `file_descriptor_bytes()` of its messages return.
+void EmitFileDescriptorBytes(Context<FileDescriptor>& file) {
+  FileDescriptorProto proto;
+  file.desc().CopyTo(&proto);
+  file.Emit({{"file_name", file.desc().name()},
+             {"bytes", absl::CHexEscape(proto.SerializeAsString())}},
+            R"rs(
+    /// The serialized `FileDescriptorProto` of `$file_name$`.
+    #[doc(hidden)]
+    pub static __FILE_DESCRIPTOR: &[u8] = b"$bytes$";
+  )rs");
+}
+
 // Emits submodule declarations so `rustc` can find non primary sources from the
 // primary file.
 void DeclareSubmodulesForNonPrimarySrcs(
@@ -253,6 +268,7 @@ bool RustGenerator::Generate(const FileDescriptor* file_desc,
   }
 
   EmitPublicImports(file, files_in_current_crate);
+  EmitFileDescriptorBytes(file);
 
   std::unique_ptr<io::ZeroCopyOutputStream> thunks_cc;
   std::unique_ptr<io::Printer> thunks_printer;
@@ -7,8 +7,16 @@
 
 #include "google/protobuf/compiler/rust/message.h"
 
+#include <cstdint>
+#include <map>
+#include <set>
+#include <string>
+#include <vector>
+
 #include "absl/log/absl_check.h"
 #include "absl/log/absl_log.h"
+#include "absl/strings/escaping.h"
+#include "absl/strings/str_cat.h"
 #include "absl/strings/string_view.h"
 #include "google/protobuf/compiler/cpp/helpers.h"
 #include "google/protobuf/compiler/cpp/names.h"
@@ -17,6 +25,7 @@
 #include "google/protobuf/compiler/rust/naming.h"
 #include "google/protobuf/compiler/rust/oneof.h"
 #include "google/protobuf/descriptor.h"
+#include "google/protobuf/descriptor.pb.h"
 
 namespace google {
 namespace protobuf {
@@ -48,6 +57,19 @@ void MessageNew(Context<Descriptor> msg) {
   ABSL_LOG(FATAL) << "unreachable";
 }
 
+// Both kernels serialize known fields in field number order, followed by
+// unknown fields in the order they were parsed in. Code relies on this to
+// keep re-serialized bytes stable (see `serialization_test.rs`), so keep it
+// true for any new kernel.
+//
+// TODO: Neither kernel can reuse the previously serialized bytes of an
+// unmodified submessage, so a mutation of one field re-encodes the whole
+// message. Supporting that needs dirty tracking in the kernels themselves;
+// the Rust mutators would then only have to mark their message as modified.
+//
+// TODO: Serializing into a caller-provided (e.g. stack) buffer isn't possible
+// yet. The C++ kernel could size and write into the buffer directly, but the
+// upb kernel always encodes into an arena, and that arena is heap allocated.
 void MessageSerialize(Context<Descriptor> msg) {
   switch (msg.opts().kernel) {
     case Kernel::kCpp:
@@ -71,6 +93,53 @@ void MessageSerialize(Context<Descriptor> msg) {
   ABSL_LOG(FATAL) << "unreachable";
 }
 
+void MessageSerializeDeterministic(Context<Descriptor> msg) {
+  switch (msg.opts().kernel) {
+    case Kernel::kCpp:
+      msg.Emit({{"serialize_thunk", Thunk(msg, "serialize_deterministic")}},
+               R"rs(
+        unsafe { $serialize_thunk$(self.inner.msg) }
+      )rs");
+      return;
+
+    case Kernel::kUpb:
+      msg.Emit({{"serialize_thunk", Thunk(msg, "serialize_ex")}}, R"rs(
+        let arena = $pbr$::Arena::new();
+        let mut len = 0;
+        unsafe {
+          let data = $serialize_thunk$(
+            self.inner.msg, $pbr$::ENCODE_OPTION_DETERMINISTIC, arena.raw(), &mut len);
+          $pbr$::SerializedData::from_raw_parts(arena, data, len)
+        }
+      )rs");
+      return;
+  }
+
+  ABSL_LOG(FATAL) << "unreachable";
+}
+
+// TODO: `deserialize` copies string and bytes fields out of `data`, so
+// parsing a memory-mapped file is possible but not zero-copy. Aliasing the
+// input (e.g. `kUpb_DecodeOption_AliasString`) needs a message type that
+// borrows `data` for its lifetime, which the Rust API doesn't have yet.
+//
+// TODO: Every upb message owns its own arena, so bulk loaders pay for one
+// arena per message. Parsing a batch into a shared arena and returning views
+// needs messages that borrow an arena, the same prerequisite as above. The
+// C++ kernel has no arenas on this path at all.
+// An `ArenaScope` that owns every message parsed while serving a request,
+// frees them all at once when dropped and deep-copies escaping messages out
+// needs the same thing, plus C++ thunks that create messages on an arena.
+//
+// TODO: Interning identical string values during parsing has to happen in
+// the kernels' decoders; the Rust side only ever sees the parsed message.
+// With a shared arena (see above), upb could deduplicate within it.
+//
+// TODO: A `deserialize_with_deadline` that aborts with a `DeadlineExceeded`
+// error once a time budget is spent needs the kernels' decoders to check the
+// budget as they go: each kernel parses in a single call that the Rust side
+// can't interrupt. Until then, `deserialize_with_limits` rejects oversized
+// inputs up front, which bounds the work of a linear-time parse.
 void MessageDeserialize(Context<Descriptor> msg) {
   switch (msg.opts().kernel) {
     case Kernel::kCpp:
@@ -123,13 +192,18 @@ void MessageExterns(Context<Descriptor> msg) {
               {"new_thunk", Thunk(msg, "new")},
               {"delete_thunk", Thunk(msg, "delete")},
               {"serialize_thunk", Thunk(msg, "serialize")},
+              {"serialize_deterministic_thunk",
+               Thunk(msg, "serialize_deterministic")},
               {"deserialize_thunk", Thunk(msg, "deserialize")},
+              {"has_unknown_fields_thunk", Thunk(msg, "has_unknown_fields")},
           },
           R"rs(
           fn $new_thunk$() -> $pbi$::RawMessage;
           fn $delete_thunk$(raw_msg: $pbi$::RawMessage);
           fn $serialize_thunk$(raw_msg: $pbi$::RawMessage) -> $pbr$::SerializedData;
+          fn $serialize_deterministic_thunk$(raw_msg: $pbi$::RawMessage) -> $pbr$::SerializedData;
           fn $deserialize_thunk$(raw_msg: $pbi$::RawMessage, data: $pbr$::SerializedData) -> bool;
+          fn $has_unknown_fields_thunk$(raw_msg: $pbi$::RawMessage) -> bool;
         )rs");
       return;
 
@@ -138,11 +212,13 @@ void MessageExterns(Context<Descriptor> msg) {
           {
               {"new_thunk", Thunk(msg, "new")},
               {"serialize_thunk", Thunk(msg, "serialize")},
+              {"serialize_ex_thunk", Thunk(msg, "serialize_ex")},
               {"deserialize_thunk", Thunk(msg, "parse")},
           },
           R"rs(
           fn $new_thunk$(arena: $pbi$::RawArena) -> $pbi$::RawMessage;
           fn $serialize_thunk$(msg: $pbi$::RawMessage, arena: $pbi$::RawArena, len: &mut usize) -> $NonNull$<u8>;
+          fn $serialize_ex_thunk$(msg: $pbi$::RawMessage, options: i32, arena: $pbi$::RawArena, len: &mut usize) -> $NonNull$<u8>;
           fn $deserialize_thunk$(data: *const u8, size: usize, arena: $pbi$::RawArena) -> Option<$pbi$::RawMessage>;
       )rs");
       return;
@@ -181,10 +257,548 @@ bool IsSimpleScalar(FieldDescriptor::Type type) {
          type == FieldDescriptor::TYPE_BOOL;
 }
 
+// Appends the type names that the flattened aliases of the messages nested in
+// `msg` would take to `names`. `flat_name` is the flattened name of `msg`.
+void CollectFlatNestedNames(const Descriptor& msg, absl::string_view flat_name,
+                            std::vector<std::string>& names) {
+  for (int i = 0; i < msg.nested_type_count(); ++i) {
+    const Descriptor& nested = *msg.nested_type(i);
+    if (nested.map_key() != nullptr) continue;
+    std::string nested_flat_name = absl::StrCat(flat_name, "_", nested.name());
+    names.push_back(nested_flat_name);
+    names.push_back(absl::StrCat(nested_flat_name, "View"));
+    names.push_back(absl::StrCat(nested_flat_name, "Mut"));
+    CollectFlatNestedNames(nested, nested_flat_name, names);
+  }
+}
+
+// Returns the type names that more than one of the file's top-level messages
+// (with their views and muts) and flattened aliases would take.
+std::set<std::string> ClashingFlatNames(const FileDescriptor& file) {
+  std::vector<std::string> names;
+  for (int i = 0; i < file.message_type_count(); ++i) {
+    const Descriptor& msg = *file.message_type(i);
+    names.push_back(msg.name());
+    names.push_back(absl::StrCat(msg.name(), "View"));
+    names.push_back(absl::StrCat(msg.name(), "Mut"));
+    CollectFlatNestedNames(msg, msg.name(), names);
+  }
+  std::map<std::string, int> counts;
+  for (const auto& name : names) ++counts[name];
+  std::set<std::string> clashing;
+  for (const auto& [name, count] : counts) {
+    if (count > 1) clashing.insert(name);
+  }
+  return clashing;
+}
+
+// Emits flattened aliases (`Outer_Inner`) for all messages nested in `msg`,
+// for the `nested_names=flat` option. `path` and `flat_name` are the nested
+// and flattened names of `msg` relative to the current module.
+//
+// Aliases whose names (or view and mut names) are also taken by another
+// message or alias in the file, per `ClashingFlatNames`, are skipped.
+void GenerateFlatNestedAliases(Context<Descriptor> msg, absl::string_view path,
+                               absl::string_view flat_name,
+                               const std::set<std::string>& clashing) {
+  for (int i = 0; i < msg.desc().nested_type_count(); ++i) {
+    auto nested = msg.WithDesc(msg.desc().nested_type(i));
+    if (nested.desc().map_key() != nullptr) continue;
+    std::string nested_path = absl::StrCat(path, "_::", nested.desc().name());
+    std::string nested_flat_name =
+        absl::StrCat(flat_name, "_", nested.desc().name());
+    if (clashing.count(nested_flat_name) != 0 ||
+        clashing.count(absl::StrCat(nested_flat_name, "View")) != 0 ||
+        clashing.count(absl::StrCat(nested_flat_name, "Mut")) != 0) {
+      ABSL_LOG(WARNING) << "not generating flat alias `" << nested_flat_name
+                        << "` for " << nested.desc().full_name()
+                        << ": it clashes with another name in "
+                        << msg.desc().file()->name();
+    } else {
+      msg.Emit({{"path", nested_path}, {"flat_name", nested_flat_name}},
+               R"rs(
+                 #[allow(non_camel_case_types)]
+                 pub type $flat_name$ = $path$;
+                 #[allow(non_camel_case_types)]
+                 pub type $flat_name$View<'a> = $path$View<'a>;
+                 #[allow(non_camel_case_types)]
+                 pub type $flat_name$Mut<'a> = $path$Mut<'a>;
+               )rs");
+    }
+    GenerateFlatNestedAliases(nested, nested_path, nested_flat_name, clashing);
+  }
+}
+
+// Returns the `FieldKind` variant for the type of `field`.
+absl::string_view FieldKindName(const FieldDescriptor& field) {
+  switch (field.type()) {
+    case FieldDescriptor::TYPE_DOUBLE:
+      return "Double";
+    case FieldDescriptor::TYPE_FLOAT:
+      return "Float";
+    case FieldDescriptor::TYPE_INT64:
+      return "Int64";
+    case FieldDescriptor::TYPE_UINT64:
+      return "UInt64";
+    case FieldDescriptor::TYPE_INT32:
+      return "Int32";
+    case FieldDescriptor::TYPE_FIXED64:
+      return "Fixed64";
+    case FieldDescriptor::TYPE_FIXED32:
+      return "Fixed32";
+    case FieldDescriptor::TYPE_BOOL:
+      return "Bool";
+    case FieldDescriptor::TYPE_STRING:
+      return "String";
+    case FieldDescriptor::TYPE_GROUP:
+      return "Group";
+    case FieldDescriptor::TYPE_MESSAGE:
+      return "Message";
+    case FieldDescriptor::TYPE_BYTES:
+      return "Bytes";
+    case FieldDescriptor::TYPE_UINT32:
+      return "UInt32";
+    case FieldDescriptor::TYPE_ENUM:
+      return "Enum";
+    case FieldDescriptor::TYPE_SFIXED32:
+      return "SFixed32";
+    case FieldDescriptor::TYPE_SFIXED64:
+      return "SFixed64";
+    case FieldDescriptor::TYPE_SINT32:
+      return "SInt32";
+    case FieldDescriptor::TYPE_SINT64:
+      return "SInt64";
+  }
+  ABSL_LOG(FATAL) << "unreachable";
+  return "";
+}
+
+// Emits the `Option<MessageType>` of `field`'s `FieldInfo`. Only types from
+// the same file link to their `FIELDS`, since the paths of other files' types
+// aren't known here.
+void FieldInfoMessageType(Context<FieldDescriptor> field) {
+  const Descriptor* type = field.desc().message_type();
+  if (type == nullptr || field.desc().is_map()) {
+    field.Emit("None");
+    return;
+  }
+  field.Emit(
+      {{"full_name", type->full_name()},
+       {"fields",
+        [&] {
+          if (type->file() != field.desc().file()) {
+            field.Emit("None");
+            return;
+          }
+          field.Emit({{"path", GetCrateRelativeQualifiedPath(
+                                   field.WithDesc(type))}},
+                     "Some(|| crate::$path$::FIELDS)");
+        }}},
+      R"rs(
+        Some($pb$::MessageType {
+          full_name: "$full_name$",
+          fields: $fields$,
+        })
+      )rs");
+}
+
+// Returns whether `FieldIsSetExpr` can tell if `field` is set: fields that
+// have no accessors yet have no thunks to ask.
+bool CanTellIfSet(const FieldDescriptor& desc) {
+  return !desc.options().has_ctype() && !desc.is_repeated() &&
+         desc.type() != FieldDescriptor::TYPE_GROUP &&
+         desc.type() != FieldDescriptor::TYPE_ENUM;
+}
+
+// Emits a `bool` expression telling whether `field` of the message `raw_msg`
+// (a `RawMessage` expression) is set, using the same thunks as its accessors.
+// Requires `CanTellIfSet(field)`.
+void FieldIsSetExpr(Context<FieldDescriptor> field, absl::string_view raw_msg) {
+  const FieldDescriptor& desc = field.desc();
+  field.Emit(
+      {{"hazzer_thunk", Thunk(field, "has")},
+       {"getter_thunk", Thunk(field, "get")},
+       {"raw_msg", raw_msg},
+       {"is_set",
+        [&] {
+          if (desc.has_presence()) {
+            field.Emit("unsafe { $hazzer_thunk$($raw_msg$) }");
+            return;
+          }
+          // Without presence, a field is set unless it has the zero value.
+          switch (desc.type()) {
+            case FieldDescriptor::TYPE_BOOL:
+              field.Emit("unsafe { $getter_thunk$($raw_msg$) }");
+              break;
+            case FieldDescriptor::TYPE_FLOAT:
+            case FieldDescriptor::TYPE_DOUBLE:
+              // -0.0 is serialized, so it counts as set.
+              field.Emit(R"rs(
+                unsafe { $getter_thunk$($raw_msg$) }.to_bits() != 0
+              )rs");
+              break;
+            case FieldDescriptor::TYPE_STRING:
+            case FieldDescriptor::TYPE_BYTES:
+              field.Emit(R"rs(
+                !unsafe { $getter_thunk$($raw_msg$).as_ref() }.is_empty()
+              )rs");
+              break;
+            default:
+              field.Emit(R"rs(
+                unsafe { $getter_thunk$($raw_msg$) } != 0
+              )rs");
+              break;
+          }
+        }}},
+      "$is_set$");
+}
+
+// Emits an `Option<bool>` expression telling whether `field` is set, or
+// `None` for fields that have no accessors yet.
+void FieldIsSet(Context<FieldDescriptor> field) {
+  if (!CanTellIfSet(field.desc())) {
+    field.Emit("None");
+    return;
+  }
+  field.Emit({{"is_set", [&] { FieldIsSetExpr(field, "self.inner.msg"); }}},
+             "Some($is_set$)");
+}
+
+// Emits the body of `$Msg$View::is_default()`: no field is set, as told by
+// the fields' thunks, and there are no unknown fields. Messages that can have
+// fields the thunks can't see (fields without accessors yet, and extensions,
+// which the C++ kernel has no thunk for) are serialized instead.
+void ViewIsDefault(Context<Descriptor> msg) {
+  bool use_thunks = msg.desc().extension_range_count() == 0;
+  for (int i = 0; i < msg.desc().field_count(); ++i) {
+    use_thunks = use_thunks && CanTellIfSet(*msg.desc().field(i));
+  }
+  if (!use_thunks) {
+    switch (msg.opts().kernel) {
+      case Kernel::kCpp:
+        msg.Emit({{"serialize_thunk", Thunk(msg, "serialize")}}, R"rs(
+          unsafe { $serialize_thunk$(self.msg) }.is_empty()
+        )rs");
+        return;
+      case Kernel::kUpb:
+        msg.Emit({{"serialize_thunk", Thunk(msg, "serialize")}}, R"rs(
+          let arena = $pbr$::Arena::new();
+          let mut len = 0;
+          unsafe { $serialize_thunk$(self.msg, arena.raw(), &mut len) };
+          len == 0
+        )rs");
+        return;
+    }
+  }
+  msg.Emit(
+      {{"field_checks",
+        [&] {
+          for (int i = 0; i < msg.desc().field_count(); ++i) {
+            auto field = msg.WithDesc(*msg.desc().field(i));
+            field.Emit(
+                {{"is_set", [&] { FieldIsSetExpr(field, "self.msg"); }}},
+                R"rs(
+                  if $is_set$ {
+                    return false;
+                  }
+                )rs");
+          }
+        }},
+       {"has_unknown_fields",
+        [&] {
+          if (msg.is_cpp()) {
+            msg.Emit({{"thunk", Thunk(msg, "has_unknown_fields")}},
+                     "unsafe { $thunk$(self.msg) }");
+          } else {
+            msg.Emit("unsafe { $pbr$::has_unknown_fields(self.msg) }");
+          }
+        }}},
+      R"rs(
+        $field_checks$
+        !$has_unknown_fields$
+      )rs");
+}
+
+// Emits a statement clearing `field` if it's set to its default value.
+// Only explicit-presence singular scalar, string and bytes fields outside of
+// oneofs are pruned: clearing a oneof field would unset its oneof too.
+// TODO: Prune submessages recursively once message fields have mutators.
+void PruneDefault(Context<FieldDescriptor> field) {
+  const FieldDescriptor& desc = field.desc();
+  if (!desc.has_presence() || desc.is_repeated() ||
+      desc.real_containing_oneof() != nullptr || desc.options().has_ctype() ||
+      desc.type() == FieldDescriptor::TYPE_MESSAGE ||
+      desc.type() == FieldDescriptor::TYPE_GROUP ||
+      desc.type() == FieldDescriptor::TYPE_ENUM) {
+    return;
+  }
+  field.Emit(
+      {{"hazzer_thunk", Thunk(field, "has")},
+       {"getter_thunk", Thunk(field, "get")},
+       {"clearer_thunk", Thunk(field, "clear")},
+       {"is_default",
+        [&] {
+          switch (desc.cpp_type()) {
+            case FieldDescriptor::CPPTYPE_STRING:
+              field.Emit(
+                  {{"default", absl::CHexEscape(desc.default_value_string())}},
+                  R"rs(
+                unsafe { $getter_thunk$(self.inner.msg).as_ref() } == b"$default$"
+              )rs");
+              break;
+            case FieldDescriptor::CPPTYPE_FLOAT:
+            case FieldDescriptor::CPPTYPE_DOUBLE:
+              // Compare bits, so that NaN defaults match and -0.0 doesn't
+              // match 0.0.
+              field.Emit({{"default", DefaultValueLiteral(desc)}}, R"rs(
+                unsafe { $getter_thunk$(self.inner.msg) }.to_bits()
+                    == ($default$).to_bits()
+              )rs");
+              break;
+            default:
+              field.Emit({{"default", DefaultValueLiteral(desc)}}, R"rs(
+                unsafe { $getter_thunk$(self.inner.msg) } == $default$
+              )rs");
+              break;
+          }
+        }}},
+      R"rs(
+        if unsafe { $hazzer_thunk$(self.inner.msg) } && $is_default$ {
+          unsafe { $clearer_thunk$(self.inner.msg) }
+        }
+      )rs");
+}
+
+// Returns whether a message declared next to `msg`, in its file or in its
+// containing message, is named `name`.
+bool HasSiblingMessage(const Descriptor& msg, absl::string_view name) {
+  const Descriptor* parent = msg.containing_type();
+  int count = parent != nullptr ? parent->nested_type_count()
+                                : msg.file()->message_type_count();
+  for (int i = 0; i < count; ++i) {
+    const Descriptor* sibling = parent != nullptr
+                                    ? parent->nested_type(i)
+                                    : msg.file()->message_type(i);
+    if (sibling->name() == name) return true;
+  }
+  return false;
+}
+
+// Returns the `FieldNum` variant of each field of `msg`, in declaration order,
+// or nothing if `FieldNum` would clash with a nested message or oneof enum of
+// the same `Msg_` module.
+//
+// Variants are the camel-cased field names. `UnderscoresToCamelCase` never
+// emits `_`, so names it maps two fields to (e.g. `foo2` and `foo_2`), and
+// `Self`, are made unique by appending `_<number>`.
+std::vector<std::string> FieldNumVariants(Context<Descriptor> msg) {
+  const Descriptor& desc = msg.desc();
+  auto clashes = [&](absl::string_view what) {
+    ABSL_LOG(WARNING) << "Not generating `FieldNum` for " << desc.full_name()
+                      << ": it would clash with " << what << ".";
+    return std::vector<std::string>();
+  };
+  for (int i = 0; i < desc.nested_type_count(); ++i) {
+    if (desc.nested_type(i)->name() == "FieldNum") {
+      return clashes("a nested message");
+    }
+  }
+  for (int i = 0; i < desc.real_oneof_decl_count(); ++i) {
+    if (cpp::UnderscoresToCamelCase(desc.real_oneof_decl(i)->name(),
+                                    /* upper initial letter */ true) ==
+        "FieldNum") {
+      return clashes("a oneof enum");
+    }
+  }
+  std::vector<std::string> variants;
+  std::set<std::string> seen;
+  for (int i = 0; i < desc.field_count(); ++i) {
+    std::string name = cpp::UnderscoresToCamelCase(
+        FieldAccessorName(msg.WithDesc(*desc.field(i))),
+        /* upper initial letter */ true);
+    if (name == "Self" || !seen.insert(name).second) {
+      absl::StrAppend(&name, "_", desc.field(i)->number());
+    }
+    variants.push_back(name);
+  }
+  return variants;
+}
+
+// Returns whether `msg` gets a `Msg_` module: for its nested messages and
+// oneofs, or for `FieldNum`. A module only holding `FieldNum` is left out if
+// a sibling message already has its name.
+bool HasMessageModule(Context<Descriptor> msg) {
+  const Descriptor& desc = msg.desc();
+  if (desc.nested_type_count() > 0 || desc.real_oneof_decl_count() > 0) {
+    return true;
+  }
+  if (desc.field_count() == 0) return false;
+  if (HasSiblingMessage(desc, absl::StrCat(desc.name(), "_"))) {
+    ABSL_LOG(WARNING) << "Not generating `FieldNum` for " << desc.full_name()
+                      << ": its module would clash with message "
+                      << desc.name() << "_.";
+    return false;
+  }
+  return true;
+}
+
+// Emits the `FieldNum` enum naming the fields of `msg`, for partial decoders
+// built on `WireReader` and `match_tag`.
+void GenerateFieldNumEnum(Context<Descriptor> msg) {
+  std::vector<std::string> variants = FieldNumVariants(msg);
+  if (variants.empty()) return;
+  msg.Emit(
+      {{"variants",
+        [&] {
+          for (int i = 0; i < msg.desc().field_count(); ++i) {
+            std::string number = std::to_string(msg.desc().field(i)->number());
+            msg.Emit({{"name", variants[i]}, {"number", number}}, R"rs(
+                       $name$ = $number$,
+                     )rs");
+          }
+        }},
+       {"from_number_arms",
+        [&] {
+          for (int i = 0; i < msg.desc().field_count(); ++i) {
+            std::string number = std::to_string(msg.desc().field(i)->number());
+            msg.Emit({{"name", variants[i]}, {"number", number}}, R"rs(
+                       $number$ => Some(Self::$name$),
+                     )rs");
+          }
+        }}},
+      R"rs(
+        /// The number of each field, e.g. to pass to `match_tag`.
+        #[allow(clippy::enum_variant_names, non_camel_case_types)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u32)]
+        pub enum FieldNum {
+          $variants$
+        }
+
+        impl $pb$::FieldNumber for FieldNum {
+          fn from_number(number: u32) -> Option<Self> {
+            match number {
+              $from_number_arms$
+              _ => None,
+            }
+          }
+
+          fn number(self) -> u32 {
+            self as u32
+          }
+        }
+      )rs");
+}
+
+// Returns the 64-bit FNV-1a hash of the `DescriptorProto` of `msg` and of the
+// message and enum types its fields refer to, transitively. Only depends on
+// the descriptors, so it is the same on every run and platform.
+uint64_t SchemaFingerprint(const Descriptor& msg) {
+  // Serialized descriptors by full name, so that the order is stable.
+  std::map<std::string, std::string> protos;
+  std::vector<const Descriptor*> pending = {&msg};
+  while (!pending.empty()) {
+    const Descriptor* desc = pending.back();
+    pending.pop_back();
+    if (protos.count(desc->full_name()) != 0) continue;
+    DescriptorProto proto;
+    desc->CopyTo(&proto);
+    protos[desc->full_name()] = proto.SerializeAsString();
+    for (int i = 0; i < desc->field_count(); ++i) {
+      const FieldDescriptor& field = *desc->field(i);
+      if (field.message_type() != nullptr) {
+        pending.push_back(field.message_type());
+      }
+      if (field.enum_type() != nullptr) {
+        EnumDescriptorProto enum_proto;
+        field.enum_type()->CopyTo(&enum_proto);
+        protos[field.enum_type()->full_name()] =
+            enum_proto.SerializeAsString();
+      }
+    }
+  }
+
+  uint64_t hash = 0xcbf29ce484222325;
+  auto update = [&](absl::string_view bytes) {
+    for (char c : bytes) {
+      hash = (hash ^ static_cast<uint8_t>(c)) * 0x100000001b3;
+    }
+  };
+  for (const auto& [name, bytes] : protos) {
+    update(absl::StrCat(name.size(), ":", name, bytes.size(), ":"));
+    update(bytes);
+  }
+  return hash;
+}
+
+// Emits `into_raw()` and `from_raw()`. The invariants they rely on are
+// documented once, in the "Raw handles" section of the runtime's crate docs.
+void GenerateRawHandles(Context<Descriptor> msg) {
+  msg.printer().PrintRaw("\n");
+  if (msg.is_cpp()) {
+    msg.Emit({{"Msg", msg.desc().name()},
+              {"cpp_name", cpp::QualifiedClassName(&msg.desc())}},
+             R"rs(
+      impl $Msg$ {
+        /// Releases the C++ message backing `self`. The caller owns it, and
+        /// has to free it with `delete` or pass it to `from_raw()`.
+        ///
+        /// See the "Raw handles" section of the `protobuf` crate docs.
+        pub fn into_raw(self) -> $pb$::RawMessage {
+          let msg = self.inner.msg;
+          $std$::mem::forget(self);
+          msg
+        }
+
+        /// Takes ownership of a C++ message.
+        ///
+        /// # Safety
+        /// `msg` must point to a heap-allocated `$cpp_name$` that nothing
+        /// else owns. See the "Raw handles" section of the `protobuf` crate
+        /// docs.
+        pub unsafe fn from_raw(msg: $pb$::RawMessage) -> Self {
+          Self { inner: $pbr$::MessageInner { msg } }
+        }
+      }
+    )rs");
+    return;
+  }
+  msg.Emit({{"Msg", msg.desc().name()}, {"full_name", msg.desc().full_name()}},
+           R"rs(
+    impl $Msg$ {
+      /// Releases the upb message backing `self` and the arena it is
+      /// allocated on. The caller owns the arena, and has to free it with
+      /// `upb_Arena_Free` or pass both handles to `from_raw()`.
+      ///
+      /// See the "Raw handles" section of the `protobuf` crate docs.
+      pub fn into_raw(self) -> ($pb$::RawMessage, $pb$::RawArena) {
+        // `$Msg$` implements `Drop`, so its fields can't be moved out.
+        let this = $std$::mem::ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so the arena is only owned by the
+        // copy read here.
+        let arena = unsafe { $std$::ptr::read(&this.inner.arena) };
+        (this.inner.msg, arena.into_raw())
+      }
+
+      /// Takes ownership of a upb message and the arena it is allocated on.
+      ///
+      /// # Safety
+      /// `msg` must be a message of type `$full_name$` allocated on `arena`,
+      /// and the caller must own `arena`. See the "Raw handles" section of
+      /// the `protobuf` crate docs.
+      pub unsafe fn from_raw(msg: $pb$::RawMessage, arena: $pb$::RawArena) -> Self {
+        // SAFETY: The caller owns `arena`, and passes ownership on.
+        let arena = unsafe { $pbr$::Arena::from_raw(arena) };
+        Self { inner: $pbr$::MessageInner { msg, arena } }
+      }
+    }
+  )rs");
+}
+
 void GenerateSubView(Context<FieldDescriptor> field) {
   field.Emit(
       {
-          {"field", field.desc().name()},
+          {"field", FieldAccessorName(field)},
           {"getter_thunk", Thunk(field, "get")},
           {"Scalar", PrimitiveRsTypeName(field.desc())},
       },
@@ -196,7 +810,28 @@ void GenerateSubView(Context<FieldDescriptor> field) {
 }
 }  // namespace
 
+// TODO: The generated types are handles to kernel-owned messages, so the
+// Rust generator has no say in their memory layout. Splitting rarely-used
+// fields into a lazily-allocated sub-struct has to be done by the kernels
+// (C++ already does this for profile-driven split messages).
+//
+// TODO: Add a `split_mut()` that returns a struct with one mutator per
+// field, so that several fields can be mutated in one scope instead of each
+// `foo_mut()` borrowing the whole message. `MutatorMessageRef` is `Copy` and
+// may already back several `Mut`s of different fields, but the field
+// mutators are only generated on `$Msg$` and take `&mut self`; they first
+// need to be built from a `MutatorMessageRef` (and exist on `$Msg$Mut`).
+// Fields of one oneof must share a single mutator.
 void GenerateRs(Context<Descriptor> msg) {
+  // TODO: Map fields are not supported yet. When adding `MapView`:
+  // - `keys()` should yield borrowed keys (e.g. `&ProtoStr`) that can be
+  //   collected into a `HashSet` directly, and `contains_key` should accept
+  //   a `&str` without converting it into an owned key.
+  // - For message values, `get_or_default(key)` should return the default
+  //   instance's view when the key is absent, as C++ map fields do.
+  // - `to_hash_map()` should copy the whole map out with one pre-sized
+  //   allocation and a single thunk call that walks the kernel's map, rather
+  //   than one FFI round trip per entry.
   if (msg.desc().map_key() != nullptr) {
     ABSL_LOG(WARNING) << "unsupported map field: " << msg.desc().full_name();
     return;
@@ -204,8 +839,107 @@ void GenerateRs(Context<Descriptor> msg) {
   msg.Emit(
       {
           {"Msg", msg.desc().name()},
+          {"full_name", msg.desc().full_name()},
+          {"file_name", msg.desc().file()->name()},
+          {"schema_fingerprint",
+           absl::StrCat("0x", absl::Hex(SchemaFingerprint(msg.desc()),
+                                        absl::kZeroPad16))},
+          {"file_scope",
+           [&] {
+             // Nested messages are in a module per containing message.
+             for (const Descriptor* d = msg.desc().containing_type();
+                  d != nullptr; d = d->containing_type()) {
+               msg.Emit("super::");
+             }
+           }},
+          {"extension_ranges",
+           [&] {
+             for (int i = 0; i < msg.desc().extension_range_count(); ++i) {
+               const auto* range = msg.desc().extension_range(i);
+               msg.Emit({{"start", std::to_string(range->start_number())},
+                         {"end", std::to_string(range->end_number())}},
+                        R"rs($start$..$end$,
+                        )rs");
+             }
+           }},
+          {"reserved_ranges",
+           [&] {
+             for (int i = 0; i < msg.desc().reserved_range_count(); ++i) {
+               const auto* range = msg.desc().reserved_range(i);
+               msg.Emit({{"start", std::to_string(range->start)},
+                         {"end", std::to_string(range->end)}},
+                        R"rs($start$..$end$,
+                        )rs");
+             }
+           }},
+          {"reserved_names",
+           [&] {
+             for (int i = 0; i < msg.desc().reserved_name_count(); ++i) {
+               msg.Emit({{"name", msg.desc().reserved_name(i)}},
+                        R"rs("$name$",
+                        )rs");
+             }
+           }},
+          {"field_infos",
+           [&] {
+             for (int i = 0; i < msg.desc().field_count(); ++i) {
+               const FieldDescriptor& field = *msg.desc().field(i);
+               absl::string_view is_repeated =
+                   field.is_repeated() ? "true" : "false";
+               absl::string_view is_packed =
+                   field.is_packed() ? "true" : "false";
+               absl::string_view has_presence =
+                   field.has_presence() ? "true" : "false";
+               bool is_closed_enum = field.enum_type() != nullptr &&
+                                     field.enum_type()->is_closed();
+               absl::string_view validates_utf8 =
+                   field.requires_utf8_validation() ? "true" : "false";
+               msg.Emit({{"number", std::to_string(field.number())},
+                         {"name", field.name()},
+                         {"kind", FieldKindName(field)},
+                         {"is_repeated", is_repeated},
+                         {"is_packed", is_packed},
+                         {"has_presence", has_presence},
+                         {"is_closed_enum", is_closed_enum ? "true" : "false"},
+                         {"validates_utf8", validates_utf8},
+                         {"message_type",
+                          [&] { FieldInfoMessageType(msg.WithDesc(field)); }}},
+                        R"rs(
+                 $pb$::FieldInfo {
+                   number: $number$,
+                   name: "$name$",
+                   kind: $pb$::FieldKind::$kind$,
+                   is_repeated: $is_repeated$,
+                   is_packed: $is_packed$,
+                   has_presence: $has_presence$,
+                   is_closed_enum: $is_closed_enum$,
+                   validates_utf8: $validates_utf8$,
+                   message_type: $message_type$,
+                 },
+               )rs");
+             }
+           }},
+          {"field_is_set_arms",
+           [&] {
+             for (int i = 0; i < msg.desc().field_count(); ++i) {
+               auto field = msg.WithDesc(*msg.desc().field(i));
+               msg.Emit({{"number", std::to_string(field.desc().number())},
+                         {"is_set", [&] { FieldIsSet(field); }}},
+                        R"rs(
+                 $number$ => $is_set$,
+               )rs");
+             }
+           }},
+          {"prune_default_fields",
+           [&] {
+             for (int i = 0; i < msg.desc().field_count(); ++i) {
+               PruneDefault(msg.WithDesc(*msg.desc().field(i)));
+             }
+           }},
           {"Msg::new", [&] { MessageNew(msg); }},
           {"Msg::serialize", [&] { MessageSerialize(msg); }},
+          {"Msg::serialize_deterministic",
+           [&] { MessageSerializeDeterministic(msg); }},
           {"Msg::deserialize", [&] { MessageDeserialize(msg); }},
           {"Msg::drop", [&] { MessageDrop(msg); }},
           {"Msg_externs", [&] { MessageExterns(msg); }},
@@ -245,13 +979,10 @@ void GenerateRs(Context<Descriptor> msg) {
            }},
           {"nested_msgs",
            [&] {
-             // If we have no nested types or oneofs, bail out without emitting
-             // an empty mod SomeMsg_.
-             if (msg.desc().nested_type_count() == 0 &&
-                 msg.desc().real_oneof_decl_count() == 0) {
-               return;
-             }
+             // Bail out without emitting an empty mod SomeMsg_.
+             if (!HasMessageModule(msg)) return;
              msg.Emit({{"Msg", msg.desc().name()},
+                       {"field_num", [&] { GenerateFieldNumEnum(msg); }},
                        {"nested_msgs",
                         [&] {
                           for (int i = 0; i < msg.desc().nested_type_count();
@@ -272,12 +1003,305 @@ void GenerateRs(Context<Descriptor> msg) {
                       R"rs(
                  #[allow(non_snake_case)]
                  pub mod $Msg$_ {
+                   $field_num$
+
                    $nested_msgs$
 
                    $oneofs$
                  }  // mod $Msg$_
                 )rs");
            }},
+          {"file_descriptor_bytes_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "file_descriptor_bytes")) return;
+             msg.Emit(R"rs(
+                 /// Returns the serialized `FileDescriptorProto` of the file that
+                 /// declares `$Msg$`, for code that needs its schema at runtime.
+                 pub fn file_descriptor_bytes() -> &'static [u8] {
+                   $file_scope$__FILE_DESCRIPTOR
+                 }
+             )rs");
+           }},
+          {"register_file_descriptor_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "register_file_descriptor")) return;
+             msg.Emit(R"rs(
+                 /// Adds the descriptor of the file that declares `$Msg$` to the
+                 /// global registry (see `registered_file_descriptor`). The files it
+                 /// imports aren't added.
+                 pub fn register_file_descriptor() {
+                   $pb$::register_file_descriptor(
+                     "$file_name$", $file_scope$__FILE_DESCRIPTOR)
+                 }
+             )rs");
+           }},
+          {"fields_with_presence_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "fields_with_presence")) return;
+             msg.Emit(R"rs(
+                 /// Returns each field in `FIELDS` with whether it's set, or `None`
+                 /// if that can't be told yet (e.g. for repeated fields).
+                 pub fn fields_with_presence(&self)
+                     -> impl Iterator<Item = (&'static $pb$::FieldInfo, Option<bool>)> + '_ {
+                   Self::FIELDS.iter().map(move |info| {
+                     let is_set = match info.number {
+                       $field_is_set_arms$
+                       _ => None,
+                     };
+                     (info, is_set)
+                   })
+                 }
+             )rs");
+           }},
+          {"view_is_default_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "is_default")) return;
+             msg.Emit({{"body", [&] { ViewIsDefault(msg); }}}, R"rs(
+                 /// Returns whether the message equals a new `$Msg$`: no field
+                 /// is set (or holds a non-default value, for fields without
+                 /// presence) and there are no unknown fields.
+                 pub fn is_default(self) -> bool {
+                   $body$
+                 }
+             )rs");
+           }},
+          {"is_default_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "is_default")) return;
+             msg.Emit(R"rs(
+                 /// Returns whether `self` equals a new `$Msg$`: no field is set (or
+                 /// holds a non-default value, for fields without presence) and
+                 /// there are no unknown fields.
+                 pub fn is_default(&self) -> bool {
+                   $Msg$View::new($pbi$::Private, self.inner.msg).is_default()
+                 }
+             )rs");
+           }},
+          {"prune_defaults_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "prune_defaults")) return;
+             msg.Emit(R"rs(
+                 /// Clears the explicit-presence fields of `$Msg$` that are set to
+                 /// their default value, so that they aren't serialized. Fields of
+                 /// oneofs and of submessages are left as they are.
+                 pub fn prune_defaults(&mut self) {
+                   $prune_default_fields$
+                 }
+             )rs");
+           }},
+          {"serialize_deterministic_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "serialize_deterministic")) return;
+             msg.Emit(R"rs(
+                 pub fn serialize_deterministic(&self) -> $pbr$::SerializedData {
+                   $pbi$::record_message_op(
+                     $pb$::MessageOp::Serialize, Self::FULL_NAME,
+                     || {
+                       $pbi$::run_serialize_hooks(self);
+                       $Msg::serialize_deterministic$
+                     }, |data| data.len())
+                 }
+             )rs");
+           }},
+          {"serialize_unpacked_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "serialize_unpacked")) return;
+             msg.Emit(R"rs(
+                 /// Like `serialize`, but writes the repeated scalar fields of
+                 /// `$Msg$` unpacked (one tag per element) even if they are declared
+                 /// packed, for old readers that don't understand packed encoding.
+                 /// Only top-level fields are unpacked: submessages are written as
+                 /// `serialize` writes them, packed fields included.
+                 pub fn serialize_unpacked(&self) -> $pbr$::SerializedData {
+                   $pbi$::record_message_op(
+                     $pb$::MessageOp::Serialize, Self::FULL_NAME,
+                     || {
+                       $pbi$::run_serialize_hooks(self);
+                       let data = $pbi$::KernelMessage::kernel_serialize(self);
+                       match $pb$::unpack_serialized(&data, Self::FIELDS) {
+                         Ok(unpacked) => unpacked.into(),
+                         // The kernel only writes valid wire format, but if it
+                         // didn't, its output is still the best serialization.
+                         Err(_) => data,
+                       }
+                     }, |data| data.len())
+                 }
+             )rs");
+           }},
+          {"deserialize_filtered_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "deserialize_filtered")) return;
+             msg.Emit(R"rs(
+                 /// Like `deserialize()`, but only keeps the fields of `data` that
+                 /// `filter` allows. `data` is only copied if `filter` drops
+                 /// something from it.
+                 pub fn deserialize_filtered(
+                     &mut self, data: &[u8], filter: &$pb$::FieldFilter)
+                     -> Result<(), $pb$::ParseError> {
+                   self.deserialize(&filter.filter(data).map_err(|_| $pb$::ParseError)?)
+                 }
+             )rs");
+           }},
+          {"deserialize_renumbered_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "deserialize_renumbered")) return;
+             msg.Emit(R"rs(
+                 /// Like `deserialize()`, but first rewrites the field numbers of
+                 /// `data` that `aliases` maps. `data` is only copied if one of
+                 /// its fields is renumbered.
+                 pub fn deserialize_renumbered(
+                     &mut self, data: &[u8], aliases: &$pb$::FieldAliases)
+                     -> Result<(), $pb$::ParseError> {
+                   self.deserialize(&aliases.renumber(data).map_err(|_| $pb$::ParseError)?)
+                 }
+             )rs");
+           }},
+          {"copy_matching_fields_from_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "copy_matching_fields_from")) return;
+             msg.Emit(R"rs(
+                 /// Merges the fields of `src`, a serialized message of another type
+                 /// described by `src_fields` (e.g. `<Other>::FIELDS`), that `$Msg$`
+                 /// declares with the same number, kind and cardinality, and for
+                 /// submessages the same message type (see
+                 /// `FieldFilter::matching`). As for any merge, scalars are
+                 /// replaced, repeated fields are appended to and submessages are
+                 /// merged. On error, `self` is unchanged.
+                 pub fn copy_matching_fields_from(
+                     &mut self, src: &[u8], src_fields: &[$pb$::FieldInfo])
+                     -> Result<(), $pb$::ParseError> {
+                   let filter = $pb$::FieldFilter::matching(src_fields, Self::FIELDS);
+                   let mut merged = $pbi$::KernelMessage::kernel_serialize(self).to_vec();
+                   merged.extend_from_slice(&filter.filter(src).map_err(|_| $pb$::ParseError)?);
+                   let mut copied = Self::new();
+                   $pbi$::KernelMessage::kernel_deserialize(&mut copied, &merged)?;
+                   *self = copied;
+                   Ok(())
+                 }
+             )rs");
+           }},
+          {"deserialize_with_report_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "deserialize_with_report")) return;
+             msg.Emit(R"rs(
+                 /// Like `deserialize`, but also reports the top-level fields of
+                 /// `data` that `$Msg$` doesn't declare (e.g. legacy data with
+                 /// fields that have since been removed). They are still kept as
+                 /// unknown fields.
+                 pub fn deserialize_with_report(&mut self, data: &[u8])
+                     -> Result<Vec<$pb$::UndeclaredField>, $pb$::ParseError> {
+                   let undeclared =
+                       $pb$::undeclared_fields(data, Self::FIELDS, Self::EXTENSION_RANGES)
+                           .map_err(|_| $pb$::ParseError)?;
+                   self.deserialize(data)?;
+                   Ok(undeclared)
+                 }
+             )rs");
+           }},
+          {"deserialize_with_duplicates_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "deserialize_with_duplicates")) return;
+             msg.Emit(R"rs(
+                 /// Like `deserialize`, but also reports the top-level singular
+                 /// fields that occur more than once in `data`. As usual, the last
+                 /// value of a scalar field wins and message fields are merged.
+                 pub fn deserialize_with_duplicates(&mut self, data: &[u8])
+                     -> Result<Vec<$pb$::DuplicateField>, $pb$::ParseError> {
+                   let duplicates = $pb$::duplicate_fields(data, Self::FIELDS)
+                       .map_err(|_| $pb$::ParseError)?;
+                   self.deserialize(data)?;
+                   Ok(duplicates)
+                 }
+             )rs");
+           }},
+          {"deserialize_with_limits_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "deserialize_with_limits")) return;
+             msg.Emit(R"rs(
+                 /// Like `deserialize`, but first rejects `data` if a field of it, or
+                 /// of a submessage `check_limits` can look up, exceeds `limits`.
+                 /// Rejected input is never handed to the kernel.
+                 pub fn deserialize_with_limits(
+                     &mut self, data: &[u8], limits: &$pb$::ParseLimits)
+                     -> Result<(), $pb$::ParseError> {
+                   $pb$::check_limits(data, Self::FIELDS, limits)
+                       .map_err(|_| $pb$::ParseError)?;
+                   self.deserialize(data)
+                 }
+             )rs");
+           }},
+          {"deserialize_lenient_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "deserialize_lenient")) return;
+             msg.Emit(R"rs(
+                 /// Like `deserialize`, but drops a truncated or malformed tail of
+                 /// `data` instead of failing, and returns a warning describing it.
+                 /// See `$pb$::lenient_prefix`.
+                 pub fn deserialize_lenient(&mut self, data: &[u8])
+                     -> Result<Option<$pb$::LenientWarning>, $pb$::ParseError> {
+                   let (valid, warning) = $pb$::lenient_prefix(data);
+                   self.deserialize(valid)?;
+                   Ok(warning)
+                 }
+             )rs");
+           }},
+          {"truncate_to_size_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "truncate_to_size")) return;
+             msg.Emit(R"rs(
+                 /// Drops fields as allowed by `policy` until the serialized message
+                 /// is at most `max_bytes` long. On error, `self` is unchanged.
+                 pub fn truncate_to_size(
+                     &mut self, max_bytes: usize, policy: &$pb$::TruncationPolicy)
+                     -> Result<$pb$::Truncation, $pb$::TruncateError> {
+                   let data = $pbi$::KernelMessage::kernel_serialize(self);
+                   let (data, truncation) = $pb$::truncate_serialized(&data, max_bytes, policy)?;
+                   if !truncation.dropped.is_empty() {
+                     let mut truncated = Self::new();
+                     $pbi$::KernelMessage::kernel_deserialize(&mut truncated, &data)
+                         .map_err(|_| $pb$::TruncateError::Parse)?;
+                     *self = truncated;
+                   }
+                   Ok(truncation)
+                 }
+             )rs");
+           }},
+          {"debug_string_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "debug_string")) return;
+             msg.Emit(R"rs(
+                 /// Prints `self` with `$pb$::debug_string`, with `options` limiting
+                 /// the nesting depth, repeated occurrences and string lengths.
+                 pub fn debug_string(&self, options: &$pb$::DebugStringOptions) -> String {
+                   $pb$::debug_string(&$pbi$::KernelMessage::kernel_serialize(self), options)
+                 }
+             )rs");
+           }},
+          {"serialize_compressed_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "serialize_compressed")) return;
+             msg.Emit(R"rs(
+                 /// Serializes `self` and compresses the result with `codec`, e.g. a
+                 /// zstd or gzip wrapper implementing `$pb$::Codec`.
+                 pub fn serialize_compressed(&self, codec: &dyn $pb$::Codec) -> Vec<u8> {
+                   $pb$::compress_serialized(&self.serialize(), codec)
+                 }
+             )rs");
+           }},
+          {"deserialize_compressed_fn",
+           [&] {
+             if (ClashesWithAccessor(msg, "deserialize_compressed")) return;
+             msg.Emit(R"rs(
+                 /// Decompresses `data` with `codec` and parses the result into
+                 /// `self`. Fails with `ParseError` if `codec` can't decompress
+                 /// `data` or the decompressed bytes don't parse.
+                 pub fn deserialize_compressed(
+                     &mut self, data: &[u8], codec: &dyn $pb$::Codec)
+                     -> Result<(), $pb$::ParseError> {
+                   self.deserialize(&$pb$::decompress_serialized(data, codec)?)
+                 }
+             )rs");
+           }},
           {"subviews",
            [&] {
              for (int i = 0; i < msg.desc().field_count(); ++i) {
@@ -304,6 +1328,19 @@ void GenerateRs(Context<Descriptor> msg) {
         //   field access is impossible.
         unsafe impl Sync for $Msg$ {}
 
+        // SAFETY:
+        // - `$Msg$` exclusively owns its message and, with upb, the arena the
+        //   message lives in; no other message shares that arena.
+        // - Views and mutators borrow `$Msg$`, so none can remain on the old
+        //   thread once it's moved.
+        // - Neither kernel ties messages or arenas to the thread that created
+        //   them.
+        // - Fusing upb arenas only joins their lifetimes, which upb tracks
+        //   atomically; each arena is still only allocated from through the
+        //   message that owns it. (`_set_serialized` copies rather than fuses
+        //   today.)
+        unsafe impl Send for $Msg$ {}
+
         impl $pb$::Proxied for $Msg$ {
           type View<'a> = $Msg$View<'a>;
           type Mut<'a> = $Msg$Mut<'a>;
@@ -321,6 +1358,9 @@ void GenerateRs(Context<Descriptor> msg) {
           pub fn new(_private: $pbi$::Private, msg: $pbi$::RawMessage) -> Self {
             Self { msg, _phantom: std::marker::PhantomData }
           }
+
+          $view_is_default_fn$
+
           $subviews$
         }
 
@@ -380,22 +1420,113 @@ void GenerateRs(Context<Descriptor> msg) {
         }
 
         impl $Msg$ {
+          /// The full name of `$Msg$` in its `.proto` file, e.g. for metrics
+          /// labels and registry keys.
+          pub const FULL_NAME: &'static str = "$full_name$";
+
+          /// The type URL of `$Msg$`, e.g. for packing it into a
+          /// `google.protobuf.Any`.
+          pub const TYPE_URL: &'static str = "type.googleapis.com/$full_name$";
+
+          /// A hash of the schema of `$Msg$` and of the message and enum
+          /// types its fields refer to, transitively. It changes whenever
+          /// one of their descriptors does (e.g. a field is added, renamed
+          /// or renumbered, or an option changes), so caches and persisted
+          /// indexes can tell when the schema that wrote stored bytes has
+          /// changed. Comments don't affect it.
+          pub const SCHEMA_FINGERPRINT: u64 = $schema_fingerprint$;
+
+          $file_descriptor_bytes_fn$
+
+          $register_file_descriptor_fn$
+
+          /// The field number ranges reserved for extensions, e.g. to find the
+          /// extensions set on a message with `ExtensionFields`.
+          pub const EXTENSION_RANGES: &'static [$std$::ops::Range<u32>] = &[
+            $extension_ranges$
+          ];
+
+          /// The field number ranges declared `reserved`, e.g. for linters
+          /// that check numbers are never reused.
+          pub const RESERVED_RANGES: &'static [$std$::ops::Range<u32>] = &[
+            $reserved_ranges$
+          ];
+
+          /// The field names declared `reserved`.
+          pub const RESERVED_NAMES: &'static [&'static str] = &[
+            $reserved_names$
+          ];
+
           pub fn new() -> Self {
             $Msg::new$
           }
 
+          /// The fields of `$Msg$`, in declaration order.
+          pub const FIELDS: &'static [$pb$::FieldInfo] = &[
+            $field_infos$
+          ];
+
+          $fields_with_presence_fn$
+
+          $is_default_fn$
+
+          $prune_defaults_fn$
+
           pub fn serialize(&self) -> $pbr$::SerializedData {
-            $Msg::serialize$
+            $pbi$::record_message_op(
+              $pb$::MessageOp::Serialize, Self::FULL_NAME,
+              || {
+                $pbi$::run_serialize_hooks(self);
+                $pbi$::KernelMessage::kernel_serialize(self)
+              }, |data| data.len())
           }
+          $serialize_deterministic_fn$
+          $serialize_unpacked_fn$
           pub fn deserialize(&mut self, data: &[u8]) -> Result<(), $pb$::ParseError> {
-            $Msg::deserialize$
+            $pbi$::record_message_op(
+              $pb$::MessageOp::Parse, Self::FULL_NAME,
+              || {
+                $pbi$::KernelMessage::kernel_deserialize(self, data)?;
+                $pbi$::run_parse_hooks(self)
+              }, |_| data.len())
           }
 
+          $deserialize_filtered_fn$
+
+          $deserialize_renumbered_fn$
+
+          $copy_matching_fields_from_fn$
+
+          $deserialize_with_report_fn$
+
+          $deserialize_with_duplicates_fn$
+
+          $deserialize_with_limits_fn$
+
+          $deserialize_lenient_fn$
+
+          $truncate_to_size_fn$
+
+          $debug_string_fn$
+
+          $serialize_compressed_fn$
+
+          $deserialize_compressed_fn$
+
           $accessor_fns$
 
           $oneof_accessor_fns$
         }  // impl $Msg$
 
+        impl $pbi$::KernelMessage for $Msg$ {
+          fn kernel_serialize(&self) -> $pbr$::SerializedData {
+            $Msg::serialize$
+          }
+          fn kernel_deserialize(&mut self, data: &[u8]) -> Result<(), $pb$::ParseError> {
+            $Msg::deserialize$
+          }
+        }
+
         //~ We implement drop unconditionally, so that `$Msg$: Drop` regardless
         //~ of kernel.
         impl $std$::ops::Drop for $Msg$ {
@@ -415,6 +1546,11 @@ void GenerateRs(Context<Descriptor> msg) {
         $nested_msgs$
       )rs");
 
+  if (msg.opts().flat_nested_names && msg.desc().containing_type() == nullptr) {
+    GenerateFlatNestedAliases(msg, msg.desc().name(), msg.desc().name(),
+                              ClashingFlatNames(*msg.desc().file()));
+  }
+
   if (msg.is_cpp()) {
     msg.printer().PrintRaw("\n");
     msg.Emit({{"Msg", msg.desc().name()}}, R"rs(
@@ -428,6 +1564,23 @@ void GenerateRs(Context<Descriptor> msg) {
       }
     )rs");
   }
+
+  if (msg.is_upb() && !ClashesWithAccessor(msg, "arena_stats")) {
+    msg.printer().PrintRaw("\n");
+    msg.Emit({{"Msg", msg.desc().name()}}, R"rs(
+      impl $Msg$ {
+        /// Returns statistics about the arena that `self` is allocated on.
+        pub fn arena_stats(&self) -> $pb$::ArenaStats {
+          self.inner.arena.stats()
+        }
+      }
+    )rs");
+  }
+
+  if (msg.opts().raw_handles && !ClashesWithAccessor(msg, "into_raw") &&
+      !ClashesWithAccessor(msg, "from_raw")) {
+    GenerateRawHandles(msg);
+  }
 }
 
 // Generates code for a particular message in `.pb.thunk.cc`.
@@ -445,7 +1598,9 @@ void GenerateThunksCc(Context<Descriptor> msg) {
        {"new_thunk", Thunk(msg, "new")},
        {"delete_thunk", Thunk(msg, "delete")},
        {"serialize_thunk", Thunk(msg, "serialize")},
+       {"serialize_deterministic_thunk", Thunk(msg, "serialize_deterministic")},
        {"deserialize_thunk", Thunk(msg, "deserialize")},
+       {"has_unknown_fields_thunk", Thunk(msg, "has_unknown_fields")},
        {"nested_msg_thunks",
         [&] {
           for (int i = 0; i < msg.desc().nested_type_count(); ++i) {
@@ -477,10 +1632,16 @@ void GenerateThunksCc(Context<Descriptor> msg) {
         google::protobuf::rust_internal::SerializedData $serialize_thunk$($QualifiedMsg$* msg) {
           return google::protobuf::rust_internal::SerializeMsg(msg);
         }
+        google::protobuf::rust_internal::SerializedData $serialize_deterministic_thunk$($QualifiedMsg$* msg) {
+          return google::protobuf::rust_internal::SerializeMsgDeterministic(msg);
+        }
         bool $deserialize_thunk$($QualifiedMsg$* msg,
                                  google::protobuf::rust_internal::SerializedData data) {
           return msg->ParseFromArray(data.data, data.len);
         }
+        bool $has_unknown_fields_thunk$($QualifiedMsg$* msg) {
+          return !msg->unknown_fields().empty();
+        }
 
         $accessor_thunks$
 
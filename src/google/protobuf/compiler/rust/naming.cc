@@ -165,6 +165,46 @@ std::string GetCrateRelativeQualifiedPath(Context<Descriptor> msg) {
   return absl::StrCat(RustModule(msg), "::", name);
 }
 
+std::string FieldAccessorName(Context<FieldDescriptor> field) {
+  auto it = field.opts().accessor_names.find(field.desc().full_name());
+  if (it != field.opts().accessor_names.end()) {
+    return it->second;
+  }
+  return field.desc().name();
+}
+
+bool ClashesWithAccessor(Context<Descriptor> msg, absl::string_view name,
+                         const FieldDescriptor* except) {
+  constexpr absl::string_view kFieldSuffixes[] = {
+      "", "_mut", "_set", "_opt", "_is_default", "_try_set",
+      "_set_serialized", "_unchecked", "_deprecated_use"};
+  for (int i = 0; i < msg.desc().field_count(); ++i) {
+    const FieldDescriptor* field = msg.desc().field(i);
+    if (field == except) continue;
+    std::string accessor = FieldAccessorName(msg.WithDesc(*field));
+    for (absl::string_view suffix : kFieldSuffixes) {
+      if (name != absl::StrCat(accessor, suffix)) continue;
+      ABSL_LOG(WARNING) << "not generating " << msg.desc().full_name()
+                        << "::" << name << "(): it clashes with an accessor of "
+                        << field->full_name()
+                        << "; rename the field with the accessor_name option";
+      return true;
+    }
+  }
+  constexpr absl::string_view kOneofSuffixes[] = {"", "_visit", "_mut"};
+  for (int i = 0; i < msg.desc().real_oneof_decl_count(); ++i) {
+    const OneofDescriptor* oneof = msg.desc().real_oneof_decl(i);
+    for (absl::string_view suffix : kOneofSuffixes) {
+      if (name != absl::StrCat(oneof->name(), suffix)) continue;
+      ABSL_LOG(WARNING) << "not generating " << msg.desc().full_name()
+                        << "::" << name << "(): it clashes with an accessor of "
+                        << oneof->full_name();
+      return true;
+    }
+  }
+  return false;
+}
+
 std::string FieldInfoComment(Context<FieldDescriptor> field) {
   absl::string_view label =
       field.desc().is_repeated() ? "repeated" : "optional";
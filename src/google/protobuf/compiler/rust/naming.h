@@ -32,6 +32,18 @@ std::string Thunk(Context<Descriptor> msg, absl::string_view op);
 
 std::string PrimitiveRsTypeName(const FieldDescriptor& desc);
 
+// Returns the base name of the accessors generated for `field`: the field's
+// name, unless it is overridden with the `accessor_name` option.
+std::string FieldAccessorName(Context<FieldDescriptor> field);
+
+// Returns whether an accessor of a field or oneof of `msg`, other than those
+// of `except`, is named `name` (e.g. `foo`, `foo_mut` or `foo_is_default` for
+// a field `foo`), and if so warns that the method `name` isn't generated. The
+// `accessor_name` option can rename the field. Every accessor suffix is tried
+// for every field, which errs on the side of reporting a clash.
+bool ClashesWithAccessor(Context<Descriptor> msg, absl::string_view name,
+                         const FieldDescriptor* except = nullptr);
+
 std::string FieldInfoComment(Context<FieldDescriptor> field);
 
 std::string RustModule(Context<Descriptor> msg);
@@ -59,6 +59,22 @@ namespace rust {
 //   pub fn some_oneof() -> SomeOneof {...}
 //   pub fn some_oneof_mut() -> SomeOneofMut {...}
 
+// }
+//
+// A visitor trait is also emitted, with a method per case plus not_set, as an
+// alternative to matching on the view enum that doesn't break when cases are
+// added:
+//
+// pub mod SomeMsg_ {
+//   pub trait SomeOneofVisitor<'msg> {
+//     fn visit_field_a(&mut self, _value: View<'msg, i32>) {}
+//     fn visit_field_b(&mut self, _value: View<'msg, u32>) {}
+//     fn visit_not_set(&mut self) {}
+//   }
+// }
+// impl SomeMsg {
+//   pub fn some_oneof_visit<'msg>(&'msg self,
+//                                 visitor: impl SomeOneofVisitor<'msg>) {...}
 // }
 //
 // An additional "Case" enum which just reflects the corresponding slot numbers
@@ -84,6 +100,10 @@ std::string oneofMutEnumRsName(const OneofDescriptor& desc) {
   return ToCamelCase(desc.name()) + "Mut";
 }
 
+std::string oneofVisitorRsName(const OneofDescriptor& desc) {
+  return ToCamelCase(desc.name()) + "Visitor";
+}
+
 std::string oneofCaseEnumName(const OneofDescriptor& desc) {
   // Note: This is the name used for the cpp Case enum, we use it for both
   // the Rust Case enum as well as for the cpp case enum in the cpp thunk.
@@ -183,6 +203,62 @@ void GenerateOneofDefinition(Context<OneofDescriptor> oneof) {
 
       )rs");
 
+  // The visitor has a default no-op method per case, so that adding a case to
+  // the oneof doesn't break existing implementations.
+  oneof.Emit(
+      {{"visitor_name", oneofVisitorRsName(desc)},
+       {"view_enum_name", oneofViewEnumRsName(desc)},
+       {"default_methods",
+        [&] {
+          for (int i = 0; i < desc.field_count(); ++i) {
+            const auto& field = desc.field(i);
+            std::string rs_type = RsTypeNameView(*field);
+            if (rs_type.empty()) {
+              continue;
+            }
+            oneof.Emit({{"field", FieldAccessorName(oneof.WithDesc(*field))},
+                        {"type", rs_type}},
+                       R"rs(
+                fn visit_$field$(&mut self, _value: $pb$::$type$) {}
+              )rs");
+          }
+        }},
+       {"forwarding_methods",
+        [&] {
+          for (int i = 0; i < desc.field_count(); ++i) {
+            const auto& field = desc.field(i);
+            std::string rs_type = RsTypeNameView(*field);
+            if (rs_type.empty()) {
+              continue;
+            }
+            oneof.Emit({{"field", FieldAccessorName(oneof.WithDesc(*field))},
+                        {"type", rs_type}},
+                       R"rs(
+                fn visit_$field$(&mut self, value: $pb$::$type$) {
+                  (**self).visit_$field$(value)
+                }
+              )rs");
+          }
+        }}},
+      R"rs(
+      /// Has a method per case of `$view_enum_name$`, called by the oneof's
+      /// `_visit()` accessor. Every method defaults to doing nothing.
+      pub trait $visitor_name$<'msg> {
+        $default_methods$
+
+        fn visit_not_set(&mut self) {}
+      }
+
+      impl<'msg, V: $visitor_name$<'msg> + ?Sized> $visitor_name$<'msg> for &mut V {
+        $forwarding_methods$
+
+        fn visit_not_set(&mut self) {
+          (**self).visit_not_set()
+        }
+      }
+
+      )rs");
+
   // Note: This enum is used as the Thunk return type for getting which case is
   // used: it exactly matches the generate case enum that both cpp and upb use.
   oneof.Emit({{"case_enum_name", oneofCaseEnumName(desc)},
@@ -228,13 +304,31 @@ void GenerateOneofAccessors(Context<OneofDescriptor> oneof) {
             oneof.Emit(
                 {
                     {"case", ToCamelCase(field->name())},
-                    {"rs_getter", field->name()},
+                    {"rs_getter", FieldAccessorName(oneof.WithDesc(*field))},
                     {"type", rs_type},
                 },
                 R"rs($Msg$_::$case_enum_name$::$case$ => $Msg$_::$view_enum_name$::$case$(self.$rs_getter$()),
                 )rs");
           }
         }},
+       {"visitor_name", oneofVisitorRsName(desc)},
+       {"visit_cases",
+        [&] {
+          for (int i = 0; i < desc.field_count(); ++i) {
+            const auto& field = desc.field(i);
+            std::string rs_type = RsTypeNameView(*field);
+            if (rs_type.empty()) {
+              continue;
+            }
+            oneof.Emit(
+                {
+                    {"case", ToCamelCase(field->name())},
+                    {"field", FieldAccessorName(oneof.WithDesc(*field))},
+                },
+                R"rs($Msg$_::$view_enum_name$::$case$(value) => visitor.visit_$field$(value),
+                )rs");
+          }
+        }},
        {"mut_cases",
         [&] {
           for (int i = 0; i < desc.field_count(); ++i) {
@@ -247,7 +341,8 @@ void GenerateOneofAccessors(Context<OneofDescriptor> oneof) {
             // _mut() on singular fields with presence is implemented.
             /*oneof.Emit({
                     {"case", ToCamelCase(field->name())},
-                    {"rs_getter", field->name() + "_mut"},
+                    {"rs_getter",
+                     FieldAccessorName(oneof.WithDesc(*field)) + "_mut"},
                     {"type", rs_type},
                 },
                 R"rs($Msg$_::$case_enum_name$::$case$ =>
@@ -264,6 +359,14 @@ void GenerateOneofAccessors(Context<OneofDescriptor> oneof) {
           }
         }
 
+        pub fn r#$oneof_name$_visit<'msg>(
+            &'msg self, mut visitor: impl $Msg$_::$visitor_name$<'msg>) {
+          match self.r#$oneof_name$() {
+            $visit_cases$
+            _ => visitor.visit_not_set(),
+          }
+        }
+
         pub fn r#$oneof_name$_mut(&mut self) -> $Msg$_::$mut_enum_name$ {
           match unsafe { $case_thunk$(self.inner.msg) } {
             $mut_cases$
@@ -81,6 +81,25 @@ size_t upb_Arena_SpaceAllocated(upb_Arena* arena) {
   return memsize;
 }
 
+void upb_Arena_GetStats(upb_Arena* arena, upb_ArenaStats* stats) {
+  stats->fused_count = 0;
+  stats->block_count = 0;
+  stats->space_allocated = 0;
+  stats->current_block_unused = _upb_ArenaHas(arena);
+
+  upb_Arena* a = _upb_Arena_FindRoot(arena).root;
+  while (a != NULL) {
+    stats->fused_count++;
+    _upb_MemBlock* block = upb_Atomic_Load(&a->blocks, memory_order_relaxed);
+    while (block != NULL) {
+      stats->block_count++;
+      stats->space_allocated += sizeof(_upb_MemBlock) + block->size;
+      block = upb_Atomic_Load(&block->next, memory_order_relaxed);
+    }
+    a = upb_Atomic_Load(&a->next, memory_order_relaxed);
+  }
+}
+
 uint32_t upb_Arena_DebugRefCount(upb_Arena* a) {
   // These loads could probably be relaxed, but given that this is debug-only,
   // it's not worth introducing a new variant for it.
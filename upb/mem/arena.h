@@ -54,6 +54,20 @@ void* _upb_Arena_SlowMalloc(upb_Arena* a, size_t size);
 size_t upb_Arena_SpaceAllocated(upb_Arena* arena);
 uint32_t upb_Arena_DebugRefCount(upb_Arena* arena);
 
+// Statistics about an arena and the arenas fused with it, for monitoring
+// memory use and tuning initial block sizes.
+typedef struct {
+  size_t fused_count;           // Number of arenas fused together, at least 1.
+  size_t block_count;           // Blocks allocated by all of them.
+  size_t space_allocated;       // As returned by upb_Arena_SpaceAllocated().
+  size_t current_block_unused;  // Bytes left in this arena's current block.
+} upb_ArenaStats;
+
+// Like upb_Arena_SpaceAllocated(), this can run concurrently with allocations
+// from the other fused arenas. Space left over in earlier blocks when a new
+// one was allocated isn't tracked.
+void upb_Arena_GetStats(upb_Arena* arena, upb_ArenaStats* stats);
+
 UPB_INLINE size_t _upb_ArenaHas(upb_Arena* a) {
   _upb_ArenaHead* h = (_upb_ArenaHead*)a;
   return (size_t)(h->end - h->ptr);